@@ -1,12 +1,62 @@
 pub use sea_orm_migration::prelude::*;
 
 mod m20220101_000001_create_table;
+mod m20230801_000001_create_exclusions_table;
+mod m20230805_000001_add_server_ping;
+mod m20230810_000001_add_bedrock_edition;
+mod m20230812_000001_add_server_query;
+mod m20230815_000001_add_server_login;
+mod m20230818_000001_create_hostnames_table;
+mod m20230822_000001_create_rescan_schedules_table;
+mod m20230825_000001_add_ip_priority;
+mod m20230828_000001_add_server_state;
+mod m20230901_000001_create_server_observations_table;
+mod m20230904_000001_create_player_sightings_table;
+mod m20230906_000001_add_player_lookup_indexes;
+mod m20230908_000001_add_description_history;
+mod m20230911_000001_create_favicon_blobs_table;
+mod m20230913_000001_add_favicon_phash;
+mod m20230915_000001_add_invalid_favicon;
+mod m20230918_000001_add_description_components;
+mod m20230920_000001_add_derived_uuids;
+mod m20230922_000001_add_observation_key;
+mod m20230922_000002_add_sighting_key;
+mod m20230924_000001_create_description_changes_table;
+mod m20230926_000001_add_favicon_blob_invalid;
+mod m20230928_000001_validate_favicon_blobs;
+mod m20230930_000001_add_description_key;
 
 pub struct Migrator;
 
 #[async_trait::async_trait]
 impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
-        vec![Box::new(m20220101_000001_create_table::Migration)]
+        vec![
+            Box::new(m20220101_000001_create_table::Migration),
+            Box::new(m20230801_000001_create_exclusions_table::Migration),
+            Box::new(m20230805_000001_add_server_ping::Migration),
+            Box::new(m20230810_000001_add_bedrock_edition::Migration),
+            Box::new(m20230812_000001_add_server_query::Migration),
+            Box::new(m20230815_000001_add_server_login::Migration),
+            Box::new(m20230818_000001_create_hostnames_table::Migration),
+            Box::new(m20230822_000001_create_rescan_schedules_table::Migration),
+            Box::new(m20230825_000001_add_ip_priority::Migration),
+            Box::new(m20230828_000001_add_server_state::Migration),
+            Box::new(m20230901_000001_create_server_observations_table::Migration),
+            Box::new(m20230904_000001_create_player_sightings_table::Migration),
+            Box::new(m20230906_000001_add_player_lookup_indexes::Migration),
+            Box::new(m20230908_000001_add_description_history::Migration),
+            Box::new(m20230911_000001_create_favicon_blobs_table::Migration),
+            Box::new(m20230913_000001_add_favicon_phash::Migration),
+            Box::new(m20230915_000001_add_invalid_favicon::Migration),
+            Box::new(m20230918_000001_add_description_components::Migration),
+            Box::new(m20230920_000001_add_derived_uuids::Migration),
+            Box::new(m20230922_000001_add_observation_key::Migration),
+            Box::new(m20230922_000002_add_sighting_key::Migration),
+            Box::new(m20230924_000001_create_description_changes_table::Migration),
+            Box::new(m20230926_000001_add_favicon_blob_invalid::Migration),
+            Box::new(m20230928_000001_validate_favicon_blobs::Migration),
+            Box::new(m20230930_000001_add_description_key::Migration),
+        ]
     }
 }
@@ -0,0 +1,73 @@
+use sea_orm_migration::prelude::*;
+
+/// Reserved ranges nobody should be pinging. Loopback and RFC 1918 space are left out on
+/// purpose so local test servers can still be scanned, add them through europa if needed.
+const RESERVED: [&str; 10] = [
+    "0.0.0.0/8",
+    "100.64.0.0/10",
+    "169.254.0.0/16",
+    "192.0.0.0/24",
+    "192.0.2.0/24",
+    "198.18.0.0/15",
+    "198.51.100.0/24",
+    "203.0.113.0/24",
+    "224.0.0.0/4",
+    "240.0.0.0/4",
+];
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.create_table(
+            Table::create()
+                .table(Exclusions::Table)
+                .if_not_exists()
+                .col(ColumnDef::new(Exclusions::Id).integer().not_null().auto_increment().primary_key())
+                .col(ColumnDef::new(Exclusions::Cidr).string().not_null())
+                .col(ColumnDef::new(Exclusions::Reason).string().not_null())
+                .col(ColumnDef::new(Exclusions::CreatedAt).date_time().not_null())
+                .to_owned(),
+        ).await?;
+
+        manager.create_index(
+            Index::create()
+            .table(Exclusions::Table)
+            .name("idx_exclusions_cidr")
+            .col(Exclusions::Cidr)
+            .unique()
+            .to_owned(),
+        ).await?;
+
+        let mut seed = Query::insert();
+        seed.into_table(Exclusions::Table)
+            .columns([Exclusions::Cidr, Exclusions::Reason, Exclusions::CreatedAt]);
+
+        for cidr in RESERVED {
+            seed.values_panic([cidr.into(), "reserved".into(), Expr::current_timestamp().into()]);
+        }
+
+        manager.exec_stmt(seed).await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Exclusions::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum Exclusions {
+    Table,
+    Id,
+    Cidr,
+    Reason,
+    CreatedAt,
+}
@@ -0,0 +1,113 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.alter_table(
+            Table::alter()
+                .table(Servers::Table)
+                .add_column(ColumnDef::new(Servers::Edition).string().not_null().default("Java"))
+                .add_column(ColumnDef::new(Servers::Gamemode).string())
+                .add_column(ColumnDef::new(Servers::ServerGuid).string())
+                .to_owned(),
+        ).await?;
+
+        manager.alter_table(
+            Table::alter()
+                .table(Ips::Table)
+                .add_column(ColumnDef::new(Ips::Edition).string().not_null().default("Java"))
+                .to_owned(),
+        ).await?;
+
+        // the same ip:port can host a java and a bedrock server
+        manager.drop_index(Index::drop().table(Ips::Table).name("idx_ips_ipport").to_owned()).await?;
+        manager.create_index(
+            Index::create()
+            .table(Ips::Table)
+            .name("idx_ips_ipport")
+            .col(Ips::Ip)
+            .col(Ips::Port)
+            .col(Ips::Edition)
+            .unique()
+            .to_owned(),
+        ).await?;
+
+        manager.drop_index(Index::drop().table(Servers::Table).name("idx_servers_ip").to_owned()).await?;
+        manager.create_index(
+            Index::create()
+            .table(Servers::Table)
+            .name("idx_servers_ip")
+            .col(Servers::Ip)
+            .col(Servers::Port)
+            .col(Servers::Edition)
+            .unique()
+            .to_owned(),
+        ).await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.drop_index(Index::drop().table(Ips::Table).name("idx_ips_ipport").to_owned()).await?;
+        manager.drop_index(Index::drop().table(Servers::Table).name("idx_servers_ip").to_owned()).await?;
+
+        manager.alter_table(
+            Table::alter()
+                .table(Servers::Table)
+                .drop_column(Servers::Edition)
+                .drop_column(Servers::Gamemode)
+                .drop_column(Servers::ServerGuid)
+                .to_owned(),
+        ).await?;
+
+        manager.alter_table(
+            Table::alter()
+                .table(Ips::Table)
+                .drop_column(Ips::Edition)
+                .to_owned(),
+        ).await?;
+
+        manager.create_index(
+            Index::create()
+            .table(Ips::Table)
+            .name("idx_ips_ipport")
+            .col(Ips::Ip)
+            .col(Ips::Port)
+            .unique()
+            .to_owned(),
+        ).await?;
+
+        manager.create_index(
+            Index::create()
+            .table(Servers::Table)
+            .name("idx_servers_ip")
+            .col(Servers::Ip)
+            .col(Servers::Port)
+            .unique()
+            .to_owned(),
+        ).await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum Servers {
+    Table,
+    Ip,
+    Port,
+    Edition,
+    Gamemode,
+    ServerGuid,
+}
+
+#[derive(Iden)]
+enum Ips {
+    Table,
+    Ip,
+    Port,
+    Edition,
+}
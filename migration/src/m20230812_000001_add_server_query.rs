@@ -0,0 +1,41 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.alter_table(
+            Table::alter()
+                .table(Servers::Table)
+                .add_column(ColumnDef::new(Servers::Plugins).text())
+                .add_column(ColumnDef::new(Servers::Map).string())
+                .add_column(ColumnDef::new(Servers::Software).string())
+                .to_owned(),
+        ).await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.alter_table(
+            Table::alter()
+                .table(Servers::Table)
+                .drop_column(Servers::Plugins)
+                .drop_column(Servers::Map)
+                .drop_column(Servers::Software)
+                .to_owned(),
+        ).await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum Servers {
+    Table,
+    Plugins,
+    Map,
+    Software,
+}
@@ -0,0 +1,44 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.alter_table(
+            Table::alter()
+                .table(Servers::Table)
+                .add_column(ColumnDef::new(Servers::DisconnectReason).text())
+                .to_owned(),
+        ).await?;
+
+        // auth used to be hard-coded to Online, nothing has actually been probed yet
+        manager.exec_stmt(
+            Query::update()
+                .table(Servers::Table)
+                .value(Servers::Auth, "Unknown")
+                .to_owned(),
+        ).await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.alter_table(
+            Table::alter()
+                .table(Servers::Table)
+                .drop_column(Servers::DisconnectReason)
+                .to_owned(),
+        ).await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum Servers {
+    Table,
+    Auth,
+    DisconnectReason,
+}
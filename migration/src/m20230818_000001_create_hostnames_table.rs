@@ -0,0 +1,63 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.create_table(
+            Table::create()
+                .table(Hostnames::Table)
+                .if_not_exists()
+                .col(ColumnDef::new(Hostnames::Id).integer().not_null().auto_increment().primary_key())
+                .col(ColumnDef::new(Hostnames::Hostname).string().not_null())
+                .col(ColumnDef::new(Hostnames::ServerId).integer().not_null())
+                .col(ColumnDef::new(Hostnames::UpdatedAt).date_time().not_null())
+                .foreign_key(
+                    ForeignKey::create()
+                    .name("fk_hostnames_server_id")
+                    .from(Hostnames::Table, Hostnames::ServerId)
+                    .to(Servers::Table, Servers::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .on_update(ForeignKeyAction::Cascade)
+                )
+                .to_owned(),
+        ).await?;
+
+        manager.create_index(
+            Index::create()
+            .table(Hostnames::Table)
+            .name("idx_hostnames_hostname")
+            .col(Hostnames::Hostname)
+            .col(Hostnames::ServerId)
+            .unique()
+            .to_owned(),
+        ).await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Hostnames::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum Hostnames {
+    Table,
+    Id,
+    Hostname,
+    ServerId,
+    UpdatedAt,
+}
+
+#[derive(Iden)]
+enum Servers {
+    Table,
+    Id,
+}
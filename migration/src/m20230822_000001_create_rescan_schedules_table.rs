@@ -0,0 +1,82 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.create_table(
+            Table::create()
+                .table(RescanSchedules::Table)
+                .if_not_exists()
+                .col(ColumnDef::new(RescanSchedules::Id).integer().not_null().auto_increment().primary_key())
+                .col(ColumnDef::new(RescanSchedules::Name).string().not_null())
+                .col(ColumnDef::new(RescanSchedules::Cron).string().not_null())
+                .col(ColumnDef::new(RescanSchedules::Selector).text().not_null())
+                .col(ColumnDef::new(RescanSchedules::Timeout).integer().not_null())
+                .col(ColumnDef::new(RescanSchedules::Workers).integer().not_null())
+                .col(ColumnDef::new(RescanSchedules::Enabled).boolean().not_null())
+                .col(ColumnDef::new(RescanSchedules::LastRun).date_time().null())
+                .col(ColumnDef::new(RescanSchedules::CreatedAt).date_time().not_null())
+                .to_owned(),
+        ).await?;
+
+        manager.create_index(
+            Index::create()
+            .table(RescanSchedules::Table)
+            .name("idx_rescan_schedules_name")
+            .col(RescanSchedules::Name)
+            .unique()
+            .to_owned(),
+        ).await?;
+
+        // what the old rescan thread did, left disabled like the toggle it replaces
+        let mut seed = Query::insert();
+        seed.into_table(RescanSchedules::Table)
+            .columns([
+                RescanSchedules::Name,
+                RescanSchedules::Cron,
+                RescanSchedules::Selector,
+                RescanSchedules::Timeout,
+                RescanSchedules::Workers,
+                RescanSchedules::Enabled,
+                RescanSchedules::CreatedAt,
+            ])
+            .values_panic([
+                "stale".into(),
+                "0 */5 * * *".into(),
+                r#"{"type":"stale","limit":10000}"#.into(),
+                5.into(),
+                10.into(),
+                false.into(),
+                Expr::current_timestamp().into(),
+            ]);
+
+        manager.exec_stmt(seed).await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(RescanSchedules::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum RescanSchedules {
+    Table,
+    Id,
+    Name,
+    Cron,
+    Selector,
+    Timeout,
+    Workers,
+    Enabled,
+    LastRun,
+    CreatedAt,
+}
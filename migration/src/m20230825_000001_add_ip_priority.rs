@@ -0,0 +1,49 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.alter_table(
+            Table::alter()
+                .table(Ips::Table)
+                .add_column(ColumnDef::new(Ips::Successes).integer().not_null().default(0))
+                .add_column(ColumnDef::new(Ips::Failures).integer().not_null().default(0))
+                .add_column(ColumnDef::new(Ips::ConsecutiveFailures).integer().not_null().default(0))
+                .to_owned(),
+        ).await?;
+
+        // every address already stored answered at least once
+        let backfill = Query::update()
+            .table(Ips::Table)
+            .value(Ips::Successes, 1)
+            .to_owned();
+
+        manager.exec_stmt(backfill).await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.alter_table(
+            Table::alter()
+                .table(Ips::Table)
+                .drop_column(Ips::Successes)
+                .drop_column(Ips::Failures)
+                .drop_column(Ips::ConsecutiveFailures)
+                .to_owned(),
+        ).await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum Ips {
+    Table,
+    Successes,
+    Failures,
+    ConsecutiveFailures,
+}
@@ -0,0 +1,73 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.alter_table(
+            Table::alter()
+                .table(Ips::Table)
+                .add_column(ColumnDef::new(Ips::LastSuccess).timestamp().null())
+                .add_column(ColumnDef::new(Ips::LastFailure).timestamp().null())
+                .add_column(ColumnDef::new(Ips::LastError).string().null())
+                .to_owned(),
+        ).await?;
+
+        manager.alter_table(
+            Table::alter()
+                .table(Servers::Table)
+                .add_column(ColumnDef::new(Servers::State).string().not_null().default("Online"))
+                .to_owned(),
+        ).await?;
+
+        // failures weren't timestamped until now, so the last scan is the best guess
+        // at the last success for anything that hasn't failed since
+        let backfill = Query::update()
+            .table(Ips::Table)
+            .value(Ips::LastSuccess, Expr::col(Ips::LastScanned))
+            .and_where(Expr::col(Ips::ConsecutiveFailures).eq(0))
+            .to_owned();
+
+        manager.exec_stmt(backfill).await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.alter_table(
+            Table::alter()
+                .table(Ips::Table)
+                .drop_column(Ips::LastSuccess)
+                .drop_column(Ips::LastFailure)
+                .drop_column(Ips::LastError)
+                .to_owned(),
+        ).await?;
+
+        manager.alter_table(
+            Table::alter()
+                .table(Servers::Table)
+                .drop_column(Servers::State)
+                .to_owned(),
+        ).await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum Ips {
+    Table,
+    LastScanned,
+    ConsecutiveFailures,
+    LastSuccess,
+    LastFailure,
+    LastError,
+}
+
+#[derive(Iden)]
+enum Servers {
+    Table,
+    State,
+}
@@ -0,0 +1,71 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.create_table(
+            Table::create()
+                .table(ServerObservations::Table)
+                .if_not_exists()
+                .col(ColumnDef::new(ServerObservations::Id).big_integer().not_null().auto_increment().primary_key())
+                .col(ColumnDef::new(ServerObservations::ServerId).integer().not_null())
+                .col(ColumnDef::new(ServerObservations::ObservedAt).date_time().not_null())
+                .col(ColumnDef::new(ServerObservations::OnlinePlayers).integer().not_null())
+                .col(ColumnDef::new(ServerObservations::MaxPlayers).integer().not_null())
+                .col(ColumnDef::new(ServerObservations::LatencyMs).integer().null())
+                .col(ColumnDef::new(ServerObservations::Version).string().not_null())
+                .col(ColumnDef::new(ServerObservations::Protocol).integer().not_null())
+                .foreign_key(
+                    ForeignKey::create()
+                    .name("fk_server_observations_server_id")
+                    .from(ServerObservations::Table, ServerObservations::ServerId)
+                    .to(Servers::Table, Servers::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .on_update(ForeignKeyAction::Cascade)
+                )
+                .to_owned(),
+        ).await?;
+
+        // every history query is one server over a time range
+        manager.create_index(
+            Index::create()
+            .table(ServerObservations::Table)
+            .name("idx_server_observations_server_time")
+            .col(ServerObservations::ServerId)
+            .col(ServerObservations::ObservedAt)
+            .to_owned(),
+        ).await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ServerObservations::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum ServerObservations {
+    Table,
+    Id,
+    ServerId,
+    ObservedAt,
+    OnlinePlayers,
+    MaxPlayers,
+    LatencyMs,
+    Version,
+    Protocol,
+}
+
+#[derive(Iden)]
+enum Servers {
+    Table,
+    Id,
+}
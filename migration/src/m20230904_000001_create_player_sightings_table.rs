@@ -0,0 +1,110 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.create_table(
+            Table::create()
+                .table(PlayerSightings::Table)
+                .if_not_exists()
+                .col(ColumnDef::new(PlayerSightings::Id).big_integer().not_null().auto_increment().primary_key())
+                .col(ColumnDef::new(PlayerSightings::ServerId).integer().not_null())
+                .col(ColumnDef::new(PlayerSightings::Uuid).string().not_null())
+                .col(ColumnDef::new(PlayerSightings::Name).string().not_null())
+                .col(ColumnDef::new(PlayerSightings::SeenAt).date_time().not_null())
+                .foreign_key(
+                    ForeignKey::create()
+                    .name("fk_player_sightings_server_id")
+                    .from(PlayerSightings::Table, PlayerSightings::ServerId)
+                    .to(Servers::Table, Servers::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .on_update(ForeignKeyAction::Cascade)
+                )
+                .to_owned(),
+        ).await?;
+
+        // sessions are rebuilt either per player or per server over a time range
+        manager.create_index(
+            Index::create()
+            .table(PlayerSightings::Table)
+            .name("idx_player_sightings_uuid_time")
+            .col(PlayerSightings::Uuid)
+            .col(PlayerSightings::SeenAt)
+            .to_owned(),
+        ).await?;
+
+        manager.create_index(
+            Index::create()
+            .table(PlayerSightings::Table)
+            .name("idx_player_sightings_server_time")
+            .col(PlayerSightings::ServerId)
+            .col(PlayerSightings::SeenAt)
+            .to_owned(),
+        ).await?;
+
+        manager.alter_table(
+            Table::alter()
+                .table(Players::Table)
+                .add_column(ColumnDef::new(Players::FirstSeen).date_time().null())
+                .to_owned(),
+        ).await?;
+
+        // the earliest sighting we still know of
+        let backfill = Query::update()
+            .table(Players::Table)
+            .value(Players::FirstSeen, Expr::col(Players::LastSeen))
+            .to_owned();
+
+        manager.exec_stmt(backfill).await?;
+
+        manager.alter_table(
+            Table::alter()
+                .table(Players::Table)
+                .modify_column(ColumnDef::new(Players::FirstSeen).date_time().not_null())
+                .to_owned(),
+        ).await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(PlayerSightings::Table).to_owned())
+            .await?;
+
+        manager.alter_table(
+            Table::alter()
+                .table(Players::Table)
+                .drop_column(Players::FirstSeen)
+                .to_owned(),
+        ).await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum PlayerSightings {
+    Table,
+    Id,
+    ServerId,
+    Uuid,
+    Name,
+    SeenAt,
+}
+
+#[derive(Iden)]
+enum Players {
+    Table,
+    LastSeen,
+    FirstSeen,
+}
+
+#[derive(Iden)]
+enum Servers {
+    Table,
+    Id,
+}
@@ -0,0 +1,43 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.create_index(
+            Index::create()
+            .table(Players::Table)
+            .name("idx_players_uuid")
+            .col(Players::Uuid)
+            .to_owned(),
+        ).await?;
+
+        // names are looked up case insensitively, which sea-query can't build an index for
+        let db = manager.get_connection();
+        db.execute_unprepared("CREATE INDEX idx_players_lower_name ON players (lower(name))").await?;
+        db.execute_unprepared("CREATE INDEX idx_player_sightings_lower_name ON player_sightings (lower(name))").await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.drop_index(Index::drop().table(Players::Table).name("idx_players_uuid").to_owned()).await?;
+        manager.drop_index(Index::drop().table(Players::Table).name("idx_players_lower_name").to_owned()).await?;
+        manager.drop_index(Index::drop().table(PlayerSightings::Table).name("idx_player_sightings_lower_name").to_owned()).await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum Players {
+    Table,
+    Uuid,
+}
+
+#[derive(Iden)]
+enum PlayerSightings {
+    Table,
+}
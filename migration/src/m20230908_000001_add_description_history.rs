@@ -0,0 +1,90 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.alter_table(
+            Table::alter()
+                .table(Descriptions::Table)
+                .add_column(ColumnDef::new(Descriptions::FirstSeen).date_time().null())
+                .add_column(ColumnDef::new(Descriptions::LastSeen).date_time().null())
+                .to_owned(),
+        ).await?;
+
+        manager.alter_table(
+            Table::alter()
+                .table(Servers::Table)
+                .add_column(ColumnDef::new(Servers::CurrentDescriptionId).integer().null())
+                .add_foreign_key(
+                    TableForeignKey::new()
+                    .name("fk_servers_current_description_id")
+                    .from_tbl(Servers::Table)
+                    .from_col(Servers::CurrentDescriptionId)
+                    .to_tbl(Descriptions::Table)
+                    .to_col(Descriptions::Id)
+                    .on_delete(ForeignKeyAction::SetNull)
+                    .on_update(ForeignKeyAction::Cascade)
+                )
+                .to_owned(),
+        ).await?;
+
+        // there's nothing better to go on than the server's own timestamps, and the
+        // newest row is the most likely to be current
+        let db = manager.get_connection();
+        db.execute_unprepared(
+            "UPDATE descriptions d SET first_seen = s.created_at, last_seen = s.updated_at \
+             FROM servers s WHERE s.id = d.server_id",
+        ).await?;
+        db.execute_unprepared(
+            "UPDATE servers s SET current_description_id = \
+             (SELECT max(d.id) FROM descriptions d WHERE d.server_id = s.id)",
+        ).await?;
+
+        manager.alter_table(
+            Table::alter()
+                .table(Descriptions::Table)
+                .modify_column(ColumnDef::new(Descriptions::FirstSeen).date_time().not_null())
+                .modify_column(ColumnDef::new(Descriptions::LastSeen).date_time().not_null())
+                .to_owned(),
+        ).await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.alter_table(
+            Table::alter()
+                .table(Servers::Table)
+                .drop_foreign_key(Alias::new("fk_servers_current_description_id"))
+                .drop_column(Servers::CurrentDescriptionId)
+                .to_owned(),
+        ).await?;
+
+        manager.alter_table(
+            Table::alter()
+                .table(Descriptions::Table)
+                .drop_column(Descriptions::FirstSeen)
+                .drop_column(Descriptions::LastSeen)
+                .to_owned(),
+        ).await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum Descriptions {
+    Table,
+    Id,
+    FirstSeen,
+    LastSeen,
+}
+
+#[derive(Iden)]
+enum Servers {
+    Table,
+    CurrentDescriptionId,
+}
@@ -0,0 +1,163 @@
+use sea_orm_migration::{prelude::*, sea_orm::Statement};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.create_table(
+            Table::create()
+                .table(FaviconBlobs::Table)
+                .if_not_exists()
+                .col(ColumnDef::new(FaviconBlobs::Hash).string().not_null().primary_key())
+                .col(ColumnDef::new(FaviconBlobs::Png).binary().not_null())
+                .col(ColumnDef::new(FaviconBlobs::Size).integer().not_null())
+                .col(ColumnDef::new(FaviconBlobs::CreatedAt).date_time().not_null())
+                .to_owned(),
+        ).await?;
+
+        manager.alter_table(
+            Table::alter()
+                .table(Servers::Table)
+                .add_column(ColumnDef::new(Servers::FaviconHash).string().null())
+                .add_foreign_key(
+                    TableForeignKey::new()
+                    .name("fk_servers_favicon_hash")
+                    .from_tbl(Servers::Table)
+                    .from_col(Servers::FaviconHash)
+                    .to_tbl(FaviconBlobs::Table)
+                    .to_col(FaviconBlobs::Hash)
+                    .on_delete(ForeignKeyAction::SetNull)
+                    .on_update(ForeignKeyAction::Cascade)
+                )
+                .to_owned(),
+        ).await?;
+
+        // for "which servers use this icon"
+        manager.create_index(
+            Index::create()
+            .table(Servers::Table)
+            .name("idx_servers_favicon_hash")
+            .col(Servers::FaviconHash)
+            .to_owned(),
+        ).await?;
+
+        // favicons were stored as base64 with or without the data url prefix and padding.
+        // Anything that isn't base64 at all is dropped rather than failing the migration.
+        let db = manager.get_connection();
+        db.execute_unprepared(
+            "CREATE TEMPORARY TABLE favicon_backfill AS \
+             SELECT server_id, png, encode(sha256(png), 'hex') AS hash FROM ( \
+                 SELECT server_id, decode(rpad(b64, ((length(b64) + 3) / 4) * 4, '='), 'base64') AS png \
+                 FROM ( \
+                     SELECT server_id, regexp_replace(replace(png, 'data:image/png;base64,', ''), '[\\s=]', '', 'g') AS b64 \
+                     FROM favicons \
+                 ) stripped \
+                 WHERE b64 ~ '^[A-Za-z0-9+/]+$' AND length(b64) % 4 <> 1 \
+             ) decoded",
+        ).await?;
+        let dropped = db.query_one(Statement::from_string(
+            manager.get_database_backend(),
+            "SELECT count(*) AS dropped FROM favicons f \
+             WHERE NOT EXISTS (SELECT 1 FROM favicon_backfill b WHERE b.server_id = f.server_id)".to_owned(),
+        )).await?
+            .map(|row| row.try_get::<i64>("", "dropped"))
+            .transpose()?
+            .unwrap_or_default();
+        if dropped > 0 {
+            // the migrator only prints its own target unless it's run with -v
+            tracing::warn!(target: "sea_orm_migration", "Dropped {} favicons that weren't base64", dropped);
+        }
+        db.execute_unprepared(
+            "INSERT INTO favicon_blobs (hash, png, size, created_at) \
+             SELECT DISTINCT ON (hash) hash, png, length(png), now() AT TIME ZONE 'utc' \
+             FROM favicon_backfill",
+        ).await?;
+        db.execute_unprepared(
+            "UPDATE servers s SET favicon_hash = f.hash FROM favicon_backfill f WHERE f.server_id = s.id",
+        ).await?;
+        db.execute_unprepared("DROP TABLE favicon_backfill").await?;
+
+        manager
+            .drop_table(Table::drop().table(Favicons::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.create_table(
+            Table::create()
+                .table(Favicons::Table)
+                .if_not_exists()
+                .col(ColumnDef::new(Favicons::Id).integer().not_null().auto_increment().primary_key())
+                .col(ColumnDef::new(Favicons::ServerId).integer().not_null())
+                .col(ColumnDef::new(Favicons::Png).text().not_null())
+                .foreign_key(
+                    ForeignKey::create()
+                    .name("fk_server_id")
+                    .from(Favicons::Table, Favicons::ServerId)
+                    .to(Servers::Table, Servers::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .on_update(ForeignKeyAction::Cascade)
+                )
+                .to_owned(),
+        ).await?;
+
+        manager.create_index(
+            Index::create()
+            .table(Favicons::Table)
+            .name("idx_favicons_server_id")
+            .col(Favicons::ServerId)
+            .unique()
+            .to_owned(),
+        ).await?;
+
+        // postgres wraps base64 every 76 characters
+        let db = manager.get_connection();
+        db.execute_unprepared(
+            "INSERT INTO favicons (server_id, png) \
+             SELECT s.id, replace(encode(b.png, 'base64'), E'\\n', '') \
+             FROM servers s JOIN favicon_blobs b ON b.hash = s.favicon_hash",
+        ).await?;
+
+        manager.alter_table(
+            Table::alter()
+                .table(Servers::Table)
+                .drop_foreign_key(Alias::new("fk_servers_favicon_hash"))
+                .drop_column(Servers::FaviconHash)
+                .to_owned(),
+        ).await?;
+
+        manager
+            .drop_table(Table::drop().table(FaviconBlobs::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum FaviconBlobs {
+    Table,
+    Hash,
+    Png,
+    Size,
+    CreatedAt,
+}
+
+#[derive(Iden)]
+enum Favicons {
+    Table,
+    Id,
+    ServerId,
+    Png,
+}
+
+#[derive(Iden)]
+enum Servers {
+    Table,
+    Id,
+    FaviconHash,
+}
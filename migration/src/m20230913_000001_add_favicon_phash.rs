@@ -0,0 +1,37 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // the PNG has to be decoded to hash it, so existing blobs are filled in by
+        // europa when it starts rather than here
+        manager.alter_table(
+            Table::alter()
+                .table(FaviconBlobs::Table)
+                .add_column(ColumnDef::new(FaviconBlobs::Phash).big_integer().null())
+                .to_owned(),
+        ).await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.alter_table(
+            Table::alter()
+                .table(FaviconBlobs::Table)
+                .drop_column(FaviconBlobs::Phash)
+                .to_owned(),
+        ).await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum FaviconBlobs {
+    Table,
+    Phash,
+}
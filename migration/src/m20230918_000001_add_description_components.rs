@@ -0,0 +1,48 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.alter_table(
+            Table::alter()
+                .table(Descriptions::Table)
+                .add_column(ColumnDef::new(Descriptions::Components).json_binary().null())
+                .add_column(ColumnDef::new(Descriptions::Plain).string().not_null().default(""))
+                .to_owned(),
+        ).await?;
+
+        // older rows only kept the top level text, the components are gone but their
+        // section sign codes can still be stripped. every colour was stored as "false".
+        let db = manager.get_connection();
+        db.execute_unprepared(
+            "UPDATE descriptions SET plain = regexp_replace(text, '§.?', '', 'g')",
+        ).await?;
+        db.execute_unprepared(
+            "UPDATE descriptions SET colour = 'white' WHERE colour = 'false'",
+        ).await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.alter_table(
+            Table::alter()
+                .table(Descriptions::Table)
+                .drop_column(Descriptions::Components)
+                .drop_column(Descriptions::Plain)
+                .to_owned(),
+        ).await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum Descriptions {
+    Table,
+    Components,
+    Plain,
+}
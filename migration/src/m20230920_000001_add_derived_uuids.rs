@@ -0,0 +1,66 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.alter_table(
+            Table::alter()
+                .table(Players::Table)
+                .add_column(ColumnDef::new(Players::DerivedUuid).boolean().not_null().default(false))
+                .to_owned(),
+        ).await?;
+
+        manager.alter_table(
+            Table::alter()
+                .table(PlayerSightings::Table)
+                .add_column(ColumnDef::new(PlayerSightings::DerivedUuid).boolean().not_null().default(false))
+                .to_owned(),
+        ).await?;
+
+        // names only seen through query were given offline mode (v3) uuids. nobody on a
+        // server that isn't known to be offline mode can have one of those.
+        let db = manager.get_connection();
+        for table in ["players", "player_sightings"] {
+            db.execute_unprepared(&format!(
+                "UPDATE {} SET derived_uuid = true WHERE substr(uuid, 15, 1) = '3' \
+                 AND server_id IN (SELECT id FROM servers WHERE auth <> 'Offline')",
+                table
+            )).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.alter_table(
+            Table::alter()
+                .table(Players::Table)
+                .drop_column(Players::DerivedUuid)
+                .to_owned(),
+        ).await?;
+
+        manager.alter_table(
+            Table::alter()
+                .table(PlayerSightings::Table)
+                .drop_column(PlayerSightings::DerivedUuid)
+                .to_owned(),
+        ).await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum Players {
+    Table,
+    DerivedUuid,
+}
+
+#[derive(Iden)]
+enum PlayerSightings {
+    Table,
+    DerivedUuid,
+}
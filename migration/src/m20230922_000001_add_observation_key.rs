@@ -0,0 +1,62 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // a batch voyager had to send again was stored twice, keep the first copy
+        manager.get_connection().execute_unprepared(
+            "DELETE FROM server_observations a USING server_observations b \
+             WHERE a.server_id = b.server_id AND a.observed_at = b.observed_at AND a.id > b.id",
+        ).await?;
+
+        manager.drop_index(
+            Index::drop()
+            .table(ServerObservations::Table)
+            .name("idx_server_observations_server_time")
+            .to_owned(),
+        ).await?;
+
+        // one observation per scan, observed_at is voyager's scan time
+        manager.create_index(
+            Index::create()
+            .table(ServerObservations::Table)
+            .name("idx_server_observations_server_time")
+            .col(ServerObservations::ServerId)
+            .col(ServerObservations::ObservedAt)
+            .unique()
+            .to_owned(),
+        ).await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.drop_index(
+            Index::drop()
+            .table(ServerObservations::Table)
+            .name("idx_server_observations_server_time")
+            .to_owned(),
+        ).await?;
+
+        manager.create_index(
+            Index::create()
+            .table(ServerObservations::Table)
+            .name("idx_server_observations_server_time")
+            .col(ServerObservations::ServerId)
+            .col(ServerObservations::ObservedAt)
+            .to_owned(),
+        ).await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum ServerObservations {
+    Table,
+    ServerId,
+    ObservedAt,
+}
@@ -0,0 +1,47 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // a batch voyager had to send again was stored twice, keep the first copy
+        manager.get_connection().execute_unprepared(
+            "DELETE FROM player_sightings a USING player_sightings b \
+             WHERE a.server_id = b.server_id AND a.uuid = b.uuid AND a.seen_at = b.seen_at AND a.id > b.id",
+        ).await?;
+
+        manager.create_index(
+            Index::create()
+            .table(PlayerSightings::Table)
+            .name("idx_player_sightings_key")
+            .col(PlayerSightings::ServerId)
+            .col(PlayerSightings::Uuid)
+            .col(PlayerSightings::SeenAt)
+            .unique()
+            .to_owned(),
+        ).await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.drop_index(
+            Index::drop()
+            .table(PlayerSightings::Table)
+            .name("idx_player_sightings_key")
+            .to_owned(),
+        ).await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum PlayerSightings {
+    Table,
+    ServerId,
+    Uuid,
+    SeenAt,
+}
@@ -0,0 +1,83 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.create_table(
+            Table::create()
+                .table(DescriptionChanges::Table)
+                .if_not_exists()
+                .col(ColumnDef::new(DescriptionChanges::Id).big_integer().not_null().auto_increment().primary_key())
+                .col(ColumnDef::new(DescriptionChanges::ServerId).integer().not_null())
+                .col(ColumnDef::new(DescriptionChanges::DescriptionId).integer().not_null())
+                .col(ColumnDef::new(DescriptionChanges::ChangedAt).date_time().not_null())
+                .foreign_key(
+                    ForeignKey::create()
+                    .name("fk_description_changes_server_id")
+                    .from(DescriptionChanges::Table, DescriptionChanges::ServerId)
+                    .to(Servers::Table, Servers::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .on_update(ForeignKeyAction::Cascade)
+                )
+                .foreign_key(
+                    ForeignKey::create()
+                    .name("fk_description_changes_description_id")
+                    .from(DescriptionChanges::Table, DescriptionChanges::DescriptionId)
+                    .to(Descriptions::Table, Descriptions::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .on_update(ForeignKeyAction::Cascade)
+                )
+                .to_owned(),
+        ).await?;
+
+        manager.create_index(
+            Index::create()
+            .table(DescriptionChanges::Table)
+            .name("idx_description_changes_server_time")
+            .col(DescriptionChanges::ServerId)
+            .col(DescriptionChanges::ChangedAt)
+            .to_owned(),
+        ).await?;
+
+        // earlier switches back and forth are lost, each description gets the one
+        // change we can be sure of, when it first showed up
+        manager.get_connection().execute_unprepared(
+            "INSERT INTO description_changes (server_id, description_id, changed_at) \
+             SELECT server_id, id, first_seen FROM descriptions",
+        ).await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(DescriptionChanges::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum DescriptionChanges {
+    Table,
+    Id,
+    ServerId,
+    DescriptionId,
+    ChangedAt,
+}
+
+#[derive(Iden)]
+enum Descriptions {
+    Table,
+    Id,
+}
+
+#[derive(Iden)]
+enum Servers {
+    Table,
+    Id,
+}
@@ -0,0 +1,37 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // blobs europa couldn't decode when filling in perceptual hashes, so it doesn't
+        // fetch them again every time it starts
+        manager.alter_table(
+            Table::alter()
+                .table(FaviconBlobs::Table)
+                .add_column(ColumnDef::new(FaviconBlobs::Invalid).string().null())
+                .to_owned(),
+        ).await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.alter_table(
+            Table::alter()
+                .table(FaviconBlobs::Table)
+                .drop_column(FaviconBlobs::Invalid)
+                .to_owned(),
+        ).await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum FaviconBlobs {
+    Table,
+    Invalid,
+}
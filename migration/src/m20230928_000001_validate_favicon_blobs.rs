@@ -0,0 +1,43 @@
+use sea_orm_migration::{prelude::*, sea_orm::Statement};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // blobs stored before favicons were validated at ingest get the same header
+        // checks, ones that only fail to decode are marked by europa's phash backfill
+        let db = manager.get_connection();
+        let marked = db.execute(Statement::from_string(
+            manager.get_database_backend(),
+            "UPDATE favicon_blobs SET invalid = CASE \
+                 WHEN substring(png from 1 for 8) <> '\\x89504e470d0a1a0a'::bytea THEN 'NotPng' \
+                 WHEN length(png) < 24 OR substring(png from 13 for 4) <> 'IHDR'::bytea THEN 'Corrupt' \
+                 ELSE 'WrongSize' END \
+             WHERE invalid IS NULL AND ( \
+                 substring(png from 1 for 8) <> '\\x89504e470d0a1a0a'::bytea \
+                 OR length(png) < 24 \
+                 OR substring(png from 13 for 4) <> 'IHDR'::bytea \
+                 OR substring(png from 17 for 8) <> '\\x0000004000000040'::bytea \
+             )".to_owned(),
+        )).await?.rows_affected();
+        if marked > 0 {
+            // the migrator only prints its own target unless it's run with -v
+            tracing::warn!(target: "sea_orm_migration", "Marked {} favicons invalid", marked);
+        }
+
+        // same as a scan bringing an invalid favicon
+        db.execute_unprepared(
+            "UPDATE servers s SET favicon_hash = NULL, invalid_favicon = b.invalid \
+             FROM favicon_blobs b WHERE b.hash = s.favicon_hash AND b.invalid IS NOT NULL",
+        ).await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, _manager: &SchemaManager) -> Result<(), DbErr> {
+        // the blobs were never valid, there's nothing to put back
+        Ok(())
+    }
+}
@@ -0,0 +1,86 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.alter_table(
+            Table::alter()
+                .table(Descriptions::Table)
+                .add_column(ColumnDef::new(Descriptions::TextHash).string().null())
+                .to_owned(),
+        ).await?;
+
+        // europa hashes the same UTF-8, so rows stored since components were kept still
+        // match. rows from before that only kept the top level text, a server whose MOTD
+        // had more than that gets one new description the next time it's scanned.
+        manager.get_connection().execute_unprepared(
+            "UPDATE descriptions SET text_hash = encode(sha256(convert_to(text, 'UTF8')), 'hex')",
+        ).await?;
+
+        manager.alter_table(
+            Table::alter()
+                .table(Descriptions::Table)
+                .modify_column(ColumnDef::new(Descriptions::TextHash).string().not_null())
+                .to_owned(),
+        ).await?;
+
+        // the rendered text of a long gradient MOTD doesn't fit in a btree index row
+        manager.drop_index(
+            Index::drop()
+            .table(Descriptions::Table)
+            .name("idx_descriptions_server_id")
+            .to_owned(),
+        ).await?;
+
+        manager.create_index(
+            Index::create()
+            .table(Descriptions::Table)
+            .name("idx_descriptions_server_text_hash")
+            .col(Descriptions::ServerId)
+            .col(Descriptions::TextHash)
+            .unique()
+            .to_owned(),
+        ).await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.drop_index(
+            Index::drop()
+            .table(Descriptions::Table)
+            .name("idx_descriptions_server_text_hash")
+            .to_owned(),
+        ).await?;
+
+        manager.create_index(
+            Index::create()
+            .table(Descriptions::Table)
+            .name("idx_descriptions_server_id")
+            .col(Descriptions::ServerId)
+            .col(Descriptions::Text)
+            .unique()
+            .to_owned(),
+        ).await?;
+
+        manager.alter_table(
+            Table::alter()
+                .table(Descriptions::Table)
+                .drop_column(Descriptions::TextHash)
+                .to_owned(),
+        ).await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum Descriptions {
+    Table,
+    ServerId,
+    Text,
+    TextHash,
+}
@@ -15,6 +15,13 @@ pub struct Model {
     pub strikethrough: bool,
     pub obfuscated: bool,
     pub colour: String,
+    pub first_seen: DateTime,
+    pub last_seen: DateTime,
+    /// The full `util::chat::Component` tree, `None` for rows from before it was kept
+    pub components: Option<Json>,
+    pub plain: String,
+    /// Hex SHA-256 of `text`, what a server's descriptions are unique by
+    pub text_hash: String,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
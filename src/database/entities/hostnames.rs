@@ -3,13 +3,13 @@
 use sea_orm::entity::prelude::*;
 
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
-#[sea_orm(table_name = "favicons")]
+#[sea_orm(table_name = "hostnames")]
 pub struct Model {
     #[sea_orm(primary_key)]
     pub id: i32,
+    pub hostname: String,
     pub server_id: i32,
-    #[sea_orm(column_type = "Text")]
-    pub png: String,
+    pub updated_at: DateTime,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
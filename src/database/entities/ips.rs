@@ -10,6 +10,13 @@ pub struct Model {
     pub ip: String,
     pub port: i32,
     pub last_scanned: Option<DateTime>,
+    pub edition: String,
+    pub successes: i32,
+    pub failures: i32,
+    pub consecutive_failures: i32,
+    pub last_success: Option<DateTime>,
+    pub last_failure: Option<DateTime>,
+    pub last_error: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
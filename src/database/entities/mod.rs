@@ -2,8 +2,14 @@
 
 pub mod prelude;
 
+pub mod description_changes;
 pub mod descriptions;
-pub mod favicons;
+pub mod exclusions;
+pub mod favicon_blobs;
+pub mod hostnames;
 pub mod ips;
+pub mod player_sightings;
 pub mod players;
+pub mod rescan_schedules;
+pub mod server_observations;
 pub mod servers;
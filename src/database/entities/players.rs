@@ -11,6 +11,8 @@ pub struct Model {
     pub uuid: String,
     pub last_seen: DateTime,
     pub server_id: i32,
+    pub first_seen: DateTime,
+    pub derived_uuid: bool,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -1,7 +1,13 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.11.1
 
+pub use super::description_changes::Entity as DescriptionChanges;
 pub use super::descriptions::Entity as Descriptions;
-pub use super::favicons::Entity as Favicons;
+pub use super::exclusions::Entity as Exclusions;
+pub use super::favicon_blobs::Entity as FaviconBlobs;
+pub use super::hostnames::Entity as Hostnames;
 pub use super::ips::Entity as Ips;
+pub use super::player_sightings::Entity as PlayerSightings;
 pub use super::players::Entity as Players;
+pub use super::rescan_schedules::Entity as RescanSchedules;
+pub use super::server_observations::Entity as ServerObservations;
 pub use super::servers::Entity as Servers;
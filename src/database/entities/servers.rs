@@ -17,16 +17,40 @@ pub struct Model {
     pub created_at: DateTime,
     pub updated_at: DateTime,
     pub forge: bool,
+    pub ping: String,
+    pub edition: String,
+    pub gamemode: Option<String>,
+    pub server_guid: Option<String>,
+    pub plugins: Option<String>,
+    pub map: Option<String>,
+    pub software: Option<String>,
+    pub disconnect_reason: Option<String>,
+    pub state: String,
+    pub current_description_id: Option<i32>,
+    pub favicon_hash: Option<String>,
+    pub invalid_favicon: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
     #[sea_orm(has_many = "super::descriptions::Entity")]
     Descriptions,
-    #[sea_orm(has_many = "super::favicons::Entity")]
-    Favicons,
+    #[sea_orm(
+        belongs_to = "super::favicon_blobs::Entity",
+        from = "Column::FaviconHash",
+        to = "super::favicon_blobs::Column::Hash",
+        on_update = "Cascade",
+        on_delete = "SetNull"
+    )]
+    FaviconBlobs,
+    #[sea_orm(has_many = "super::hostnames::Entity")]
+    Hostnames,
+    #[sea_orm(has_many = "super::player_sightings::Entity")]
+    PlayerSightings,
     #[sea_orm(has_many = "super::players::Entity")]
     Players,
+    #[sea_orm(has_many = "super::server_observations::Entity")]
+    ServerObservations,
 }
 
 impl Related<super::descriptions::Entity> for Entity {
@@ -35,9 +59,21 @@ impl Related<super::descriptions::Entity> for Entity {
     }
 }
 
-impl Related<super::favicons::Entity> for Entity {
+impl Related<super::favicon_blobs::Entity> for Entity {
     fn to() -> RelationDef {
-        Relation::Favicons.def()
+        Relation::FaviconBlobs.def()
+    }
+}
+
+impl Related<super::hostnames::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Hostnames.def()
+    }
+}
+
+impl Related<super::player_sightings::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::PlayerSightings.def()
     }
 }
 
@@ -47,4 +83,10 @@ impl Related<super::players::Entity> for Entity {
     }
 }
 
+impl Related<super::server_observations::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ServerObservations.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,96 @@
+//! Every successful ping of a server, kept so player counts can be charted over time.
+//!
+//! Raw observations are returned as they are, longer ranges are better asked for
+//! hourly or daily, which Postgres buckets with `date_trunc`. Description switches are
+//! kept in `description_changes`, see `MotdChange`.
+
+use chrono::NaiveDateTime;
+use sea_orm::{ActiveValue, FromQueryResult};
+use serde::{Deserialize, Serialize};
+
+use crate::util::types::{Description, Server};
+
+use super::entities::server_observations;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Observation {
+    pub observed_at: NaiveDateTime,
+    pub online_players: i32,
+    pub max_players: i32,
+    pub latency_ms: Option<i32>,
+    pub version: String,
+    pub protocol: i32,
+}
+
+impl Observation {
+    pub fn model(server: &Server, server_id: i32) -> server_observations::ActiveModel {
+        server_observations::ActiveModel {
+            server_id: ActiveValue::Set(server_id),
+            observed_at: ActiveValue::Set(server.updated_at),
+            online_players: ActiveValue::Set(server.online_players as i32),
+            max_players: ActiveValue::Set(server.max_players as i32),
+            latency_ms: ActiveValue::Set(server.latency_ms.map(|ms| ms as i32)),
+            version: ActiveValue::Set(server.version.clone()),
+            protocol: ActiveValue::Set(server.protocol),
+            ..Default::default()
+        }
+    }
+
+    pub fn from_model(model: server_observations::Model) -> Self {
+        Self {
+            observed_at: model.observed_at,
+            online_players: model.online_players,
+            max_players: model.max_players,
+            latency_ms: model.latency_ms,
+            version: model.version,
+            protocol: model.protocol,
+        }
+    }
+}
+
+/// The observations that fell in one hour or day
+#[derive(Debug, Clone, Serialize, Deserialize, FromQueryResult)]
+pub struct ObservationBucket {
+    /// Start of the hour or day
+    pub bucket: NaiveDateTime,
+    pub samples: i64,
+    pub online_min: i32,
+    pub online_max: i32,
+    pub online_avg: f64,
+    /// The highest `max_players` seen, it rarely changes within a bucket
+    pub max_players: i32,
+    /// `None` when no observation in the bucket was timed
+    pub latency_avg: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Resolution {
+    #[default]
+    Raw,
+    Hourly,
+    Daily,
+}
+
+impl Resolution {
+    /// The `date_trunc` field, `None` for raw observations
+    pub fn unit(&self) -> Option<&'static str> {
+        match self {
+            Resolution::Raw => None,
+            Resolution::Hourly => Some("hour"),
+            Resolution::Daily => Some("day"),
+        }
+    }
+}
+
+/// One stretch of a server's timeline. The flattened description's own first and last
+/// seen cover every stretch it was used in.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MotdChange {
+    #[serde(flatten)]
+    pub description: Description,
+    /// When a scan first saw the server switch to it
+    pub since: NaiveDateTime,
+    /// When it switched away again, `None` while it's current
+    pub until: Option<NaiveDateTime>,
+}
@@ -1,20 +1,55 @@
-use std::time::{Duration, Instant};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    time::{Duration, Instant},
+};
 
-use crate::util::types::{Description, Entry, Favicon, OntosPlayer};
+use crate::util::types::{
+    Description, Edition, Entry, Exclusion, Favicon, InvalidFavicon, OntosAddress, OntosPlayer,
+    RejectedRecord, RescanSchedule, ScanFailure, ServerState, TargetSelector,
+};
 use crate::{
     database::entities::{players, prelude::*},
     util::types::Server,
 };
 use anyhow::anyhow;
-use log::{debug, error};
+use chrono::NaiveDateTime;
+use ipnet::Ipv4Net;
+use log::{debug, error, warn};
 use rand::seq::SliceRandom;
 use sea_orm::{
-    prelude::*, Condition, ConnectOptions, Database, DatabaseTransaction, TransactionTrait,
+    prelude::*,
+    sea_query::{Expr, Func, OnConflict, Query},
+    ActiveValue, Condition, ConnectOptions, Database, DatabaseTransaction, QueryOrder, QuerySelect,
+    TransactionTrait,
 };
 
-use self::entities::{ips, servers};
+use self::{
+    entities::{
+        description_changes, descriptions, exclusions, favicon_blobs, ips, player_sightings,
+        rescan_schedules, server_observations, servers,
+    },
+    history::{MotdChange, Observation, ObservationBucket, Resolution},
+    priority::RescanPriority,
+    profile::{PlayerProfile, PlayerServer},
+    sessions::{PlayerPresence, Sighting, SightingScope},
+    similarity::{FaviconMatch, SimilarServer},
+};
 
 pub mod entities;
+pub mod history;
+pub mod priority;
+pub mod profile;
+pub mod sessions;
+pub mod similarity;
+
+/// How many addresses a reping picks up at a time
+const REPING_BATCH: usize = 10_000;
+/// A server is dead once it has failed this many rescans in a row...
+const DEAD_AFTER_FAILURES: i32 = 5;
+/// ...and hasn't answered for this long
+const DEAD_AFTER_DAYS: i64 = 7;
+/// How many favicons the perceptual hash backfill holds in memory at once
+const PHASH_BACKFILL_PAGE: u64 = 100;
 
 #[derive(Clone, Debug)]
 pub struct DbConn {
@@ -44,6 +79,8 @@ impl QueryParams {
             "max_players" => servers::Column::MaxPlayers,
             "online_players" => servers::Column::OnlinePlayers,
             "auth" => servers::Column::Auth,
+            "edition" => servers::Column::Edition,
+            "state" => servers::Column::State,
             _ => return Err(anyhow!("Invalid column")),
         };
 
@@ -59,40 +96,47 @@ impl DbConn {
     }
 
     pub async fn add_server(&self, entry: Entry) -> anyhow::Result<DatabaseTransaction> {
-        let Entry {
-            server,
-            description,
-            favicon,
-        } = entry;
+        let txn = self.client.begin().await?;
+        insert_entry(&txn, entry).await?;
 
-        let now = Instant::now();
-        let client = &self.client;
-        let txn = client.begin().await?;
-
-        let server_id = {
-            server.update_scan_time(&txn).await?;
-            server.insert(&txn).await?
-        };
+        Ok(txn)
+    }
 
-        description.insert(&txn, server_id).await?;
+    /// Stores an uploaded batch in one transaction, so a batch that has to be sent
+    /// again was either stored completely or not at all. Anything in it that can't be
+    /// stored is rolled back on its own and returned instead of failing the rest.
+    pub async fn add_batch(
+        &self,
+        servers: Vec<Entry>,
+        failures: &[ScanFailure],
+    ) -> anyhow::Result<Vec<RejectedRecord>> {
+        let txn = self.client.begin().await?;
+        let mut rejected = Vec::new();
 
-        if let Some(players) = server.sample_players {
-            // !! find out why some players are null !!
-            // TODO: try to find a server with null players to debug
-            let list = OntosPlayer::from_sample(players, server_id);
-            if let Err(e) = OntosPlayer::insert(&txn, list).await {
-                error!("Failed to insert players: {}", e);
-            };
+        for (index, entry) in servers.into_iter().enumerate() {
+            let savepoint = txn.begin().await?;
+            match insert_entry(&savepoint, entry).await {
+                Ok(()) => savepoint.commit().await?,
+                Err(e) => {
+                    savepoint.rollback().await?;
+                    rejected.push(RejectedRecord::server(index, e));
+                }
+            }
         }
 
-        if favicon.png.is_some() {
-            favicon.insert(&txn, server_id).await?;
+        for (index, failure) in failures.iter().enumerate() {
+            let savepoint = txn.begin().await?;
+            match record_failure(&savepoint, failure).await {
+                Ok(()) => savepoint.commit().await?,
+                Err(e) => {
+                    savepoint.rollback().await?;
+                    rejected.push(RejectedRecord::failure(index, e));
+                }
+            }
         }
 
-        let elapsed = Instant::now() - now;
-        debug!("Added server in {}ms", elapsed.as_millis());
-
-        Ok(txn)
+        txn.commit().await?;
+        Ok(rejected)
     }
 
     pub async fn create_stats(&self) -> anyhow::Result<DbStats> {
@@ -109,45 +153,138 @@ impl DbConn {
         })
     }
 
-    pub async fn get_random_ip(&self) -> anyhow::Result<String> {
+    pub async fn get_random_ip(&self) -> anyhow::Result<OntosAddress> {
         let client = &self.client;
 
         let size = Ips::find().count(client).await? as i32;
         let index = rand::random::<i32>() % size;
         let res = Ips::find_by_id(index).one(client).await?.unwrap();
-        let host = format!("{}:{}", res.ip, res.port as u16);
 
-        Ok(host)
+        Ok(address(res))
+    }
+
+    /// With `reping` only the most overdue addresses are returned, see `priority`
+    pub async fn get_all_ips(&self, reping: bool) -> anyhow::Result<Vec<OntosAddress>> {
+        if reping {
+            return self.get_overdue_ips(REPING_BATCH).await;
+        }
+
+        let client = &self.client;
+        let ips = Ips::find().all(client).await?;
+        let ips = ips.into_iter().map(address).collect();
+
+        Ok(ips)
     }
 
-    pub async fn get_all_ips(&self, reping: bool) -> anyhow::Result<Vec<String>> {
+    pub async fn get_overdue_ips(&self, limit: usize) -> anyhow::Result<Vec<OntosAddress>> {
+        let list = self.get_priorities(None, Some(limit as u64)).await?;
+
+        Ok(list.into_iter().map(|p| p.address).collect())
+    }
+
+    /// Rescan priority of the `limit` most overdue addresses, or every port on `ip`,
+    /// most overdue first. The database picks them, see `priority::score_expr`.
+    pub async fn get_priorities(
+        &self,
+        ip: Option<&str>,
+        limit: Option<u64>,
+    ) -> anyhow::Result<Vec<RescanPriority>> {
         let client = &self.client;
-        let mut ips = Ips::find().all(client).await?;
+        let now = chrono::Utc::now().naive_utc();
 
-        if reping {
-            ips.sort_by(|a, b| a.last_scanned.cmp(&b.last_scanned));
-            ips.drain(..10_000);
+        let mut ips = Ips::find()
+            .order_by_desc(priority::score_expr(now))
+            .order_by_asc(ips::Column::Id)
+            .limit(limit);
+        if let Some(ip) = ip {
+            ips = ips.filter(ips::Column::Ip.eq(ip));
         }
+        let ips = ips.all(client).await?;
 
-        let ips = ips
+        // only the servers behind the picked addresses
+        let hosts = ips
+            .iter()
+            .map(|model| model.ip.clone())
+            .collect::<BTreeSet<_>>();
+        let players = Servers::find()
+            .select_only()
+            .column(servers::Column::Ip)
+            .column(servers::Column::Port)
+            .column(servers::Column::Edition)
+            .column(servers::Column::OnlinePlayers)
+            .filter(servers::Column::Ip.is_in(hosts))
+            .into_tuple::<(String, i32, String, i32)>()
+            .all(client)
+            .await?
+            .into_iter()
+            .map(|(ip, port, edition, players)| ((ip, port, edition), players))
+            .collect::<HashMap<_, _>>();
+
+        let mut list = ips
+            .into_iter()
+            .map(|model| {
+                let key = (model.ip.clone(), model.port, model.edition.clone());
+                let online = players.get(&key).copied().unwrap_or_default();
+                RescanPriority::new(&model, address(model.clone()), online, now)
+            })
+            .collect::<Vec<_>>();
+
+        list.sort_by(|a, b| b.score.total_cmp(&a.score));
+        Ok(list)
+    }
+
+    /// Only counts against addresses we already know, a failed ping on anything
+    /// else isn't worth a row. The server behind the address goes offline, or dead
+    /// once it has failed `DEAD_AFTER_FAILURES` times and been gone `DEAD_AFTER_DAYS`.
+    /// Failures no newer than the address's last success or failure are ignored.
+    pub async fn record_failure(&self, failure: &ScanFailure) -> anyhow::Result<()> {
+        record_failure(&self.client, failure).await
+    }
+
+    /// How many servers are in each state
+    pub async fn count_states(&self) -> anyhow::Result<BTreeMap<String, i64>> {
+        let client = &self.client;
+        let counts = Servers::find()
+            .select_only()
+            .column(servers::Column::State)
+            .column_as(servers::Column::Id.count(), "count")
+            .group_by(servers::Column::State)
+            .into_tuple::<(String, i64)>()
+            .all(client)
+            .await?
             .into_iter()
-            .map(|ip| format!("{}:{}", ip.ip, ip.port))
             .collect();
 
-        Ok(ips)
+        Ok(counts)
     }
 
-    pub async fn get_some_ips(&self, amount: usize) -> anyhow::Result<Vec<String>> {
+    /// Servers in `state`, most recently updated first
+    pub async fn get_servers_in_state(
+        &self,
+        state: ServerState,
+        limit: u64,
+        offset: u64,
+    ) -> anyhow::Result<Vec<Entry>> {
+        let client = &self.client;
+        let models = Servers::find()
+            .filter(servers::Column::State.eq(state.to_string_but_consistent()))
+            .order_by_desc(servers::Column::UpdatedAt)
+            .limit(limit)
+            .offset(offset)
+            .all(client)
+            .await?;
+
+        self.entries(models).await
+    }
+
+    pub async fn get_some_ips(&self, amount: usize) -> anyhow::Result<Vec<OntosAddress>> {
         let client = &self.client;
         let mut ips = Ips::find().all(client).await?;
 
         ips.shuffle(&mut rand::thread_rng());
         ips.truncate(amount);
 
-        let ips = ips
-            .into_iter()
-            .map(|ip| format!("{}:{}", ip.ip, ip.port))
-            .collect();
+        let ips = ips.into_iter().map(address).collect();
 
         Ok(ips)
     }
@@ -166,24 +303,878 @@ impl DbConn {
         }
 
         let results = Servers::find().filter(condition).all(client).await?;
+        self.entries(results).await
+    }
+
+    /// Looks up the description and favicon for each server
+    async fn entries(&self, models: Vec<servers::Model>) -> anyhow::Result<Vec<Entry>> {
+        let client = &self.client;
         let mut output = Vec::new();
 
-        for model in results {
+        for model in models {
             output.push(Entry {
                 server: Server::from_model(model.clone()),
                 description: {
-                    let model = model.find_related(Descriptions).one(client).await?;
+                    let model = match model.current_description_id {
+                        Some(id) => Descriptions::find_by_id(id).one(client).await?,
+                        None => model.find_related(Descriptions).one(client).await?,
+                    };
                     model.map_or(Description::empty(), Description::from_model)
                 },
-                favicon: {
-                    let model = model.find_related(Favicons).one(client).await?;
-                    model.map_or(Favicon::empty(), Favicon::from_model)
+                favicon: match model.find_related(FaviconBlobs).one(client).await? {
+                    Some(blob) => Favicon::from_model(blob, model.id),
+                    None => Favicon {
+                        invalid: model
+                            .invalid_favicon
+                            .as_deref()
+                            .map(InvalidFavicon::from_str_lossy),
+                        ..Favicon::empty()
+                    },
                 },
             });
         }
 
         Ok(output)
     }
+
+    pub async fn server_exists(&self, server_id: i32) -> anyhow::Result<bool> {
+        let client = &self.client;
+        let model = Servers::find_by_id(server_id).one(client).await?;
+
+        Ok(model.is_some())
+    }
+
+    pub async fn count_observations(
+        &self,
+        server_id: i32,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) -> anyhow::Result<u64> {
+        let client = &self.client;
+        let count = ServerObservations::find()
+            .filter(observation_range(server_id, from, to))
+            .count(client)
+            .await?;
+
+        Ok(count)
+    }
+
+    /// Every observation of a server between `from` and `to`, oldest first
+    pub async fn get_observations(
+        &self,
+        server_id: i32,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) -> anyhow::Result<Vec<Observation>> {
+        let client = &self.client;
+        let list = ServerObservations::find()
+            .filter(observation_range(server_id, from, to))
+            .order_by_asc(server_observations::Column::ObservedAt)
+            .all(client)
+            .await?
+            .into_iter()
+            .map(Observation::from_model)
+            .collect();
+
+        Ok(list)
+    }
+
+    /// Observations between `from` and `to` summarised per hour or day, oldest first
+    pub async fn get_history(
+        &self,
+        server_id: i32,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+        resolution: Resolution,
+    ) -> anyhow::Result<Vec<ObservationBucket>> {
+        let client = &self.client;
+        let Some(unit) = resolution.unit() else {
+            return Err(anyhow!("raw observations aren't bucketed"));
+        };
+
+        let bucket = Expr::cust(&format!("date_trunc('{}', observed_at)", unit));
+        let list = ServerObservations::find()
+            .select_only()
+            .column_as(bucket.clone(), "bucket")
+            .column_as(server_observations::Column::Id.count(), "samples")
+            .column_as(
+                server_observations::Column::OnlinePlayers.min(),
+                "online_min",
+            )
+            .column_as(
+                server_observations::Column::OnlinePlayers.max(),
+                "online_max",
+            )
+            // avg of an integer is a numeric, which doesn't decode into an f64
+            .column_as(Expr::cust("avg(online_players)::float8"), "online_avg")
+            .column_as(server_observations::Column::MaxPlayers.max(), "max_players")
+            .column_as(Expr::cust("avg(latency_ms)::float8"), "latency_avg")
+            .filter(observation_range(server_id, from, to))
+            .group_by(bucket.clone())
+            .order_by_asc(bucket)
+            .into_model::<ObservationBucket>()
+            .all(client)
+            .await?;
+
+        Ok(list)
+    }
+
+    /// Sightings between `from` and `to`, at most `limit` of them
+    pub async fn get_sightings(
+        &self,
+        scope: SightingScope,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+        limit: u64,
+    ) -> anyhow::Result<Vec<Sighting>> {
+        let client = &self.client;
+        // a derived uuid only names a player on the server it was seen on
+        let condition = match scope {
+            SightingScope::Player(uuid) => Condition::all()
+                .add(player_sightings::Column::Uuid.eq(uuid.to_string()))
+                .add(player_sightings::Column::DerivedUuid.eq(false)),
+            SightingScope::Server(id) => {
+                Condition::all().add(player_sightings::Column::ServerId.eq(id))
+            }
+        };
+
+        let list = PlayerSightings::find()
+            .filter(condition)
+            .filter(player_sightings::Column::SeenAt.gte(from))
+            .filter(player_sightings::Column::SeenAt.lt(to))
+            .order_by_asc(player_sightings::Column::SeenAt)
+            .limit(limit)
+            .all(client)
+            .await?
+            .into_iter()
+            .filter_map(Sighting::from_model)
+            .collect();
+
+        Ok(list)
+    }
+
+    /// First and last seen for a player on every server, or for every player on a server,
+    /// most recently seen first
+    pub async fn get_presence(&self, scope: SightingScope) -> anyhow::Result<Vec<PlayerPresence>> {
+        let client = &self.client;
+        let condition = match scope {
+            SightingScope::Player(uuid) => Condition::all()
+                .add(players::Column::Uuid.eq(uuid.to_string()))
+                .add(players::Column::DerivedUuid.eq(false)),
+            SightingScope::Server(id) => Condition::all().add(players::Column::ServerId.eq(id)),
+        };
+
+        let list = Players::find()
+            .filter(condition)
+            .order_by_desc(players::Column::LastSeen)
+            .all(client)
+            .await?
+            .into_iter()
+            .map(PlayerPresence::from_model)
+            .collect();
+
+        Ok(list)
+    }
+
+    /// Every server the uuid was seen on and every name it used, `None` if it never was
+    pub async fn get_player_profile(
+        &self,
+        uuid: uuid::Uuid,
+    ) -> anyhow::Result<Option<PlayerProfile>> {
+        let client = &self.client;
+        let rows = Players::find()
+            .filter(players::Column::Uuid.eq(uuid.to_string()))
+            .filter(players::Column::DerivedUuid.eq(false))
+            .order_by_desc(players::Column::LastSeen)
+            .find_also_related(Servers)
+            .all(client)
+            .await?;
+
+        let sightings = PlayerSightings::find()
+            .select_only()
+            .column(player_sightings::Column::Name)
+            .column_as(player_sightings::Column::SeenAt.min(), "first_seen")
+            .column_as(player_sightings::Column::SeenAt.max(), "last_seen")
+            .filter(player_sightings::Column::Uuid.eq(uuid.to_string()))
+            .filter(player_sightings::Column::DerivedUuid.eq(false))
+            .group_by(player_sightings::Column::Name)
+            .into_tuple::<(String, NaiveDateTime, NaiveDateTime)>()
+            .all(client)
+            .await?;
+
+        if rows.is_empty() && sightings.is_empty() {
+            return Ok(None);
+        }
+
+        let players = rows
+            .iter()
+            .map(|(player, _)| player.clone())
+            .collect::<Vec<_>>();
+        let servers = rows
+            .into_iter()
+            .filter_map(|(player, server)| Some(PlayerServer::new(&player, server?)))
+            .collect();
+
+        Ok(Some(PlayerProfile {
+            uuid,
+            names: profile::name_history(sightings, &players),
+            servers,
+        }))
+    }
+
+    /// Every uuid seen under `name` at some point, ignoring case. Offline mode servers
+    /// hand out their own uuids, so one name often has several. Derived uuids aren't
+    /// anyone's, see `OntosPlayer::derived_uuid`.
+    pub async fn find_players_by_name(
+        &self,
+        name: &str,
+        limit: u64,
+    ) -> anyhow::Result<Vec<uuid::Uuid>> {
+        let client = &self.client;
+        let name = name.to_lowercase();
+
+        let current = Players::find()
+            .select_only()
+            .column(players::Column::Uuid)
+            .distinct()
+            .filter(Expr::expr(Func::lower(Expr::col(players::Column::Name))).eq(name.as_str()))
+            .filter(players::Column::DerivedUuid.eq(false))
+            .limit(limit)
+            .into_tuple::<String>()
+            .all(client)
+            .await?;
+
+        let past = PlayerSightings::find()
+            .select_only()
+            .column(player_sightings::Column::Uuid)
+            .distinct()
+            .filter(
+                Expr::expr(Func::lower(Expr::col(player_sightings::Column::Name)))
+                    .eq(name.as_str()),
+            )
+            .filter(player_sightings::Column::DerivedUuid.eq(false))
+            .limit(limit)
+            .into_tuple::<String>()
+            .all(client)
+            .await?;
+
+        let uuids = current
+            .into_iter()
+            .chain(past)
+            .filter_map(|uuid| uuid::Uuid::parse_str(&uuid).ok())
+            .collect::<BTreeSet<_>>();
+
+        Ok(uuids.into_iter().take(limit as usize).collect())
+    }
+
+    /// Servers whose current description contains `text` once formatting is stripped,
+    /// ignoring case, most recently updated first
+    pub async fn find_servers_by_motd(
+        &self,
+        text: &str,
+        limit: u64,
+        offset: u64,
+    ) -> anyhow::Result<Vec<Entry>> {
+        let client = &self.client;
+        let escaped = text
+            .to_lowercase()
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_");
+
+        let matching = Query::select()
+            .column(descriptions::Column::Id)
+            .from(Descriptions)
+            .and_where(
+                Expr::expr(Func::lower(Expr::col(descriptions::Column::Plain)))
+                    .like(format!("%{}%", escaped)),
+            )
+            .to_owned();
+        let models = Servers::find()
+            .filter(servers::Column::CurrentDescriptionId.in_subquery(matching))
+            .order_by_desc(servers::Column::UpdatedAt)
+            .limit(limit)
+            .offset(offset)
+            .all(client)
+            .await?;
+
+        self.entries(models).await
+    }
+
+    /// Every switch of the server's description, oldest first. A description the
+    /// server went back to shows up once per stretch it was used.
+    /// `None` if there's no such server.
+    pub async fn get_motd_timeline(
+        &self,
+        server_id: i32,
+    ) -> anyhow::Result<Option<Vec<MotdChange>>> {
+        let client = &self.client;
+        if Servers::find_by_id(server_id).one(client).await?.is_none() {
+            return Ok(None);
+        }
+
+        let changes = DescriptionChanges::find()
+            .filter(description_changes::Column::ServerId.eq(server_id))
+            .order_by_asc(description_changes::Column::ChangedAt)
+            .order_by_asc(description_changes::Column::Id)
+            .find_also_related(Descriptions)
+            .all(client)
+            .await?;
+
+        let mut list: Vec<MotdChange> = Vec::with_capacity(changes.len());
+        for (change, model) in changes {
+            let Some(model) = model else {
+                continue;
+            };
+            if let Some(last) = list.last_mut() {
+                last.until = Some(change.changed_at);
+            }
+            list.push(MotdChange {
+                description: Description::from_model(model),
+                since: change.changed_at,
+                until: None,
+            });
+        }
+
+        Ok(Some(list))
+    }
+
+    /// Servers using the favicon with this hash, most recently updated first
+    pub async fn get_servers_by_favicon(
+        &self,
+        hash: &str,
+        limit: u64,
+        offset: u64,
+    ) -> anyhow::Result<Vec<Entry>> {
+        let client = &self.client;
+        let models = Servers::find()
+            .filter(servers::Column::FaviconHash.eq(hash.to_lowercase()))
+            .order_by_desc(servers::Column::UpdatedAt)
+            .limit(limit)
+            .offset(offset)
+            .all(client)
+            .await?;
+
+        self.entries(models).await
+    }
+
+    /// The server's favicon. `None` if there's no such server, `Some(None)` if it
+    /// doesn't have one.
+    pub async fn get_favicon(
+        &self,
+        server_id: i32,
+    ) -> anyhow::Result<Option<Option<favicon_blobs::Model>>> {
+        let client = &self.client;
+        let Some(server) = Servers::find_by_id(server_id).one(client).await? else {
+            return Ok(None);
+        };
+
+        let blob = server.find_related(FaviconBlobs).one(client).await?;
+        Ok(Some(blob))
+    }
+
+    /// Servers whose favicon is at most `max_distance` bits from `phash`, closest
+    /// first and then most recently updated
+    pub async fn get_similar_servers(
+        &self,
+        phash: i64,
+        max_distance: u32,
+        exclude: Option<i32>,
+        limit: u64,
+    ) -> anyhow::Result<Vec<SimilarServer>> {
+        let client = &self.client;
+        let distance = Expr::cust(&format!(
+            "bit_count((favicon_blobs.phash # {})::bit(64))",
+            phash
+        ));
+
+        let mut query = Servers::find()
+            .select_only()
+            .column(servers::Column::Id)
+            .column_as(distance.clone(), "distance")
+            .inner_join(FaviconBlobs)
+            .filter(favicon_blobs::Column::Phash.is_not_null())
+            .filter(Expr::expr(distance.clone()).lte(max_distance));
+        if let Some(server_id) = exclude {
+            query = query.filter(servers::Column::Id.ne(server_id));
+        }
+
+        let matches = query
+            .order_by_asc(distance)
+            .order_by_desc(servers::Column::UpdatedAt)
+            .limit(limit)
+            .into_model::<FaviconMatch>()
+            .all(client)
+            .await?;
+
+        let ids = matches.iter().map(|m| m.id).collect::<Vec<_>>();
+        let mut models = Servers::find()
+            .filter(servers::Column::Id.is_in(ids))
+            .all(client)
+            .await?
+            .into_iter()
+            .map(|model| (model.id, model))
+            .collect::<HashMap<_, _>>();
+
+        // in the order the matches came back
+        let models = matches
+            .iter()
+            .filter_map(|m| models.remove(&m.id))
+            .collect();
+        let list = self
+            .entries(models)
+            .await?
+            .into_iter()
+            .zip(matches)
+            .map(|(entry, m)| SimilarServer {
+                distance: m.distance as u32,
+                entry,
+            })
+            .collect();
+
+        Ok(list)
+    }
+
+    /// Computes the perceptual hash of favicons stored before it existed, a page at a
+    /// time. Ones that don't decode are marked `Corrupt` so the next start skips them,
+    /// and the servers using them lose their favicon like a scan would have had them.
+    /// Returns how many were hashed and how many were marked.
+    pub async fn backfill_favicon_phashes(&self) -> anyhow::Result<(usize, usize)> {
+        let client = &self.client;
+        let (mut hashed, mut corrupt) = (0, 0);
+        let mut after = String::new();
+
+        loop {
+            let blobs = FaviconBlobs::find()
+                .filter(favicon_blobs::Column::Phash.is_null())
+                .filter(favicon_blobs::Column::Invalid.is_null())
+                .filter(favicon_blobs::Column::Hash.gt(after.as_str()))
+                .order_by_asc(favicon_blobs::Column::Hash)
+                .limit(PHASH_BACKFILL_PAGE)
+                .all(client)
+                .await?;
+            let Some(last) = blobs.last() else {
+                break;
+            };
+            after = last.hash.clone();
+
+            // decoding is CPU bound, keep it off the runtime's threads
+            let phashes = tokio::task::spawn_blocking(move || {
+                blobs
+                    .into_iter()
+                    .map(|blob| (Favicon::phash(&blob.png), blob.hash))
+                    .collect::<Vec<_>>()
+            })
+            .await?;
+
+            for (phash, hash) in phashes {
+                let Some(phash) = phash else {
+                    let invalid = InvalidFavicon::Corrupt.to_string_but_consistent();
+                    FaviconBlobs::update_many()
+                        .col_expr(favicon_blobs::Column::Invalid, Expr::value(&invalid))
+                        .filter(favicon_blobs::Column::Hash.eq(&hash))
+                        .exec(client)
+                        .await?;
+                    Servers::update_many()
+                        .col_expr(servers::Column::FaviconHash, Expr::value(None::<String>))
+                        .col_expr(servers::Column::InvalidFavicon, Expr::value(invalid))
+                        .filter(servers::Column::FaviconHash.eq(hash))
+                        .exec(client)
+                        .await?;
+                    corrupt += 1;
+                    continue;
+                };
+
+                FaviconBlobs::update_many()
+                    .col_expr(favicon_blobs::Column::Phash, Expr::value(phash))
+                    .filter(favicon_blobs::Column::Hash.eq(hash))
+                    .exec(client)
+                    .await?;
+                hashed += 1;
+            }
+        }
+
+        Ok((hashed, corrupt))
+    }
+
+    pub async fn get_exclusions(&self) -> anyhow::Result<Vec<Exclusion>> {
+        let client = &self.client;
+        let list = Exclusions::find()
+            .order_by_asc(exclusions::Column::Id)
+            .all(client)
+            .await?
+            .into_iter()
+            .map(Exclusion::from_model)
+            .collect();
+
+        Ok(list)
+    }
+
+    pub async fn add_exclusion(&self, cidr: Ipv4Net, reason: String) -> anyhow::Result<Exclusion> {
+        let client = &self.client;
+        let model = Exclusions::insert(exclusions::ActiveModel {
+            cidr: ActiveValue::Set(cidr.to_string()),
+            reason: ActiveValue::Set(reason),
+            created_at: ActiveValue::Set(chrono::Utc::now().naive_utc()),
+            ..Default::default()
+        })
+        .on_conflict(
+            OnConflict::column(exclusions::Column::Cidr)
+                .update_column(exclusions::Column::Reason)
+                .to_owned(),
+        )
+        .exec_with_returning(client)
+        .await?;
+
+        Ok(Exclusion::from_model(model))
+    }
+
+    /// Returns false if there was no exclusion with that id
+    pub async fn remove_exclusion(&self, id: i32) -> anyhow::Result<bool> {
+        let client = &self.client;
+        let res = Exclusions::delete_by_id(id).exec(client).await?;
+
+        Ok(res.rows_affected > 0)
+    }
+
+    /// Schedules that can't be parsed anymore are left out with a warning
+    pub async fn get_schedules(&self) -> anyhow::Result<Vec<RescanSchedule>> {
+        let client = &self.client;
+        let models = RescanSchedules::find()
+            .order_by_asc(rescan_schedules::Column::Id)
+            .all(client)
+            .await?;
+
+        let mut list = Vec::new();
+        for model in models {
+            let id = model.id;
+            match RescanSchedule::from_model(model) {
+                Ok(schedule) => list.push(schedule),
+                Err(e) => warn!("Skipping invalid rescan schedule {}: {}", id, e),
+            }
+        }
+
+        Ok(list)
+    }
+
+    pub async fn get_schedule(&self, id: i32) -> anyhow::Result<Option<RescanSchedule>> {
+        let client = &self.client;
+        let model = RescanSchedules::find_by_id(id).one(client).await?;
+
+        model.map(RescanSchedule::from_model).transpose()
+    }
+
+    pub async fn add_schedule(&self, schedule: &RescanSchedule) -> anyhow::Result<RescanSchedule> {
+        let client = &self.client;
+        let mut model = schedule.to_active_model()?;
+        model.created_at = ActiveValue::Set(chrono::Utc::now().naive_utc());
+
+        let model = RescanSchedules::insert(model)
+            .exec_with_returning(client)
+            .await?;
+
+        RescanSchedule::from_model(model)
+    }
+
+    pub async fn update_schedule(
+        &self,
+        schedule: &RescanSchedule,
+    ) -> anyhow::Result<RescanSchedule> {
+        let client = &self.client;
+        let mut model = schedule.to_active_model()?;
+        model.id = ActiveValue::Unchanged(schedule.id);
+
+        RescanSchedule::from_model(model.update(client).await?)
+    }
+
+    /// Returns false if there was no schedule with that id
+    pub async fn remove_schedule(&self, id: i32) -> anyhow::Result<bool> {
+        let client = &self.client;
+        let res = RescanSchedules::delete_by_id(id).exec(client).await?;
+
+        Ok(res.rows_affected > 0)
+    }
+
+    pub async fn mark_schedule_run(&self, id: i32) -> anyhow::Result<()> {
+        let client = &self.client;
+        RescanSchedules::update_many()
+            .col_expr(
+                rescan_schedules::Column::LastRun,
+                Expr::value(chrono::Utc::now().naive_utc()),
+            )
+            .filter(rescan_schedules::Column::Id.eq(id))
+            .exec(client)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn select_targets(
+        &self,
+        selector: &TargetSelector,
+    ) -> anyhow::Result<Vec<OntosAddress>> {
+        let client = &self.client;
+
+        let servers = match selector {
+            TargetSelector::Stale { limit } => {
+                return self.get_overdue_ips(*limit as usize).await;
+            }
+            TargetSelector::All => {
+                let ips = Ips::find().all(client).await?;
+                return Ok(ips.into_iter().map(address).collect());
+            }
+            TargetSelector::PlayersOnline { min } => {
+                Servers::find()
+                    .filter(servers::Column::OnlinePlayers.gte(*min))
+                    .all(client)
+                    .await?
+            }
+            TargetSelector::NotSeenFor { days } => {
+                let cutoff = chrono::Utc::now().naive_utc() - chrono::Duration::days(*days);
+                Servers::find()
+                    .filter(servers::Column::UpdatedAt.lt(cutoff))
+                    .all(client)
+                    .await?
+            }
+            TargetSelector::Version { version } => {
+                Servers::find()
+                    .filter(servers::Column::Version.contains(version))
+                    .all(client)
+                    .await?
+            }
+        };
+
+        Ok(servers.into_iter().map(server_address).collect())
+    }
+}
+
+async fn record_failure<C: ConnectionTrait>(
+    client: &C,
+    failure: &ScanFailure,
+) -> anyhow::Result<()> {
+    let Some((ip, port)) = failure.address.host.rsplit_once(':') else {
+        return Err(anyhow!("expected ip:port, got {}", failure.address.host));
+    };
+    let port = port.parse::<u16>()? as i32;
+    let edition = failure.address.edition.to_string_but_consistent();
+
+    // only a failure newer than anything recorded counts, so one sent again after
+    // a lost answer isn't counted twice and a late one doesn't outweigh a success
+    let newer = |column: ips::Column| {
+        Condition::any()
+            .add(column.is_null())
+            .add(column.lt(failure.failed_at))
+    };
+    let updated = Ips::update_many()
+        .col_expr(
+            ips::Column::Failures,
+            Expr::col(ips::Column::Failures).add(1),
+        )
+        .col_expr(
+            ips::Column::ConsecutiveFailures,
+            Expr::col(ips::Column::ConsecutiveFailures).add(1),
+        )
+        .col_expr(
+            ips::Column::LastScanned,
+            Expr::cust_with_values("GREATEST(last_scanned, $1)", [failure.failed_at]),
+        )
+        .col_expr(ips::Column::LastFailure, Expr::value(failure.failed_at))
+        .col_expr(
+            ips::Column::LastError,
+            Expr::value(failure.kind.to_string_but_consistent()),
+        )
+        .filter(ips::Column::Ip.eq(ip))
+        .filter(ips::Column::Port.eq(port))
+        .filter(ips::Column::Edition.eq(&edition))
+        .filter(newer(ips::Column::LastSuccess))
+        .filter(newer(ips::Column::LastFailure))
+        .exec(client)
+        .await?;
+    if updated.rows_affected == 0 {
+        return Ok(());
+    }
+
+    let model = Ips::find()
+        .filter(ips::Column::Ip.eq(ip))
+        .filter(ips::Column::Port.eq(port))
+        .filter(ips::Column::Edition.eq(&edition))
+        .one(client)
+        .await?;
+    let Some(model) = model else {
+        return Ok(());
+    };
+
+    let cutoff = failure.failed_at - chrono::Duration::days(DEAD_AFTER_DAYS);
+    let gone = model.last_success.map_or(true, |last| last < cutoff);
+    let state = if model.consecutive_failures >= DEAD_AFTER_FAILURES && gone {
+        ServerState::Dead
+    } else {
+        ServerState::Offline
+    };
+
+    Servers::update_many()
+        .col_expr(
+            servers::Column::State,
+            Expr::value(state.to_string_but_consistent()),
+        )
+        .filter(servers::Column::Ip.eq(ip))
+        .filter(servers::Column::Port.eq(port))
+        .filter(servers::Column::Edition.eq(&edition))
+        .exec(client)
+        .await?;
+
+    Ok(())
+}
+
+async fn insert_entry(txn: &DatabaseTransaction, entry: Entry) -> anyhow::Result<()> {
+    let Entry {
+        server,
+        description,
+        favicon,
+    } = entry;
+
+    let now = Instant::now();
+
+    let server_id = {
+        server.update_scan_time(txn).await?;
+        server.insert(txn).await?
+    };
+
+    server.insert_hostname(txn, server_id).await?;
+
+    // a batch sent again after a lost answer has the same scan time
+    ServerObservations::insert(Observation::model(&server, server_id))
+        .on_conflict(
+            OnConflict::columns([
+                server_observations::Column::ServerId,
+                server_observations::Column::ObservedAt,
+            ])
+            .do_nothing()
+            .to_owned(),
+        )
+        .exec_without_returning(txn)
+        .await?;
+
+    // only switches are recorded, the description's own last_seen covers the rest.
+    // what the server had as of this scan, so a batch sent again or late doesn't
+    // record a switch that didn't happen
+    let previous = last_description_change(txn, server_id, Some(server.updated_at)).await?;
+    let description_id = description.insert(txn, server_id).await?;
+    if previous != Some(description_id) {
+        DescriptionChanges::insert(description_changes::ActiveModel {
+            server_id: ActiveValue::Set(server_id),
+            description_id: ActiveValue::Set(description_id),
+            changed_at: ActiveValue::Set(server.updated_at),
+            ..Default::default()
+        })
+        .exec_without_returning(txn)
+        .await?;
+
+        // a late batch lands before a switch to the same description, which isn't
+        // a switch anymore
+        let next = DescriptionChanges::find()
+            .filter(description_changes::Column::ServerId.eq(server_id))
+            .filter(description_changes::Column::ChangedAt.gt(server.updated_at))
+            .order_by_asc(description_changes::Column::ChangedAt)
+            .order_by_asc(description_changes::Column::Id)
+            .one(txn)
+            .await?;
+        if let Some(next) = next.filter(|next| next.description_id == description_id) {
+            DescriptionChanges::delete_by_id(next.id).exec(txn).await?;
+        }
+    }
+    let current = last_description_change(txn, server_id, None).await?;
+    Servers::update_many()
+        .col_expr(servers::Column::CurrentDescriptionId, Expr::value(current))
+        .filter(servers::Column::Id.eq(server_id))
+        .exec(txn)
+        .await?;
+
+    if let Some(players) = server.sample_players {
+        if !players.is_empty() {
+            let sightings = players
+                .iter()
+                .map(|player| Sighting::model(player, server_id));
+            PlayerSightings::insert_many(sightings)
+                .on_conflict(
+                    OnConflict::columns([
+                        player_sightings::Column::ServerId,
+                        player_sightings::Column::Uuid,
+                        player_sightings::Column::SeenAt,
+                    ])
+                    .do_nothing()
+                    .to_owned(),
+                )
+                .exec_without_returning(txn)
+                .await?;
+        }
+
+        // !! find out why some players are null !!
+        // TODO: try to find a server with null players to debug
+        // in its own savepoint, a failed statement would abort the rest of the entry
+        let list = OntosPlayer::from_sample(players, server_id);
+        let savepoint = txn.begin().await?;
+        match OntosPlayer::insert(&savepoint, list).await {
+            Ok(_) => savepoint.commit().await?,
+            Err(e) => {
+                error!("Failed to insert players: {}", e);
+                savepoint.rollback().await?;
+            }
+        }
+    }
+
+    favicon.insert(txn, server_id).await?;
+
+    let elapsed = Instant::now() - now;
+    debug!("Added server in {}ms", elapsed.as_millis());
+
+    Ok(())
+}
+
+/// The description the server switched to last, up to `at` if given
+async fn last_description_change(
+    txn: &DatabaseTransaction,
+    server_id: i32,
+    at: Option<NaiveDateTime>,
+) -> anyhow::Result<Option<i32>> {
+    let mut query =
+        DescriptionChanges::find().filter(description_changes::Column::ServerId.eq(server_id));
+    if let Some(at) = at {
+        query = query.filter(description_changes::Column::ChangedAt.lte(at));
+    }
+
+    let change = query
+        .order_by_desc(description_changes::Column::ChangedAt)
+        .order_by_desc(description_changes::Column::Id)
+        .one(txn)
+        .await?;
+
+    Ok(change.map(|change| change.description_id))
+}
+
+fn server_address(model: servers::Model) -> OntosAddress {
+    OntosAddress {
+        host: format!("{}:{}", model.ip, model.port as u16),
+        edition: Edition::from_str_lossy(&model.edition),
+        hostname: None,
+        srv: false,
+    }
+}
+
+fn observation_range(server_id: i32, from: NaiveDateTime, to: NaiveDateTime) -> Condition {
+    Condition::all()
+        .add(server_observations::Column::ServerId.eq(server_id))
+        .add(server_observations::Column::ObservedAt.gte(from))
+        .add(server_observations::Column::ObservedAt.lt(to))
+}
+
+fn address(model: ips::Model) -> OntosAddress {
+    OntosAddress {
+        host: format!("{}:{}", model.ip, model.port as u16),
+        edition: Edition::from_str_lossy(&model.edition),
+        hostname: None,
+        srv: false,
+    }
 }
 
 async fn connect() -> anyhow::Result<DatabaseConnection> {
@@ -0,0 +1,94 @@
+//! How urgently each known address should be rescanned.
+//!
+//! Every address gets a target interval between rescans: busy servers with a good
+//! uptime are due more often, and each consecutive failure doubles the interval so
+//! addresses that died long ago fade out. The score is how far past that interval the
+//! address is, anything at or above 1 is due and higher means more overdue.
+
+use chrono::NaiveDateTime;
+use sea_orm::sea_query::{Expr, SimpleExpr};
+use serde::{Deserialize, Serialize};
+
+use crate::util::types::OntosAddress;
+
+use super::entities::ips;
+
+/// Rescan interval for an address with no players and an average uptime
+const BASE_INTERVAL_HOURS: f64 = 6.0;
+/// Failures past this don't back off any further, 2^8 * 6h is about two months
+const MAX_BACKOFF_EXPONENT: i32 = 8;
+/// Addresses that were never scanned go first
+const NEVER_SCANNED: f64 = f64::MAX;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RescanPriority {
+    #[serde(flatten)]
+    pub address: OntosAddress,
+    pub score: f64,
+    /// The interval the score is measured against
+    pub interval_hours: f64,
+    pub last_scanned: Option<NaiveDateTime>,
+    /// Share of scans that got an answer, smoothed so one scan doesn't decide it
+    pub uptime: f64,
+    pub online_players: i32,
+    pub consecutive_failures: i32,
+    pub last_success: Option<NaiveDateTime>,
+    pub last_failure: Option<NaiveDateTime>,
+    /// What went wrong the last time it failed, see `FailureKind`
+    pub last_error: Option<String>,
+}
+
+/// The score as SQL over `ips`, so the database can pick the most overdue rows without
+/// every one of them being loaded. Has to stay in step with `RescanPriority::new`.
+pub fn score_expr(now: NaiveDateTime) -> SimpleExpr {
+    let sql = format!(
+        "CASE WHEN ips.last_scanned IS NULL THEN 'Infinity'::float8 ELSE \
+         GREATEST(EXTRACT(EPOCH FROM ($1 - ips.last_scanned))::float8, 0) / 3600 \
+         * (1 + LN(1 + GREATEST(COALESCE((SELECT MAX(servers.online_players) FROM servers \
+         WHERE servers.ip = ips.ip AND servers.port = ips.port \
+         AND servers.edition = ips.edition), 0), 0))) \
+         * (0.5 + (ips.successes + 1)::float8 / (ips.successes + ips.failures + 2)) \
+         / ({} * POWER(2, LEAST(GREATEST(ips.consecutive_failures, 0), {}))) END",
+        BASE_INTERVAL_HOURS, MAX_BACKOFF_EXPONENT
+    );
+
+    Expr::cust_with_values(&sql, [now])
+}
+
+impl RescanPriority {
+    /// `online_players` is what the server reported last time it answered
+    pub fn new(
+        model: &ips::Model,
+        address: OntosAddress,
+        online_players: i32,
+        now: NaiveDateTime,
+    ) -> Self {
+        let uptime =
+            (model.successes as f64 + 1.0) / (model.successes as f64 + model.failures as f64 + 2.0);
+
+        let activity = 1.0 + (online_players.max(0) as f64).ln_1p();
+        let backoff = 2f64.powi(model.consecutive_failures.clamp(0, MAX_BACKOFF_EXPONENT));
+        let interval_hours = BASE_INTERVAL_HOURS * backoff / (activity * (0.5 + uptime));
+
+        let score = match model.last_scanned {
+            Some(last) => {
+                let age_hours = (now - last).num_seconds().max(0) as f64 / 3600.0;
+                age_hours / interval_hours
+            }
+            None => NEVER_SCANNED,
+        };
+
+        Self {
+            address,
+            score,
+            interval_hours,
+            last_scanned: model.last_scanned,
+            uptime,
+            online_players,
+            consecutive_failures: model.consecutive_failures,
+            last_success: model.last_success,
+            last_failure: model.last_failure,
+            last_error: model.last_error.clone(),
+        }
+    }
+}
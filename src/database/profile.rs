@@ -0,0 +1,89 @@
+//! Everything known about one player across every server, looked up by uuid or name.
+
+use std::collections::BTreeMap;
+
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::util::types::{Edition, ServerState};
+
+use super::entities::{players, servers};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayerProfile {
+    pub uuid: uuid::Uuid,
+    /// Every name the uuid was seen under, oldest first
+    pub names: Vec<NameUse>,
+    /// Every server the uuid was seen on, most recently seen first
+    pub servers: Vec<PlayerServer>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NameUse {
+    pub name: String,
+    pub first_seen: NaiveDateTime,
+    pub last_seen: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayerServer {
+    pub server_id: i32,
+    pub ip: String,
+    pub port: u16,
+    pub edition: Edition,
+    pub state: ServerState,
+    /// The name the player had there last time
+    pub name: String,
+    pub first_seen: NaiveDateTime,
+    pub last_seen: NaiveDateTime,
+}
+
+impl PlayerServer {
+    pub fn new(player: &players::Model, server: servers::Model) -> Self {
+        Self {
+            server_id: server.id,
+            ip: server.ip,
+            port: server.port as u16,
+            edition: Edition::from_str_lossy(&server.edition),
+            state: ServerState::from_str_lossy(&server.state),
+            name: player.name.clone(),
+            first_seen: player.first_seen,
+            last_seen: player.last_seen,
+        }
+    }
+}
+
+/// Names from sightings, plus any name in `players` that was never sighted. Those are
+/// from before sightings were recorded, and a row's dates are the uuid's rather than
+/// the name's, so they're a rougher guess.
+pub fn name_history(
+    sightings: Vec<(String, NaiveDateTime, NaiveDateTime)>,
+    players: &[players::Model],
+) -> Vec<NameUse> {
+    let mut names = sightings
+        .into_iter()
+        .map(|(name, first, last)| (name, (first, last)))
+        .collect::<BTreeMap<_, _>>();
+
+    let mut unsighted = BTreeMap::<String, (NaiveDateTime, NaiveDateTime)>::new();
+    for player in players.iter().filter(|p| !names.contains_key(&p.name)) {
+        let entry = unsighted
+            .entry(player.name.clone())
+            .or_insert((player.first_seen, player.last_seen));
+        entry.0 = entry.0.min(player.first_seen);
+        entry.1 = entry.1.max(player.last_seen);
+    }
+    names.extend(unsighted);
+
+    let mut list = names
+        .into_iter()
+        .map(|(name, (first_seen, last_seen))| NameUse {
+            name,
+            first_seen,
+            last_seen,
+        })
+        .collect::<Vec<_>>();
+
+    list.sort_by_key(|name| name.first_seen);
+    list
+}
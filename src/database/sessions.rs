@@ -0,0 +1,130 @@
+//! Approximate play sessions, rebuilt from every time a player showed up in a sample.
+//!
+//! A player is only seen when a scan happens to catch them, so sightings on the same
+//! server less than `gap` apart are taken to be one session. A session runs from its
+//! first sighting to its last, the real one started a little before and ended a
+//! little after.
+
+use chrono::{Duration, NaiveDateTime};
+use sea_orm::ActiveValue;
+use serde::{Deserialize, Serialize};
+
+use crate::util::types::OntosPlayer;
+
+use super::entities::{player_sightings, players};
+
+/// Sightings further apart than this start a new session unless asked otherwise
+pub const DEFAULT_SESSION_GAP_MINUTES: i64 = 30;
+
+/// Whose sightings to look at
+#[derive(Debug, Clone, Copy)]
+pub enum SightingScope {
+    Player(uuid::Uuid),
+    Server(i32),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sighting {
+    pub server_id: i32,
+    pub uuid: uuid::Uuid,
+    pub name: String,
+    pub seen_at: NaiveDateTime,
+    /// See `OntosPlayer::derived_uuid`
+    #[serde(default)]
+    pub derived_uuid: bool,
+}
+
+impl Sighting {
+    pub fn model(player: &OntosPlayer, server_id: i32) -> player_sightings::ActiveModel {
+        player_sightings::ActiveModel {
+            server_id: ActiveValue::Set(server_id),
+            uuid: ActiveValue::Set(player.uuid.to_string()),
+            name: ActiveValue::Set(player.name.clone()),
+            seen_at: ActiveValue::Set(player.last_seen),
+            derived_uuid: ActiveValue::Set(player.derived_uuid),
+            ..Default::default()
+        }
+    }
+
+    /// `None` if the stored uuid doesn't parse
+    pub fn from_model(model: player_sightings::Model) -> Option<Self> {
+        Some(Self {
+            server_id: model.server_id,
+            uuid: uuid::Uuid::parse_str(&model.uuid).ok()?,
+            name: model.name,
+            seen_at: model.seen_at,
+            derived_uuid: model.derived_uuid,
+        })
+    }
+}
+
+/// When a player was first and last seen on one server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayerPresence {
+    pub server_id: i32,
+    pub uuid: String,
+    pub name: String,
+    pub first_seen: NaiveDateTime,
+    pub last_seen: NaiveDateTime,
+    /// See `OntosPlayer::derived_uuid`
+    pub derived_uuid: bool,
+}
+
+impl PlayerPresence {
+    pub fn from_model(model: players::Model) -> Self {
+        Self {
+            server_id: model.server_id,
+            uuid: model.uuid,
+            name: model.name,
+            first_seen: model.first_seen,
+            last_seen: model.last_seen,
+            derived_uuid: model.derived_uuid,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayerSession {
+    pub server_id: i32,
+    pub uuid: uuid::Uuid,
+    /// The name from the last sighting
+    pub name: String,
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+    pub sightings: usize,
+    /// See `OntosPlayer::derived_uuid`
+    pub derived_uuid: bool,
+}
+
+/// Groups sightings into sessions, sorted by when they started
+pub fn sessions(mut sightings: Vec<Sighting>, gap: Duration) -> Vec<PlayerSession> {
+    sightings
+        .sort_by(|a, b| (a.server_id, a.uuid, a.seen_at).cmp(&(b.server_id, b.uuid, b.seen_at)));
+
+    let mut list: Vec<PlayerSession> = Vec::new();
+    for sighting in sightings {
+        match list.last_mut() {
+            Some(session)
+                if session.server_id == sighting.server_id
+                    && session.uuid == sighting.uuid
+                    && sighting.seen_at - session.end <= gap =>
+            {
+                session.end = sighting.seen_at;
+                session.name = sighting.name;
+                session.sightings += 1;
+            }
+            _ => list.push(PlayerSession {
+                server_id: sighting.server_id,
+                uuid: sighting.uuid,
+                name: sighting.name,
+                start: sighting.seen_at,
+                end: sighting.seen_at,
+                sightings: 1,
+                derived_uuid: sighting.derived_uuid,
+            }),
+        }
+    }
+
+    list.sort_by_key(|session| session.start);
+    list
+}
@@ -0,0 +1,31 @@
+//! Servers whose favicons look alike, which is how networks of related servers give
+//! themselves away even when an icon was re-saved, recoloured or cropped a little.
+//!
+//! Each stored favicon keeps the perceptual hash from `util::png`, two icons are
+//! compared by how many of its 64 bits differ.
+
+use sea_orm::FromQueryResult;
+use serde::{Deserialize, Serialize};
+
+use crate::util::types::Entry;
+
+/// Icons up to this many bits apart count as similar unless asked otherwise, a
+/// couple of pixels of shift or a new hue stays under it
+pub const DEFAULT_DISTANCE: u32 = 10;
+/// Unrelated icons land around 32 bits apart, well past this
+pub const MAX_DISTANCE: u32 = 20;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SimilarServer {
+    /// Bits the server's favicon hash differs in, 0 for ones that look the same
+    pub distance: u32,
+    #[serde(flatten)]
+    pub entry: Entry,
+}
+
+/// A server whose favicon is close enough, before its entry is looked up
+#[derive(Debug, FromQueryResult)]
+pub struct FaviconMatch {
+    pub id: i32,
+    pub distance: i64,
+}
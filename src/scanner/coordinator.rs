@@ -0,0 +1,277 @@
+//! Worker side of europa's coordinator, see `web::coordinator`.
+//! Enabled with `VOYAGER_COORDINATED=true`, after which this instance scans
+//! whatever europa leases to it on top of its own HTTP API.
+
+use std::{
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use anyhow::anyhow;
+use log::{debug, info, warn};
+use once_cell::sync::Lazy;
+use tokio::sync::Mutex;
+
+use crate::{
+    database::DbConn,
+    util::types::OntosAddress,
+    web::{
+        coordinator::{LeaseInfo, ScanOptions},
+        server::{Response, WebRequest},
+    },
+};
+
+use super::{
+    jobs::{JobKind, JobRegistry, JobStatus},
+    targets::TargetSet,
+    worker::{self, ScanJob},
+};
+
+/// Targets asked for per lease when `VOYAGER_LEASE_SIZE` isn't set
+const DEFAULT_LEASE_SIZE: usize = 100;
+/// Workers per lease when `VOYAGER_LEASE_WORKERS` isn't set
+const DEFAULT_LEASE_WORKERS: usize = 10;
+/// Seconds per target when neither the lease nor `VOYAGER_LEASE_TIMEOUT` set one
+const DEFAULT_LEASE_TIMEOUT: i32 = 5;
+/// How long to wait before asking again when the queue is empty
+const IDLE_POLL: Duration = Duration::from_secs(10);
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// `None` unless `VOYAGER_COORDINATED` is set
+pub static COORDINATOR: Lazy<Option<Arc<CoordinatorClient>>> =
+    Lazy::new(|| CoordinatorClient::from_env().map(Arc::new));
+
+pub struct CoordinatorClient {
+    client: reqwest::Client,
+    base: String,
+    key: String,
+    name: String,
+    lease_size: usize,
+    workers: usize,
+    timeout: i32,
+    /// 0 until registered, reset when europa forgets about us
+    worker: AtomicU64,
+}
+
+impl CoordinatorClient {
+    pub fn from_env() -> Option<Self> {
+        let enabled = std::env::var("VOYAGER_COORDINATED").unwrap_or_default();
+        if !matches!(enabled.as_str(), "1" | "true") {
+            return None;
+        }
+
+        fn env_or<T: FromStr>(key: &str, default: T) -> T {
+            std::env::var(key)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        }
+
+        let url = std::env::var("WEBSERVER_URL").ok()?;
+        let port = std::env::var("WEBSERVER_PORT").ok()?;
+
+        Some(Self {
+            client: reqwest::Client::new(),
+            base: format!("http://{}:{}", url, port),
+            key: std::env::var("ADMIN_KEY").unwrap_or_default(),
+            name: std::env::var("VOYAGER_WORKER_NAME").unwrap_or("voyager".to_string()),
+            lease_size: env_or("VOYAGER_LEASE_SIZE", DEFAULT_LEASE_SIZE),
+            workers: env_or("VOYAGER_LEASE_WORKERS", DEFAULT_LEASE_WORKERS).max(1),
+            timeout: env_or("VOYAGER_LEASE_TIMEOUT", DEFAULT_LEASE_TIMEOUT).max(1),
+            worker: AtomicU64::new(0),
+        })
+    }
+
+    async fn post(&self, path: &str, body: &WebRequest) -> anyhow::Result<Response> {
+        let res = self
+            .client
+            .post(format!("{}{}", self.base, path))
+            .header("auth", &self.key)
+            .json(body)
+            .send()
+            .await?
+            .json::<Response>()
+            .await?;
+
+        Ok(res)
+    }
+
+    /// Registers if we aren't already, returning the worker id
+    async fn worker_id(&self) -> anyhow::Result<u64> {
+        let id = self.worker.load(Ordering::Relaxed);
+        if id != 0 {
+            return Ok(id);
+        }
+
+        let body = WebRequest {
+            name: Some(self.name.clone()),
+            ..Default::default()
+        };
+        let res = self.post("/workers", &body).await?;
+        let Some(id) = res.data.and_then(|data| data.worker) else {
+            return Err(anyhow!("europa answered {}: {}", res.status, res.message));
+        };
+
+        info!("Registered with the coordinator as worker {}", id);
+        self.worker.store(id, Ordering::Relaxed);
+        Ok(id)
+    }
+
+    /// Checks the answer, forgetting our id if europa doesn't know it anymore
+    fn check(&self, res: Response) -> anyhow::Result<Response> {
+        match res.status {
+            200 => Ok(res),
+            404 => {
+                self.worker.store(0, Ordering::Relaxed);
+                Err(anyhow!("europa forgot this worker, registering again"))
+            }
+            status => Err(anyhow!("europa answered {}: {}", status, res.message)),
+        }
+    }
+
+    async fn heartbeat(&self) -> anyhow::Result<()> {
+        let id = self.worker_id().await?;
+        let res = self
+            .post(
+                &format!("/workers/{}/heartbeat", id),
+                &WebRequest::default(),
+            )
+            .await?;
+        self.check(res)?;
+
+        Ok(())
+    }
+
+    async fn lease(&self) -> anyhow::Result<Option<LeaseInfo>> {
+        let id = self.worker_id().await?;
+        let body = WebRequest {
+            size: Some(self.lease_size),
+            ..Default::default()
+        };
+        let res = self.post(&format!("/workers/{}/lease", id), &body).await?;
+
+        Ok(self.check(res)?.data.and_then(|data| data.lease))
+    }
+
+    /// `done` completes the lease, otherwise it goes back on the queue
+    async fn finish(&self, lease: u64, done: bool) -> anyhow::Result<()> {
+        let id = self.worker_id().await?;
+        let op = if done { "complete" } else { "release" };
+        let res = self
+            .post(
+                &format!("/workers/{}/leases/{}/{}", id, lease, op),
+                &WebRequest::default(),
+            )
+            .await?;
+        self.check(res)?;
+
+        Ok(())
+    }
+
+    /// Adds targets to europa's queue, any already pending there are skipped so
+    /// every instance can do this without doubling up.
+    pub async fn enqueue(
+        &self,
+        targets: Vec<OntosAddress>,
+        options: ScanOptions,
+    ) -> anyhow::Result<String> {
+        let body = WebRequest {
+            targets: Some(targets),
+            options: Some(options),
+            ..Default::default()
+        };
+        let res = self.post("/queue", &body).await?;
+
+        Ok(self.check(res)?.message)
+    }
+
+    /// Leases and scans batches forever, never returns.
+    pub async fn run(self: Arc<Self>, jobs: Arc<Mutex<JobRegistry>>, db: Option<DbConn>) {
+        let mut backoff = MIN_BACKOFF;
+
+        loop {
+            let lease = match self.lease().await {
+                Ok(Some(lease)) => lease,
+                Ok(None) => {
+                    tokio::time::sleep(IDLE_POLL).await;
+                    continue;
+                }
+                Err(e) => {
+                    warn!("Failed to lease from the coordinator: {}", e);
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                    continue;
+                }
+            };
+
+            debug!(
+                "Leased {} targets as lease {}",
+                lease.targets.len(),
+                lease.id
+            );
+            let status = self.scan(&lease, &jobs, db.clone()).await;
+
+            let done = status == JobStatus::Finished;
+            if let Err(e) = self.finish(lease.id, done).await {
+                warn!("Failed to return lease {}: {}", lease.id, e);
+            }
+
+            // a scan that couldn't start, e.g. without exclusions, fails the same way
+            // on the next lease straight away
+            if !done {
+                warn!("Lease {} {}, retrying in {:?}", lease.id, status, backoff);
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+                continue;
+            }
+            backoff = MIN_BACKOFF;
+        }
+    }
+
+    async fn scan(
+        self: &Arc<Self>,
+        lease: &LeaseInfo,
+        jobs: &Mutex<JobRegistry>,
+        db: Option<DbConn>,
+    ) -> JobStatus {
+        let options = lease.options;
+        let targets = TargetSet::from_addresses(lease.targets.clone());
+        let timeout = options.timeout.unwrap_or(self.timeout);
+        let Some(mut job) = ScanJob::new(targets, Some(timeout), Some(self.workers)) else {
+            return JobStatus::Finished;
+        };
+        job.ping = options.ping;
+        job.query = options.query;
+        job.login = options.login;
+        // whoever queued the targets knows whether they're known addresses
+        job.report_failures = options.report_failures;
+        job.db = db;
+
+        let handle = jobs
+            .lock()
+            .await
+            .register(JobKind::Lease, job.targets.len());
+
+        // a third of the timeout leaves room for a couple of missed beats
+        let interval = Duration::from_secs((lease.timeout_secs / 3).max(1));
+        let client = Arc::clone(self);
+        let heartbeat = tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                if let Err(e) = client.heartbeat().await {
+                    warn!("Coordinator heartbeat failed: {}", e);
+                }
+            }
+        });
+
+        worker::run_blocking(job, Arc::clone(&handle)).await;
+        heartbeat.abort();
+
+        handle.status()
+    }
+}
@@ -5,20 +5,47 @@ use self::routes::Response;
 use axum::Json;
 use tokio::sync::Mutex;
 
-use super::rescan::RescanStatus;
+use super::{
+    jobs::{JobRegistry, JobSummary},
+    rescan::Scheduler,
+    targets::InvalidTarget,
+};
+
+use crate::util::types::RescanSchedule;
 
 pub mod routes;
 
 #[derive(Clone)]
 pub struct AppState {
-    pub db: crate::database::DbConn,
-    pub rescan_active: Arc<Mutex<RescanStatus>>,
+    pub db: Option<crate::database::DbConn>,
+    /// `None` without a database
+    pub scheduler: Option<Arc<Scheduler>>,
+    pub jobs: Arc<Mutex<JobRegistry>>,
 }
 
 pub fn success(message: String) -> Json<Response> {
     Json(Response {
         status: 200,
         message,
+        ..Default::default()
+    })
+}
+
+pub fn jobs(message: String, jobs: Vec<JobSummary>) -> Json<Response> {
+    Json(Response {
+        status: 200,
+        message,
+        jobs: Some(jobs),
+        ..Default::default()
+    })
+}
+
+pub fn schedules(message: String, schedules: Vec<RescanSchedule>) -> Json<Response> {
+    Json(Response {
+        status: 200,
+        message,
+        schedules: Some(schedules),
+        ..Default::default()
     })
 }
 
@@ -26,5 +53,15 @@ pub fn error(message: String) -> Json<Response> {
     Json(Response {
         status: 400,
         message,
+        ..Default::default()
+    })
+}
+
+pub fn invalid_targets(errors: Vec<InvalidTarget>) -> Json<Response> {
+    Json(Response {
+        status: 400,
+        message: format!("{} invalid targets", errors.len()),
+        errors: Some(errors),
+        ..Default::default()
     })
 }
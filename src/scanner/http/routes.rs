@@ -6,34 +6,128 @@ use axum::{
 use serde::{Deserialize, Serialize};
 
 use crate::scanner::worker;
-use crate::scanner::{rescan::RescanStatus, worker::ScanJob};
+use crate::scanner::{
+    jobs::{JobKind, JobStatus, JobSummary},
+    sink::SinkConfig,
+    targets::{InvalidTarget, TargetSet},
+    worker::ScanJob,
+};
+use crate::util::{
+    cron::Cron,
+    types::{Edition, PingMode, RescanSchedule, TargetSelector},
+};
 
 use super::AppState;
 
-#[derive(Serialize, Deserialize)]
+/// Upper bound on the worker count a single request can ask for
+const MAX_WORKERS: usize = 1024;
+/// What the old fixed rescan used, for schedules that don't say otherwise
+const DEFAULT_RESCAN_TIMEOUT: i32 = 5;
+const DEFAULT_RESCAN_WORKERS: i32 = 10;
+
+#[derive(Serialize, Deserialize, Default)]
 pub struct Response {
     pub status: u16,
     pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jobs: Option<Vec<JobSummary>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub errors: Option<Vec<InvalidTarget>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schedules: Option<Vec<RescanSchedule>>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct ScanInput {
+    /// `host:port`, bare hosts, CIDR blocks or dash ranges, see `TargetSet::parse`
     pub hosts: Vec<String>,
+    /// Ports to cross with every entry that doesn't specify one, e.g. `25565,25566`
+    pub ports: Option<String>,
     pub timeout: Option<i32>,
+    pub workers: Option<usize>,
+    /// `Auto` (default), `Modern` or `Legacy`, only used for Java targets
+    pub ping: Option<PingMode>,
+    /// `Java` (default), `Bedrock` or both, every target is pinged once per edition
+    pub editions: Option<Vec<Edition>>,
+    /// Also run a GameSpy4 query against every Java server that answers, defaults to false
+    pub query: Option<bool>,
+    /// Also attempt a login to find out whether the server is online mode, defaults to false
+    pub login: Option<bool>,
+    /// Where results go, e.g. `{"type": "csv", "file": "scan.csv"}`, defaults to europa
+    pub sink: Option<SinkConfig>,
+}
+
+/// Creates a rescan schedule, or changes one when only some fields are given
+#[derive(Serialize, Deserialize)]
+pub struct ScheduleInput {
+    pub name: Option<String>,
+    /// Five field cron expression in UTC, e.g. `0 */5 * * *`, see `util::cron`
+    pub cron: Option<String>,
+    /// e.g. `{"type": "players_online", "min": 1}` or `{"type": "not_seen_for", "days": 7}`
+    pub selector: Option<TargetSelector>,
+    pub timeout: Option<i32>,
+    pub workers: Option<usize>,
+    pub enabled: Option<bool>,
+}
+
+impl ScheduleInput {
+    fn apply(self, schedule: &mut RescanSchedule) -> Result<(), String> {
+        if let Some(name) = self.name {
+            schedule.name = name;
+        }
+
+        if let Some(cron) = self.cron {
+            schedule.cron = Cron::parse(&cron).map_err(|e| e.to_string())?;
+        }
+
+        if let Some(selector) = self.selector {
+            schedule.selector = selector;
+        }
+
+        if let Some(timeout) = self.timeout {
+            if timeout <= 0 {
+                return Err(format!("invalid timeout: {}", timeout));
+            }
+            schedule.timeout = timeout;
+        }
+
+        if let Some(workers) = self.workers {
+            if workers == 0 || workers > MAX_WORKERS {
+                return Err(format!(
+                    "workers must be between 1 and {}, got {}",
+                    MAX_WORKERS, workers
+                ));
+            }
+            schedule.workers = workers as i32;
+        }
+
+        if let Some(enabled) = self.enabled {
+            schedule.enabled = enabled;
+        }
+
+        Ok(())
+    }
 }
 
 pub fn app() -> Router {
     Router::new()
         .route("/", get(index))
         .route("/scan", post(single_scan))
-        .route("/repings/:op", post(toggle_repings))
+        .route("/scan/bulk", post(multi_scan))
+        .route("/schedules", get(list_schedules).post(add_schedule))
+        .route(
+            "/schedules/:id",
+            get(get_schedule)
+                .patch(update_schedule)
+                .delete(remove_schedule),
+        )
+        .route("/schedules/:id/run", post(run_schedule))
+        .route("/jobs", get(list_jobs))
+        .route("/jobs/:id", get(get_job).delete(cancel_job))
 }
 
 pub async fn index() -> Json<Response> {
-    Json(Response {
-        status: 200,
-        message: "Hello, World!".to_string(),
-    })
+    super::success("Hello, World!".to_string())
 }
 
 pub async fn single_scan(
@@ -41,19 +135,57 @@ pub async fn single_scan(
     extract::Json(input): extract::Json<ScanInput>,
 ) -> Json<Response> {
     let timeout_sec = input.timeout.unwrap_or(10);
-    let _db = &state.db;
-    let default = "127.0.0.1".to_string();
-    let host = input.hosts.first().unwrap_or(&default);
+    if timeout_sec <= 0 {
+        return super::error(format!("invalid timeout: {}", timeout_sec));
+    }
 
-    let Some(job) = ScanJob::new(vec![host.clone()], Some(timeout_sec), None) else {
-        return super::error(format!("no target provided"));
+    let host = match input.hosts.as_slice() {
+        [host] => host,
+        [] => return super::error("no target provided".to_string()),
+        _ => return super::error("more than one target, use /scan/bulk".to_string()),
     };
 
-    if let Err(e) = worker::run(job).await {
-        return super::error(format!("failed to run scan job for {}: {}", host, e));
-    }
+    let targets = match TargetSet::parse(
+        &input.hosts,
+        input.ports.as_deref(),
+        input.editions.as_deref().unwrap_or_default(),
+    ) {
+        Ok(targets) => targets,
+        Err(errors) => return super::invalid_targets(errors),
+    };
+
+    let Some(mut job) = ScanJob::new(targets, Some(timeout_sec), None) else {
+        return super::error("no target provided".to_string());
+    };
+    job.ping = input.ping.unwrap_or_default();
+    job.query = input.query.unwrap_or_default();
+    job.login = input.login.unwrap_or_default();
+    job.db = state.db.clone();
+    job.sink = match input
+        .sink
+        .unwrap_or_default()
+        .build(state.db.as_ref())
+        .await
+    {
+        Ok(sink) => sink,
+        Err(e) => return super::error(format!("invalid sink: {}", e)),
+    };
+
+    let handle = state
+        .jobs
+        .lock()
+        .await
+        .register(JobKind::Scan, job.targets.len());
+    let summary = handle.summary();
+    worker::run(job, handle);
 
-    super::success(format!("scanning {} with {}s timeout", host, timeout_sec))
+    super::jobs(
+        format!(
+            "started job {} scanning {} with {}s timeout",
+            summary.id, host, timeout_sec
+        ),
+        vec![summary],
+    )
 }
 
 pub async fn multi_scan(
@@ -61,51 +193,241 @@ pub async fn multi_scan(
     extract::Json(input): extract::Json<ScanInput>,
 ) -> Json<Response> {
     let timeout_sec = input.timeout.unwrap_or(10);
-    let len = input.hosts.len();
-    let _db = &state.db;
+    if timeout_sec <= 0 {
+        return super::error(format!("invalid timeout: {}", timeout_sec));
+    }
 
-    let Some(job) = ScanJob::new(input.hosts, Some(timeout_sec), None) else {
-        return super::error(format!("no targets provided"));
+    let workers = input.workers.unwrap_or(1);
+    if workers == 0 || workers > MAX_WORKERS {
+        return super::error(format!(
+            "workers must be between 1 and {}, got {}",
+            MAX_WORKERS, workers
+        ));
+    }
+
+    let targets = match TargetSet::parse(
+        &input.hosts,
+        input.ports.as_deref(),
+        input.editions.as_deref().unwrap_or_default(),
+    ) {
+        Ok(targets) => targets,
+        Err(errors) => return super::invalid_targets(errors),
     };
+    let len = targets.len();
 
-    if let Err(e) = worker::run(job).await {
-        return super::error(format!("failed to run scan job: {}", e));
-    }
+    let Some(mut job) = ScanJob::new(targets, Some(timeout_sec), Some(workers)) else {
+        return super::error("no targets provided".to_string());
+    };
+    job.ping = input.ping.unwrap_or_default();
+    job.query = input.query.unwrap_or_default();
+    job.login = input.login.unwrap_or_default();
+    job.db = state.db.clone();
+    job.sink = match input
+        .sink
+        .unwrap_or_default()
+        .build(state.db.as_ref())
+        .await
+    {
+        Ok(sink) => sink,
+        Err(e) => return super::error(format!("invalid sink: {}", e)),
+    };
+
+    let handle = state.jobs.lock().await.register(JobKind::Scan, len);
+    let summary = handle.summary();
+    worker::run(job, handle);
 
-    super::success(format!("started scanning {} hosts", len))
+    super::jobs(
+        format!(
+            "started job {} scanning {} targets with {} workers and {}s timeout",
+            summary.id, len, workers, timeout_sec
+        ),
+        vec![summary],
+    )
 }
 
-pub async fn toggle_repings(
+pub async fn list_schedules(Extension(state): Extension<AppState>) -> Json<Response> {
+    let Some(scheduler) = state.scheduler else {
+        return no_database();
+    };
+
+    match scheduler.db().get_schedules().await {
+        Ok(list) => super::schedules(format!("{} rescan schedules", list.len()), list),
+        Err(e) => super::error(format!("failed to fetch schedules: {}", e)),
+    }
+}
+
+pub async fn get_schedule(
     Extension(state): Extension<AppState>,
-    extract::Path(input): extract::Path<String>,
+    extract::Path(id): extract::Path<i32>,
 ) -> Json<Response> {
-    enum Action {
-        Status,
-        Toggle,
+    let Some(scheduler) = state.scheduler else {
+        return no_database();
+    };
+
+    match scheduler.db().get_schedule(id).await {
+        Ok(Some(schedule)) => super::schedules(format!("schedule {}", id), vec![schedule]),
+        Ok(None) => super::error(format!("no schedule with id {}", id)),
+        Err(e) => super::error(format!("failed to fetch schedule: {}", e)),
     }
+}
 
-    let action = match input {
-        s if s == "status" => Action::Status,
-        s if s == "toggle" => Action::Toggle,
-        _ => return super::error("invalid input".to_string()),
+pub async fn add_schedule(
+    Extension(state): Extension<AppState>,
+    extract::Json(input): extract::Json<ScheduleInput>,
+) -> Json<Response> {
+    let Some(scheduler) = state.scheduler else {
+        return no_database();
     };
 
-    match action {
-        Action::Status => {
-            let status = state.rescan_active.lock().await;
+    let (Some(name), Some(cron), Some(selector)) = (
+        input.name.clone(),
+        input.cron.clone(),
+        input.selector.clone(),
+    ) else {
+        return super::error("name, cron and selector are required".to_string());
+    };
 
-            super::success(format!("rescanner is {}", *status))
+    let cron = match Cron::parse(&cron) {
+        Ok(cron) => cron,
+        Err(e) => return super::error(e.to_string()),
+    };
+
+    let mut schedule = RescanSchedule {
+        id: 0,
+        name,
+        cron,
+        selector,
+        timeout: DEFAULT_RESCAN_TIMEOUT,
+        workers: DEFAULT_RESCAN_WORKERS,
+        enabled: true,
+        last_run: None,
+        created_at: chrono::Utc::now().naive_utc(),
+    };
+    if let Err(e) = input.apply(&mut schedule) {
+        return super::error(e);
+    }
+
+    match scheduler.db().add_schedule(&schedule).await {
+        Ok(schedule) => {
+            super::schedules(format!("created schedule {}", schedule.id), vec![schedule])
         }
+        Err(e) => super::error(format!("failed to create schedule: {}", e)),
+    }
+}
 
-        Action::Toggle => {
-            let mut status = state.rescan_active.lock().await;
+pub async fn update_schedule(
+    Extension(state): Extension<AppState>,
+    extract::Path(id): extract::Path<i32>,
+    extract::Json(input): extract::Json<ScheduleInput>,
+) -> Json<Response> {
+    let Some(scheduler) = state.scheduler else {
+        return no_database();
+    };
 
-            match *status {
-                RescanStatus::Idle => *status = RescanStatus::Active,
-                RescanStatus::Active => *status = RescanStatus::Idle,
-            };
+    let mut schedule = match scheduler.db().get_schedule(id).await {
+        Ok(Some(schedule)) => schedule,
+        Ok(None) => return super::error(format!("no schedule with id {}", id)),
+        Err(e) => return super::error(format!("failed to fetch schedule: {}", e)),
+    };
 
-            super::success(format!("rescanner is now {status}"))
-        }
+    if let Err(e) = input.apply(&mut schedule) {
+        return super::error(e);
+    }
+
+    match scheduler.db().update_schedule(&schedule).await {
+        Ok(schedule) => super::schedules(format!("updated schedule {}", id), vec![schedule]),
+        Err(e) => super::error(format!("failed to update schedule: {}", e)),
+    }
+}
+
+pub async fn remove_schedule(
+    Extension(state): Extension<AppState>,
+    extract::Path(id): extract::Path<i32>,
+) -> Json<Response> {
+    let Some(scheduler) = state.scheduler else {
+        return no_database();
+    };
+
+    match scheduler.db().remove_schedule(id).await {
+        Ok(true) => super::success(format!("removed schedule {}", id)),
+        Ok(false) => super::error(format!("no schedule with id {}", id)),
+        Err(e) => super::error(format!("failed to remove schedule: {}", e)),
+    }
+}
+
+/// Runs a schedule straight away, whether it's enabled or not
+pub async fn run_schedule(
+    Extension(state): Extension<AppState>,
+    extract::Path(id): extract::Path<i32>,
+) -> Json<Response> {
+    let Some(scheduler) = state.scheduler else {
+        return no_database();
+    };
+
+    let schedule = match scheduler.db().get_schedule(id).await {
+        Ok(Some(schedule)) => schedule,
+        Ok(None) => return super::error(format!("no schedule with id {}", id)),
+        Err(e) => return super::error(format!("failed to fetch schedule: {}", e)),
+    };
+
+    match scheduler.start(schedule).await {
+        Ok(Some(summary)) => super::jobs(
+            format!("started job {} for schedule {}", summary.id, id),
+            vec![summary],
+        ),
+        Ok(None) => super::success(format!("schedule {} had nothing to scan here", id)),
+        Err(e) => super::error(format!("failed to run schedule: {}", e)),
+    }
+}
+
+fn no_database() -> Json<Response> {
+    super::error("rescan schedules need a database".to_string())
+}
+
+pub async fn list_jobs(Extension(state): Extension<AppState>) -> Json<Response> {
+    let list = state
+        .jobs
+        .lock()
+        .await
+        .all()
+        .iter()
+        .map(|job| job.summary())
+        .collect::<Vec<_>>();
+
+    super::jobs(
+        format!(
+            "{} jobs, {} connections in flight",
+            list.len(),
+            worker::THROTTLE.in_flight()
+        ),
+        list,
+    )
+}
+
+pub async fn get_job(
+    Extension(state): Extension<AppState>,
+    extract::Path(id): extract::Path<u64>,
+) -> Json<Response> {
+    let Some(job) = state.jobs.lock().await.get(id) else {
+        return super::error(format!("no job with id {}", id));
+    };
+
+    let summary = job.summary();
+    super::jobs(format!("job {} is {}", id, summary.status), vec![summary])
+}
+
+pub async fn cancel_job(
+    Extension(state): Extension<AppState>,
+    extract::Path(id): extract::Path<u64>,
+) -> Json<Response> {
+    let Some(job) = state.jobs.lock().await.get(id) else {
+        return super::error(format!("no job with id {}", id));
+    };
+
+    if job.status() != JobStatus::Running {
+        return super::error(format!("job {} is already {}", id, job.status()));
     }
+
+    job.cancel();
+    super::jobs(format!("cancelling job {}", id), vec![job.summary()])
 }
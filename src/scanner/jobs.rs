@@ -0,0 +1,241 @@
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display, Formatter},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+/// How many finished jobs to remember before the oldest ones are forgotten
+const KEEP_FINISHED: usize = 100;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum JobKind {
+    Scan,
+    Rescan,
+    /// A batch leased from europa's coordinator
+    Lease,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum JobStatus {
+    Running,
+    Finished,
+    Cancelled,
+    Failed,
+}
+
+impl Display for JobStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            JobStatus::Running => write!(f, "Running"),
+            JobStatus::Finished => write!(f, "Finished"),
+            JobStatus::Cancelled => write!(f, "Cancelled"),
+            JobStatus::Failed => write!(f, "Failed"),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct JobStats {
+    pub done: AtomicU64,
+    pub ok: AtomicU64,
+    pub failed: AtomicU64,
+    pub timeouts: AtomicU64,
+    pub excluded: AtomicU64,
+}
+
+/// A running (or finished) scan job, shared between its workers and the HTTP API.
+#[derive(Debug)]
+pub struct Job {
+    pub id: u64,
+    pub kind: JobKind,
+    pub total: u64,
+    pub stats: JobStats,
+    started: Instant,
+    cancelled: AtomicBool,
+    state: Mutex<(JobStatus, Option<Instant>)>,
+}
+
+impl Job {
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Workers stop picking up new targets, anything in flight still finishes.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn status(&self) -> JobStatus {
+        self.state.lock().unwrap().0
+    }
+
+    pub fn finish(&self, status: JobStatus) {
+        let mut state = self.state.lock().unwrap();
+        *state = (status, Some(Instant::now()));
+    }
+
+    pub fn summary(&self) -> JobSummary {
+        let (status, finished) = *self.state.lock().unwrap();
+        let elapsed = finished.unwrap_or_else(Instant::now) - self.started;
+        let done = self.stats.done.load(Ordering::Relaxed);
+
+        let eta_secs = match status {
+            JobStatus::Running if done > 0 => {
+                let per_target = elapsed.as_secs_f64() / done as f64;
+                Some(per_target * self.total.saturating_sub(done) as f64)
+            }
+            _ => None,
+        };
+
+        JobSummary {
+            id: self.id,
+            kind: self.kind,
+            status,
+            total: self.total,
+            done,
+            ok: self.stats.ok.load(Ordering::Relaxed),
+            failed: self.stats.failed.load(Ordering::Relaxed),
+            timeouts: self.stats.timeouts.load(Ordering::Relaxed),
+            excluded: self.stats.excluded.load(Ordering::Relaxed),
+            elapsed_secs: elapsed.as_secs_f64(),
+            eta_secs,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobSummary {
+    pub id: u64,
+    pub kind: JobKind,
+    pub status: JobStatus,
+    pub total: u64,
+    pub done: u64,
+    pub ok: u64,
+    pub failed: u64,
+    pub timeouts: u64,
+    pub excluded: u64,
+    pub elapsed_secs: f64,
+    pub eta_secs: Option<f64>,
+}
+
+#[derive(Debug, Default)]
+pub struct JobRegistry {
+    next_id: u64,
+    jobs: BTreeMap<u64, Arc<Job>>,
+}
+
+impl JobRegistry {
+    pub fn register(&mut self, kind: JobKind, total: u64) -> Arc<Job> {
+        self.prune();
+
+        self.next_id += 1;
+        let job = Arc::new(Job {
+            id: self.next_id,
+            kind,
+            total,
+            stats: JobStats::default(),
+            started: Instant::now(),
+            cancelled: AtomicBool::new(false),
+            state: Mutex::new((JobStatus::Running, None)),
+        });
+
+        self.jobs.insert(job.id, Arc::clone(&job));
+        job
+    }
+
+    pub fn get(&self, id: u64) -> Option<Arc<Job>> {
+        self.jobs.get(&id).cloned()
+    }
+
+    pub fn all(&self) -> Vec<Arc<Job>> {
+        self.jobs.values().cloned().collect()
+    }
+
+    fn prune(&mut self) {
+        let finished = self
+            .jobs
+            .values()
+            .filter(|job| job.status() != JobStatus::Running)
+            .map(|job| job.id)
+            .collect::<Vec<_>>();
+
+        // ids are handed out in order, so the first ones are the oldest
+        let excess = finished.len().saturating_sub(KEEP_FINISHED);
+        for id in finished.into_iter().take(excess) {
+            self.jobs.remove(&id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn prunes_finished_past_limit() {
+        let mut registry = JobRegistry::default();
+        let running = registry.register(JobKind::Scan, 1);
+        for _ in 0..KEEP_FINISHED + 4 {
+            registry
+                .register(JobKind::Rescan, 1)
+                .finish(JobStatus::Finished);
+        }
+
+        // pruned on every registration, oldest finished first, running ones stay
+        let last = registry.register(JobKind::Scan, 1);
+        let ids = registry.all().iter().map(|job| job.id).collect::<Vec<_>>();
+        assert_eq!(ids.len(), KEEP_FINISHED + 2);
+        assert_eq!(ids[0], running.id);
+        assert_eq!(ids[1], running.id + 5);
+        assert_eq!(*ids.last().unwrap(), last.id);
+        assert!(registry.get(running.id + 1).is_none());
+        assert!(registry.get(running.id + 5).is_some());
+    }
+
+    #[test]
+    fn cancel() {
+        let mut registry = JobRegistry::default();
+        let job = registry.register(JobKind::Scan, 10);
+        let other = registry.register(JobKind::Scan, 10);
+
+        job.cancel();
+        assert!(job.is_cancelled());
+        assert!(!other.is_cancelled());
+        // running until the workers wind down
+        assert_eq!(job.status(), JobStatus::Running);
+
+        job.finish(JobStatus::Cancelled);
+        let summary = registry.get(job.id).unwrap().summary();
+        assert_eq!(summary.status, JobStatus::Cancelled);
+        assert_eq!(summary.eta_secs, None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn eta() {
+        let mut registry = JobRegistry::default();
+        let job = registry.register(JobKind::Scan, 10);
+        assert_eq!(job.summary().eta_secs, None);
+
+        tokio::time::advance(Duration::from_secs(4)).await;
+        job.stats.done.store(2, Ordering::Relaxed);
+        let summary = job.summary();
+        assert_eq!(summary.elapsed_secs, 4.0);
+        assert_eq!(summary.eta_secs, Some(16.0));
+
+        // finished jobs stop the clock
+        job.stats.done.store(10, Ordering::Relaxed);
+        job.finish(JobStatus::Finished);
+        tokio::time::advance(Duration::from_secs(30)).await;
+        let summary = job.summary();
+        assert_eq!(summary.elapsed_secs, 4.0);
+        assert_eq!(summary.eta_secs, None);
+    }
+}
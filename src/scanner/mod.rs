@@ -9,10 +9,16 @@ use tokio::sync::Mutex;
 
 use crate::util::misc;
 
-use self::{http::AppState, rescan::RescanStatus};
+use self::{http::AppState, jobs::JobRegistry, rescan::Scheduler};
 
+mod coordinator;
 mod http;
+mod jobs;
 mod rescan;
+mod sink;
+mod spool;
+mod targets;
+mod throttle;
 mod worker;
 
 pub async fn start() -> anyhow::Result<()> {
@@ -24,12 +30,45 @@ pub async fn start() -> anyhow::Result<()> {
         std::process::exit(0);
     });
 
+    // only needed for rescans and the database sink, scans still work without it
+    let db = match crate::database::DbConn::new().await {
+        Ok(db) => Some(db),
+        Err(e) => {
+            warn!("Running without a database, rescans are disabled: {}", e);
+            None
+        }
+    };
+
+    let jobs = Arc::new(Mutex::new(JobRegistry::default()));
+    let scheduler = db
+        .clone()
+        .map(|db| Arc::new(Scheduler::new(db, Arc::clone(&jobs))));
+
     let state = AppState {
-        db: crate::database::DbConn::new().await?,
-        rescan_active: Arc::new(Mutex::new(RescanStatus::Idle)),
+        db,
+        scheduler: scheduler.clone(),
+        jobs,
     };
 
-    rescan::start_thread(Arc::clone(&state.rescan_active));
+    debug!(
+        "Limiting scans to {} connections/s with at most {} in flight",
+        worker::THROTTLE.rate_limit,
+        worker::THROTTLE.max_in_flight
+    );
+
+    worker::SPOOL.recover().await?;
+    debug!("Spooling scan results in {}", worker::SPOOL.dir().display());
+    tokio::spawn(async move { worker::SPOOL.run_uploader().await });
+
+    if let Some(client) = coordinator::COORDINATOR.as_ref() {
+        debug!("Leasing targets from the coordinator");
+        let jobs = Arc::clone(&state.jobs);
+        tokio::spawn(Arc::clone(client).run(jobs, state.db.clone()));
+    }
+
+    if let Some(scheduler) = scheduler {
+        tokio::spawn(scheduler.run());
+    }
 
     let port = {
         let var = std::env::var("VOYAGER_PORT")?;
@@ -1,78 +1,169 @@
 use std::{
-    fmt::{self, Display, Formatter},
-    sync::Arc,
+    collections::HashSet,
+    sync::{Arc, Mutex as StdMutex},
     time::{Duration, Instant},
 };
 
-use log::debug;
-use serde::{Deserialize, Serialize};
+use anyhow::anyhow;
+use chrono::{NaiveDateTime, Timelike};
+use log::{debug, error, warn};
 use tokio::sync::Mutex;
 
 use crate::{
     database::DbConn,
-    scanner::worker::ScanJob,
-    util::misc::{wh_send, WHLog},
+    scanner::{
+        coordinator::COORDINATOR,
+        jobs::{Job, JobKind, JobRegistry, JobSummary},
+        targets::TargetSet,
+        worker::{self, ScanJob},
+    },
+    util::{
+        misc::{wh_send, WHLog},
+        types::RescanSchedule,
+    },
+    web::coordinator::ScanOptions,
 };
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
-pub enum RescanStatus {
-    Active,
-    Idle,
+/// Runs the rescan schedules stored in the database, see `util::cron` for the format.
+#[derive(Debug)]
+pub struct Scheduler {
+    db: DbConn,
+    jobs: Arc<Mutex<JobRegistry>>,
+    /// Schedules with a job in progress, a schedule never overlaps with itself
+    running: StdMutex<HashSet<i32>>,
 }
 
-impl Display for RescanStatus {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        match self {
-            RescanStatus::Active => write!(f, "Active"),
-            RescanStatus::Idle => write!(f, "Idle"),
+impl Scheduler {
+    pub fn new(db: DbConn, jobs: Arc<Mutex<JobRegistry>>) -> Self {
+        Self {
+            db,
+            jobs,
+            running: StdMutex::new(HashSet::new()),
         }
     }
-}
 
-pub fn start_thread(status: Arc<Mutex<RescanStatus>>) {
-    debug!("Starting rescan thread");
-    tokio::spawn(async move {
+    pub fn db(&self) -> &DbConn {
+        &self.db
+    }
+
+    /// Checks every schedule at the start of each minute, never returns.
+    pub async fn run(self: Arc<Self>) {
+        debug!("Starting rescan scheduler");
+
         loop {
-            tokio::time::sleep(Duration::from_secs(60 * 60 * 5)).await;
-            let status = status.lock().await;
-            if status.eq(&RescanStatus::Idle) {
-                continue;
+            let now = chrono::Utc::now().naive_utc();
+            let next_minute = 60 - now.second() as u64;
+            tokio::time::sleep(Duration::from_secs(next_minute)).await;
+
+            let now = chrono::Utc::now().naive_utc();
+            if let Err(e) = self.tick(now).await {
+                error!("Failed to check rescan schedules: {}", e);
             }
+        }
+    }
 
-            wh_send(WHLog::Voyager, "Starting reping", Some("Voyager")).await;
+    async fn tick(self: &Arc<Self>, now: NaiveDateTime) -> anyhow::Result<()> {
+        let due = self
+            .db
+            .get_schedules()
+            .await?
+            .into_iter()
+            .filter(|schedule| schedule.enabled && schedule.cron.matches(&now));
 
-            if let Err(e) = start_job().await {
-                wh_send(
-                    WHLog::Error,
-                    &format!("Error starting job: {}", e),
-                    Some("Voyager"),
-                )
-                .await;
-            };
+        for schedule in due {
+            let name = schedule.name.clone();
+            if let Err(e) = self.start(schedule).await {
+                warn!("Not running rescan schedule {}: {}", name, e);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Picks the schedule's targets and starts scanning them in the background.
+    /// Returns `None` if nothing was selected or the targets went to the coordinator.
+    pub async fn start(
+        self: &Arc<Self>,
+        schedule: RescanSchedule,
+    ) -> anyhow::Result<Option<JobSummary>> {
+        if !self.running.lock().unwrap().insert(schedule.id) {
+            return Err(anyhow!("{} is still running", schedule.name));
         }
-    });
-}
 
-async fn start_job() -> anyhow::Result<()> {
-    let list = db_list().await?;
-    let scan = ScanJob {
-        ips: list,
-        timeout: Duration::from_secs(5),
-        workers: 10,
-    };
+        let res = self.prepare(&schedule).await;
+        let (job, handle) = match res {
+            Ok(Some(job)) => job,
+            other => {
+                self.running.lock().unwrap().remove(&schedule.id);
+                return other.map(|_| None);
+            }
+        };
 
-    let now = Instant::now();
-    super::worker::run_blocking(scan).await;
+        let summary = handle.summary();
+        let scheduler = Arc::clone(self);
+        tokio::spawn(async move {
+            let msg = format!("Starting reping {}", schedule.name);
+            wh_send(WHLog::Voyager, &msg, Some("Voyager")).await;
 
-    let msg = format!("Finished reping in {}s", now.elapsed().as_secs_f32());
-    wh_send(WHLog::Voyager, &msg, Some("Voyager")).await;
+            let now = Instant::now();
+            worker::run_blocking(job, handle).await;
+            scheduler.running.lock().unwrap().remove(&schedule.id);
 
-    Ok(())
-}
+            let msg = format!(
+                "Finished reping {} in {}s",
+                schedule.name,
+                now.elapsed().as_secs_f32()
+            );
+            wh_send(WHLog::Voyager, &msg, Some("Voyager")).await;
+        });
 
-async fn db_list() -> anyhow::Result<Vec<String>> {
-    let conn = DbConn::new().await?;
-    let list = conn.get_all_ips(true).await?;
+        Ok(Some(summary))
+    }
+
+    async fn prepare(
+        &self,
+        schedule: &RescanSchedule,
+    ) -> anyhow::Result<Option<(ScanJob, Arc<Job>)>> {
+        let list = self.db.select_targets(&schedule.selector).await?;
+        self.db.mark_schedule_run(schedule.id).await?;
+        debug!(
+            "Rescan schedule {} selected {} targets",
+            schedule.name,
+            list.len()
+        );
+
+        if list.is_empty() {
+            return Ok(None);
+        }
 
-    Ok(list)
+        // the coordinator hands the list out to every worker instead of each instance
+        // rescanning all of it
+        if let Some(client) = COORDINATOR.as_ref() {
+            let options = ScanOptions {
+                timeout: Some(schedule.timeout),
+                report_failures: true,
+                ..Default::default()
+            };
+            let msg = client.enqueue(list, options).await?;
+            wh_send(WHLog::Voyager, &msg, Some("Voyager")).await;
+            return Ok(None);
+        }
+
+        let targets = TargetSet::from_addresses(list);
+        let timeout = Some(schedule.timeout);
+        let workers = Some(schedule.workers as usize);
+        let Some(mut job) = ScanJob::new(targets, timeout, workers) else {
+            return Ok(None);
+        };
+        job.report_failures = true;
+        job.db = Some(self.db.clone());
+
+        let handle = self
+            .jobs
+            .lock()
+            .await
+            .register(JobKind::Rescan, job.targets.len());
+
+        Ok(Some((job, handle)))
+    }
 }
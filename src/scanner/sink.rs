@@ -0,0 +1,223 @@
+use std::{path::PathBuf, sync::Arc};
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs::{File, OpenOptions},
+    io::AsyncWriteExt,
+    sync::Mutex,
+};
+
+use crate::{
+    database::DbConn,
+    util::types::{Entry, ScanFailure},
+};
+
+use super::{spool::Spool, worker::SPOOL};
+
+/// Directory file sinks write into when `VOYAGER_RESULTS_DIR` isn't set
+pub const DEFAULT_RESULTS_DIR: &str = "results";
+
+/// Somewhere finished scans end up.
+#[async_trait]
+pub trait ResultSink: Send + Sync {
+    async fn push(&self, entry: &Entry) -> anyhow::Result<()>;
+
+    /// Only sent for jobs that rescan known addresses, ignored unless the sink
+    /// keeps per-address history
+    async fn push_failure(&self, _failure: &ScanFailure) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Called as each worker finishes, anything buffered should be written out
+    async fn flush(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// Which sink a job writes to, picked per request
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum SinkConfig {
+    /// Upload to europa through the on-disk spool
+    #[default]
+    Europa,
+    /// Write straight to the database with `DbConn::add_server`
+    Database,
+    /// Append one JSON entry per line to `file` in the results directory
+    Jsonl { file: String },
+    /// Append one row per server to `file` in the results directory
+    Csv { file: String },
+    /// Print one JSON entry per line
+    Stdout,
+}
+
+impl SinkConfig {
+    /// `db` is the instance's connection, the database sink shares it rather than
+    /// opening a pool per job
+    pub async fn build(&self, db: Option<&DbConn>) -> anyhow::Result<Arc<dyn ResultSink>> {
+        let sink: Arc<dyn ResultSink> = match self {
+            SinkConfig::Europa => Arc::new(EuropaSink::new()),
+            SinkConfig::Database => {
+                let conn = db.ok_or_else(|| anyhow!("voyager is running without a database"))?;
+                Arc::new(DatabaseSink { conn: conn.clone() })
+            }
+            SinkConfig::Jsonl { file } => Arc::new(FileSink::open(file, FileFormat::Jsonl).await?),
+            SinkConfig::Csv { file } => Arc::new(FileSink::open(file, FileFormat::Csv).await?),
+            SinkConfig::Stdout => Arc::new(StdoutSink),
+        };
+
+        Ok(sink)
+    }
+}
+
+pub struct EuropaSink {
+    spool: &'static Spool,
+}
+
+impl EuropaSink {
+    pub fn new() -> Self {
+        Self { spool: &SPOOL }
+    }
+}
+
+impl Default for EuropaSink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl ResultSink for EuropaSink {
+    async fn push(&self, entry: &Entry) -> anyhow::Result<()> {
+        self.spool.push(entry).await
+    }
+
+    async fn push_failure(&self, failure: &ScanFailure) -> anyhow::Result<()> {
+        self.spool.push(failure).await
+    }
+
+    async fn flush(&self) -> anyhow::Result<()> {
+        self.spool.flush().await
+    }
+}
+
+pub struct DatabaseSink {
+    conn: DbConn,
+}
+
+#[async_trait]
+impl ResultSink for DatabaseSink {
+    async fn push(&self, entry: &Entry) -> anyhow::Result<()> {
+        let txn = self.conn.add_server(entry.clone()).await?;
+        txn.commit().await?;
+
+        Ok(())
+    }
+
+    async fn push_failure(&self, failure: &ScanFailure) -> anyhow::Result<()> {
+        self.conn.record_failure(failure).await
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FileFormat {
+    Jsonl,
+    Csv,
+}
+
+const CSV_HEADER: &str = "ip,port,hostname,edition,version,protocol,online_players,max_players,auth,ping,motd,scanned_at\n";
+
+pub struct FileSink {
+    file: Mutex<File>,
+    format: FileFormat,
+}
+
+impl FileSink {
+    /// `name` has to be a plain file name, the API can't write outside the results directory
+    async fn open(name: &str, format: FileFormat) -> anyhow::Result<Self> {
+        let valid =
+            !name.is_empty() && !name.starts_with('.') && !name.contains(|c| c == '/' || c == '\\');
+        if !valid {
+            return Err(anyhow!("invalid result file name: {}", name));
+        }
+
+        let dir = std::env::var("VOYAGER_RESULTS_DIR").unwrap_or(DEFAULT_RESULTS_DIR.to_string());
+        tokio::fs::create_dir_all(&dir).await?;
+
+        let path = PathBuf::from(dir).join(name);
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await?;
+
+        if format == FileFormat::Csv && file.metadata().await?.len() == 0 {
+            file.write_all(CSV_HEADER.as_bytes()).await?;
+        }
+
+        Ok(Self {
+            file: Mutex::new(file),
+            format,
+        })
+    }
+}
+
+#[async_trait]
+impl ResultSink for FileSink {
+    async fn push(&self, entry: &Entry) -> anyhow::Result<()> {
+        let line = match self.format {
+            FileFormat::Jsonl => serde_json::to_string(entry)? + "\n",
+            FileFormat::Csv => csv_row(entry),
+        };
+
+        self.file.lock().await.write_all(line.as_bytes()).await?;
+        Ok(())
+    }
+
+    async fn flush(&self) -> anyhow::Result<()> {
+        self.file.lock().await.flush().await?;
+        Ok(())
+    }
+}
+
+fn csv_row(entry: &Entry) -> String {
+    let server = &entry.server;
+    let fields = [
+        server.ip.clone(),
+        server.port.to_string(),
+        server.hostname.clone().unwrap_or_default(),
+        server.edition.to_string_but_consistent(),
+        server.version.clone(),
+        server.protocol.to_string(),
+        server.online_players.to_string(),
+        server.max_players.to_string(),
+        server.auth.to_string_but_consistent(),
+        server.ping.to_string_but_consistent(),
+        entry.description.text.clone(),
+        server.updated_at.to_string(),
+    ];
+
+    let mut row = fields.map(|field| csv_escape(&field)).join(",");
+    row.push('\n');
+    row
+}
+
+fn csv_escape(field: &str) -> String {
+    if field.contains(|c| matches!(c, ',' | '"' | '\n' | '\r')) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+pub struct StdoutSink;
+
+#[async_trait]
+impl ResultSink for StdoutSink {
+    async fn push(&self, entry: &Entry) -> anyhow::Result<()> {
+        println!("{}", serde_json::to_string(entry)?);
+        Ok(())
+    }
+}
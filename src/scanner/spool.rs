@@ -0,0 +1,546 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::anyhow;
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs::{self, File, OpenOptions},
+    io::AsyncWriteExt,
+    sync::{Mutex, Notify},
+};
+
+use crate::{
+    util::types::{Entry, RejectedRecord, ScanFailure},
+    web::server::{Response, WebRequest},
+};
+
+/// Spool directory when `VOYAGER_SPOOL_DIR` isn't set
+pub const DEFAULT_SPOOL_DIR: &str = "spool";
+/// Results per upload, a partial batch is sent when a slice finishes
+const BATCH_SIZE: usize = 10;
+/// The batch being written to, anything else in the directory is waiting for upload
+const CURRENT: &str = "current.jsonl";
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+/// How often the uploader looks for batches when nobody wakes it up
+const IDLE_POLL: Duration = Duration::from_secs(30);
+/// Records europa couldn't store, kept to be looked at rather than sent again
+const REJECTED: &str = "rejected.jsonl";
+
+/// Scan results on their way to europa, kept on disk until europa has committed them.
+///
+/// Results are appended to `current.jsonl`, which is sealed into a numbered batch
+/// file once it's full or flushed. The uploader sends sealed batches in order and
+/// only deletes one once europa acknowledges it, retrying with backoff otherwise.
+/// Records europa acknowledges but can't store are appended to `rejected.jsonl`.
+#[derive(Debug)]
+pub struct Spool {
+    dir: PathBuf,
+    /// Where europa is, e.g. `http://127.0.0.1:7000`
+    base: String,
+    state: Mutex<SpoolState>,
+    sealed: Notify,
+}
+
+/// One line of a batch, untagged so batches spooled before failures were
+/// recorded still read back
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+enum SpoolRecord {
+    Entry(Box<Entry>),
+    Failure(ScanFailure),
+}
+
+#[derive(Debug, Default)]
+struct SpoolState {
+    current: Option<File>,
+    count: usize,
+    next_batch: u64,
+}
+
+impl Spool {
+    pub fn new(dir: impl Into<PathBuf>, base: impl Into<String>) -> Self {
+        Self {
+            dir: dir.into(),
+            base: base.into(),
+            state: Mutex::new(SpoolState::default()),
+            sealed: Notify::new(),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let dir = std::env::var("VOYAGER_SPOOL_DIR").unwrap_or(DEFAULT_SPOOL_DIR.to_string());
+        // both are checked at startup
+        let url = std::env::var("WEBSERVER_URL").unwrap_or_default();
+        let port = std::env::var("WEBSERVER_PORT").unwrap_or_default();
+
+        Self::new(dir, format!("http://{}:{}", url, port))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Creates the directory and seals whatever a previous run left half written.
+    pub async fn recover(&self) -> anyhow::Result<()> {
+        fs::create_dir_all(&self.dir).await?;
+
+        let mut state = self.state.lock().await;
+        state.next_batch = self.batches().await?.last().map_or(0, |(id, _)| id + 1);
+
+        if fs::try_exists(self.dir.join(CURRENT)).await? {
+            warn!("Recovering unsent scan results from a previous run");
+            self.seal(&mut state).await?;
+        }
+
+        Ok(())
+    }
+
+    /// `record` is an `Entry` or a `ScanFailure`, see `SpoolRecord`
+    pub async fn push(&self, record: &(impl Serialize + Sync)) -> anyhow::Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+
+        let mut state = self.state.lock().await;
+        if state.current.is_none() {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(self.dir.join(CURRENT))
+                .await?;
+            state.current = Some(file);
+        }
+
+        let file = state.current.as_mut().expect("opened above");
+        file.write_all(&line).await?;
+        file.flush().await?;
+        state.count += 1;
+
+        if state.count >= BATCH_SIZE {
+            self.seal(&mut state).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Seals the current batch even if it isn't full, so it goes out straight away.
+    pub async fn flush(&self) -> anyhow::Result<()> {
+        let mut state = self.state.lock().await;
+        if state.count > 0 {
+            self.seal(&mut state).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn seal(&self, state: &mut SpoolState) -> anyhow::Result<()> {
+        if let Some(file) = state.current.take() {
+            file.sync_all().await?;
+        }
+
+        let path = self.dir.join(format!("{:020}.jsonl", state.next_batch));
+        fs::rename(self.dir.join(CURRENT), path).await?;
+
+        state.next_batch += 1;
+        state.count = 0;
+        self.sealed.notify_one();
+
+        Ok(())
+    }
+
+    async fn quarantine(&self, lines: &str) -> anyhow::Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.dir.join(REJECTED))
+            .await?;
+        file.write_all(lines.as_bytes()).await?;
+        file.sync_all().await?;
+
+        Ok(())
+    }
+
+    /// Sealed batches, oldest first
+    async fn batches(&self) -> anyhow::Result<Vec<(u64, PathBuf)>> {
+        let mut batches = Vec::new();
+        let mut dir = fs::read_dir(&self.dir).await?;
+
+        while let Some(file) = dir.next_entry().await? {
+            let path = file.path();
+            let id = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_suffix(".jsonl"))
+                .and_then(|id| id.parse::<u64>().ok());
+
+            if let Some(id) = id {
+                batches.push((id, path));
+            }
+        }
+
+        batches.sort();
+        Ok(batches)
+    }
+
+    /// Uploads sealed batches forever, never returns.
+    pub async fn run_uploader(&self) {
+        let mut backoff = MIN_BACKOFF;
+
+        loop {
+            let batches = match self.batches().await {
+                Ok(batches) => batches,
+                Err(e) => {
+                    error!("Failed to list spooled batches: {}", e);
+                    Vec::new()
+                }
+            };
+
+            if batches.is_empty() {
+                let _ = tokio::time::timeout(IDLE_POLL, self.sealed.notified()).await;
+                continue;
+            }
+
+            for (id, path) in batches {
+                if let Err(e) = self.send(id, &path).await {
+                    warn!(
+                        "Failed to upload batch {}, retrying in {:?}: {}",
+                        id, backoff, e
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                    break;
+                }
+
+                backoff = MIN_BACKOFF;
+            }
+        }
+    }
+
+    async fn send(&self, id: u64, path: &Path) -> anyhow::Result<()> {
+        let content = fs::read_to_string(path).await?;
+        let mut entries = Vec::new();
+        let mut failures = Vec::new();
+        // the lines behind each, to quarantine whatever europa rejects
+        let mut entry_lines = Vec::new();
+        let mut failure_lines = Vec::new();
+
+        for line in content.lines().filter(|line| !line.is_empty()) {
+            match serde_json::from_str::<SpoolRecord>(line) {
+                Ok(SpoolRecord::Entry(entry)) => {
+                    entries.push(*entry);
+                    entry_lines.push(line);
+                }
+                Ok(SpoolRecord::Failure(failure)) => {
+                    failures.push(failure);
+                    failure_lines.push(line);
+                }
+                // most likely the tail of a write that was cut off, nothing to retry
+                Err(e) => warn!("Dropping unreadable line in batch {}: {}", id, e),
+            }
+        }
+
+        if !entries.is_empty() || !failures.is_empty() {
+            let rejected = upload_servers(&self.base, id, entries, failures).await?;
+            if !rejected.is_empty() {
+                let mut quarantine = String::new();
+                for record in rejected {
+                    let lines = if record.failure {
+                        &failure_lines
+                    } else {
+                        &entry_lines
+                    };
+                    let Some(line) = lines.get(record.index) else {
+                        continue;
+                    };
+
+                    warn!(
+                        "Europa rejected a record in batch {}: {}",
+                        id, record.reason
+                    );
+                    quarantine.push_str(line);
+                    quarantine.push('\n');
+                }
+
+                // the batch is committed either way, sending it again won't help
+                if let Err(e) = self.quarantine(&quarantine).await {
+                    error!("Failed to keep rejected records of batch {}: {}", id, e);
+                }
+            }
+        }
+
+        fs::remove_file(path).await?;
+        debug!("Batch {} acknowledged", id);
+
+        Ok(())
+    }
+}
+
+/// Returns whatever europa couldn't store, everything else is committed
+async fn upload_servers(
+    base: &str,
+    batch: u64,
+    servers: Vec<Entry>,
+    failures: Vec<ScanFailure>,
+) -> anyhow::Result<Vec<RejectedRecord>> {
+    let client = reqwest::Client::new();
+    let len = servers.len();
+
+    let input = WebRequest {
+        servers: Some(servers),
+        failures: Some(failures),
+        batch: Some(batch),
+        ..Default::default()
+    };
+
+    let res = client
+        .post(format!("{}/upload", base))
+        .json(&input)
+        .send()
+        .await?
+        .json::<Response>()
+        .await?;
+
+    // europa only echoes the batch back once everything in it is committed
+    let (acked, rejected) = match res.data {
+        Some(data) => (data.batch, data.rejected.unwrap_or_default()),
+        None => (None, Vec::new()),
+    };
+    match (res.status, acked) {
+        (200, Some(acked)) if acked == batch => {
+            info!("Uploaded {} servers", len);
+            Ok(rejected)
+        }
+        (status, _) => Err(anyhow!("europa answered {}: {}", status, res.message)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex as StdMutex};
+
+    use axum::{routing::post, Extension, Json, Router};
+    use azalea_protocol::ServerAddress;
+
+    use crate::{
+        util::{
+            legacy::LegacyResponse,
+            types::{Edition, FailureKind, OntosAddress},
+        },
+        web::server::ResponseData,
+    };
+
+    use super::*;
+
+    /// A directory of its own under the system temp dir, gone once dropped
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path =
+                std::env::temp_dir().join(format!("voyager-spool-{}-{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&path);
+            std::fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn entry(port: u16) -> Entry {
+        let packet = LegacyResponse {
+            protocol: 127,
+            version: "1.8.9".to_string(),
+            motd: "A server".to_string(),
+            online_players: 1,
+            max_players: 20,
+        };
+        let addr = ServerAddress {
+            host: "10.0.0.1".to_string(),
+            port,
+        };
+
+        Entry::from_legacy(packet, addr)
+    }
+
+    fn failure(port: u16) -> ScanFailure {
+        let address = OntosAddress {
+            host: format!("10.0.0.1:{}", port),
+            edition: Edition::Java,
+            hostname: None,
+            srv: false,
+        };
+
+        ScanFailure::new(&address, FailureKind::Timeout)
+    }
+
+    fn files(dir: &TempDir) -> Vec<String> {
+        let mut files = std::fs::read_dir(&dir.0)
+            .unwrap()
+            .map(|file| file.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        files.sort();
+        files
+    }
+
+    fn lines(dir: &TempDir, file: &str) -> usize {
+        std::fs::read_to_string(dir.0.join(file))
+            .unwrap()
+            .lines()
+            .count()
+    }
+
+    #[tokio::test]
+    async fn seals_current() {
+        let dir = TempDir::new("seal");
+        let spool = Spool::new(&dir.0, "http://127.0.0.1:1");
+        spool.recover().await.unwrap();
+
+        spool.push(&entry(1)).await.unwrap();
+        spool.push(&failure(2)).await.unwrap();
+        assert_eq!(files(&dir), [CURRENT]);
+        assert_eq!(lines(&dir, CURRENT), 2);
+
+        spool.flush().await.unwrap();
+        assert_eq!(files(&dir), ["00000000000000000000.jsonl"]);
+        // nothing to seal
+        spool.flush().await.unwrap();
+
+        for port in 0..BATCH_SIZE as u16 {
+            spool.push(&entry(port)).await.unwrap();
+        }
+        assert_eq!(
+            files(&dir),
+            ["00000000000000000000.jsonl", "00000000000000000001.jsonl"]
+        );
+        assert_eq!(lines(&dir, "00000000000000000001.jsonl"), BATCH_SIZE);
+    }
+
+    #[tokio::test]
+    async fn recovers_after_crash() {
+        let dir = TempDir::new("recover");
+        let line = serde_json::to_string(&entry(1)).unwrap() + "\n";
+        std::fs::write(dir.0.join("00000000000000000004.jsonl"), &line).unwrap();
+        std::fs::write(dir.0.join(CURRENT), &line).unwrap();
+
+        let spool = Spool::new(&dir.0, "http://127.0.0.1:1");
+        spool.recover().await.unwrap();
+        assert_eq!(
+            files(&dir),
+            ["00000000000000000004.jsonl", "00000000000000000005.jsonl"]
+        );
+
+        // numbering carries on after the recovered batch
+        spool.push(&entry(2)).await.unwrap();
+        spool.flush().await.unwrap();
+        assert_eq!(files(&dir).last().unwrap(), "00000000000000000006.jsonl");
+    }
+
+    #[test]
+    fn reads_records_from_before_failures() {
+        // an entry on its own line is how every batch looked before failures
+        let line = serde_json::to_string(&entry(25565)).unwrap();
+        match serde_json::from_str::<SpoolRecord>(&line).unwrap() {
+            SpoolRecord::Entry(entry) => assert_eq!(entry.server.port, 25565),
+            other => panic!("expected an entry, got {:?}", other),
+        }
+
+        let line = serde_json::to_string(&failure(25566)).unwrap();
+        match serde_json::from_str::<SpoolRecord>(&line).unwrap() {
+            SpoolRecord::Failure(failure) => assert_eq!(failure.address.host, "10.0.0.1:25566"),
+            other => panic!("expected a failure, got {:?}", other),
+        }
+    }
+
+    /// Batch id, servers and failures of every upload
+    type Seen = Arc<StdMutex<Vec<(u64, usize, usize)>>>;
+
+    /// Answers like europa, except the first upload fails and the first server of
+    /// batch 1 is rejected. Records every batch it was sent.
+    async fn upload(
+        Extension(seen): Extension<Seen>,
+        Json(input): Json<WebRequest>,
+    ) -> Json<Response> {
+        let batch = input.batch.unwrap();
+        let servers = input.servers.unwrap_or_default().len();
+        let failures = input.failures.unwrap_or_default().len();
+
+        let mut seen = seen.lock().unwrap();
+        let first = seen.is_empty();
+        seen.push((batch, servers, failures));
+        if first {
+            return Json(Response {
+                status: 500,
+                message: "Internal server error".to_string(),
+                data: None,
+            });
+        }
+
+        let rejected = (batch == 1).then(|| {
+            vec![RejectedRecord {
+                index: 0,
+                failure: false,
+                reason: "bad".to_string(),
+            }]
+        });
+        Json(Response {
+            status: 200,
+            message: "success".to_string(),
+            data: Some(ResponseData {
+                batch: Some(batch),
+                rejected,
+                ..Default::default()
+            }),
+        })
+    }
+
+    #[tokio::test]
+    async fn uploads_in_order_until_acked() {
+        let seen = Seen::default();
+        let app = Router::new()
+            .route("/upload", post(upload))
+            .layer(Extension(Arc::clone(&seen)));
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service());
+        tokio::spawn(server);
+
+        let dir = TempDir::new("upload");
+        let spool = Arc::new(Spool::new(&dir.0, format!("http://{}", addr)));
+        spool.recover().await.unwrap();
+        for batch in 0..3 {
+            spool.push(&entry(batch)).await.unwrap();
+            spool.push(&failure(batch)).await.unwrap();
+            spool.flush().await.unwrap();
+        }
+
+        let uploader = Arc::clone(&spool);
+        let task = tokio::spawn(async move { uploader.run_uploader().await });
+        let start = std::time::Instant::now();
+        while files(&dir).iter().any(|file| file != REJECTED) {
+            assert!(start.elapsed() < Duration::from_secs(10), "still spooled");
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        task.abort();
+
+        // batch 0 stayed until it went through on the retry, then the rest in order
+        let seen = seen.lock().unwrap().clone();
+        assert_eq!(seen, [(0, 1, 1), (0, 1, 1), (1, 1, 1), (2, 1, 1)]);
+
+        let rejected = std::fs::read_to_string(dir.0.join(REJECTED)).unwrap();
+        let rejected = rejected.lines().collect::<Vec<_>>();
+        assert_eq!(rejected.len(), 1);
+        match serde_json::from_str::<SpoolRecord>(rejected[0]).unwrap() {
+            SpoolRecord::Entry(entry) => assert_eq!(entry.server.port, 1),
+            other => panic!("expected an entry, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,390 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    net::Ipv4Addr,
+};
+
+use anyhow::anyhow;
+use ipnet::{Ipv4AddrRange, Ipv4Net, Ipv4Subnets};
+use iprange::IpRange;
+use serde::{Deserialize, Serialize};
+
+use crate::util::types::{Edition, OntosAddress};
+
+/// Everything a scan job should ping, kept in compact form until the workers ask for it.
+///
+/// Literal `host:port` strings are kept as-is, while CIDR blocks, dash ranges and bare
+/// IPv4 addresses are merged into a single `IpRange` and crossed with `ports` on demand.
+/// Every target is tried once per edition in `editions`.
+#[derive(Debug, Clone)]
+pub struct TargetSet {
+    hosts: Vec<OntosAddress>,
+    networks: IpRange<Ipv4Net>,
+    /// `None` means each edition's default port
+    ports: Option<Vec<u16>>,
+    editions: Vec<Edition>,
+}
+
+impl TargetSet {
+    /// Parses a list of target specs, each one of:
+    /// - `host:port` (pinged as-is)
+    /// - `host` or `1.2.3.4` (crossed with `ports`)
+    /// - `10.0.0.0/16` (crossed with `ports`)
+    /// - `10.0.0.1-10.0.3.255` (crossed with `ports`)
+    ///
+    /// `ports` is a comma separated list of ports and port ranges, e.g. `25565,25570-25580`.
+    /// Without it every edition is scanned on its default port.
+    ///
+    /// Every entry is checked, so the error lists all of the bad ones rather than just the first.
+    pub fn parse(
+        specs: &[String],
+        ports: Option<&str>,
+        editions: &[Edition],
+    ) -> Result<Self, Vec<InvalidTarget>> {
+        let ports = match ports {
+            Some(ports) => Some(parse_ports(ports).map_err(|e| {
+                vec![InvalidTarget {
+                    target: ports.to_string(),
+                    reason: e.to_string(),
+                }]
+            })?),
+            None => None,
+        };
+
+        let mut editions = editions.iter().fold(Vec::new(), |mut list, edition| {
+            if !list.contains(edition) {
+                list.push(*edition);
+            }
+            list
+        });
+        if editions.is_empty() {
+            editions.push(Edition::Java);
+        }
+
+        let mut set = Self {
+            hosts: Vec::new(),
+            networks: IpRange::new(),
+            ports,
+            editions,
+        };
+
+        let errors = specs
+            .iter()
+            .filter_map(|spec| {
+                set.add(spec).err().map(|e| InvalidTarget {
+                    target: spec.clone(),
+                    reason: e.to_string(),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        set.networks.simplify();
+        Ok(set)
+    }
+
+    /// Wraps a list of already known addresses, e.g. from the `ips` table.
+    pub fn from_addresses(hosts: Vec<OntosAddress>) -> Self {
+        Self {
+            hosts,
+            networks: IpRange::new(),
+            ports: None,
+            editions: Vec::new(),
+        }
+    }
+
+    fn ports_for(&self, edition: Edition) -> Vec<u16> {
+        match &self.ports {
+            Some(ports) => ports.clone(),
+            None => vec![edition.default_port()],
+        }
+    }
+
+    fn add(&mut self, spec: &str) -> anyhow::Result<()> {
+        let spec = spec.trim();
+        if spec.is_empty() {
+            return Err(anyhow!("empty target"));
+        }
+
+        if spec.contains('/') {
+            let net = spec
+                .parse::<Ipv4Net>()
+                .map_err(|_| anyhow!("invalid CIDR block: {}", spec))?;
+            self.networks.add(net.trunc());
+            return Ok(());
+        }
+
+        // hostnames can contain dashes too, so only treat this as a range if both ends are IPs
+        if let Some((start, end)) = spec.split_once('-') {
+            if let (Ok(start), Ok(end)) = (
+                start.trim().parse::<Ipv4Addr>(),
+                end.trim().parse::<Ipv4Addr>(),
+            ) {
+                if start > end {
+                    return Err(anyhow!("address range is backwards: {}", spec));
+                }
+
+                for net in Ipv4Subnets::new(start, end, 0) {
+                    self.networks.add(net);
+                }
+                return Ok(());
+            }
+        }
+
+        if let Ok(addr) = spec.parse::<Ipv4Addr>() {
+            self.networks.add(Ipv4Net::from(addr));
+            return Ok(());
+        }
+
+        let port_given = match spec.split_once(':') {
+            Some((host, port)) => {
+                let Ok(port) = port.parse::<u16>() else {
+                    return Err(anyhow!("invalid target: {}", spec));
+                };
+
+                if host.is_empty() {
+                    return Err(anyhow!("invalid target: {}", spec));
+                }
+                Some((host, port))
+            }
+            None => None,
+        };
+
+        for &edition in &self.editions {
+            let hosts = match port_given {
+                Some((host, port)) => vec![(host, port)],
+                None => self
+                    .ports_for(edition)
+                    .into_iter()
+                    .map(|port| (spec, port))
+                    .collect(),
+            };
+
+            for (host, port) in hosts {
+                self.hosts.push(OntosAddress {
+                    host: format!("{}:{}", host, port),
+                    edition,
+                    hostname: None,
+                    // a port that was asked for wins over whatever SRV says
+                    srv: port_given.is_none() && self.ports.is_none(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn ports_per_addr(&self) -> u64 {
+        self.editions
+            .iter()
+            .map(|&edition| self.ports_for(edition).len() as u64)
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hosts.is_empty() && (self.networks.is_empty() || self.ports_per_addr() == 0)
+    }
+
+    /// Total number of `host:port` and edition pairs this set expands to.
+    pub fn len(&self) -> u64 {
+        let addrs: u64 = self
+            .networks
+            .iter()
+            .map(|net| 1u64 << (32 - net.prefix_len()))
+            .sum();
+
+        self.hosts.len() as u64 + addrs * self.ports_per_addr()
+    }
+
+    /// Lazily expands the set into addresses.
+    pub fn expand(self) -> TargetIter {
+        let networks = self.networks.iter().collect::<Vec<_>>();
+        let pairs = self
+            .editions
+            .iter()
+            .flat_map(|&edition| {
+                self.ports_for(edition)
+                    .into_iter()
+                    .map(move |port| (edition, port))
+            })
+            .collect::<Vec<_>>();
+
+        let expanded = networks.into_iter().flat_map(move |net| {
+            let pairs = pairs.clone();
+            Ipv4AddrRange::new(net.network(), net.broadcast()).flat_map(move |addr| {
+                pairs
+                    .clone()
+                    .into_iter()
+                    .map(move |(edition, port)| OntosAddress {
+                        host: format!("{}:{}", addr, port),
+                        edition,
+                        hostname: None,
+                        srv: false,
+                    })
+            })
+        });
+
+        Box::new(self.hosts.into_iter().chain(expanded))
+    }
+}
+
+pub type TargetIter = Box<dyn Iterator<Item = OntosAddress> + Send>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InvalidTarget {
+    pub target: String,
+    pub reason: String,
+}
+
+impl Display for InvalidTarget {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.target, self.reason)
+    }
+}
+
+pub fn parse_ports(input: &str) -> anyhow::Result<Vec<u16>> {
+    let mut ports = Vec::new();
+
+    for part in input.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        match part.split_once('-') {
+            Some((start, end)) => {
+                let (Ok(start), Ok(end)) = (start.trim().parse::<u16>(), end.trim().parse::<u16>())
+                else {
+                    return Err(anyhow!("invalid port range: {}", part));
+                };
+
+                if start > end {
+                    return Err(anyhow!("port range is backwards: {}", part));
+                }
+
+                ports.extend(start..=end);
+            }
+            None => {
+                let port = part
+                    .parse::<u16>()
+                    .map_err(|_| anyhow!("invalid port: {}", part))?;
+                ports.push(port);
+            }
+        }
+    }
+
+    if ports.is_empty() {
+        return Err(anyhow!("no ports provided"));
+    }
+
+    ports.sort_unstable();
+    ports.dedup();
+
+    Ok(ports)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(specs: &[&str], ports: Option<&str>, editions: &[Edition]) -> Vec<OntosAddress> {
+        let specs = specs.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        TargetSet::parse(&specs, ports, editions)
+            .unwrap()
+            .expand()
+            .collect()
+    }
+
+    #[test]
+    fn srv_only_without_a_port() {
+        let hosts = expand(&["play.example.com"], None, &[]);
+        assert_eq!(hosts.len(), 1);
+        assert_eq!(hosts[0].host, "play.example.com:25565");
+        assert!(hosts[0].srv);
+
+        let hosts = expand(&["play.example.com:25570"], None, &[]);
+        assert!(!hosts[0].srv);
+
+        let hosts = expand(&["play.example.com"], Some("25565-25566"), &[]);
+        assert_eq!(hosts.len(), 2);
+        assert!(hosts.iter().all(|host| !host.srv));
+
+        let hosts = expand(&["10.0.0.1"], None, &[]);
+        assert!(!hosts[0].srv);
+    }
+
+    fn parse(specs: &[&str], ports: Option<&str>, editions: &[Edition]) -> TargetSet {
+        let specs = specs.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        TargetSet::parse(&specs, ports, editions).unwrap()
+    }
+
+    #[test]
+    fn len_matches_expand() {
+        let both = [Edition::Java, Edition::Bedrock];
+        let cases = [
+            (parse(&["10.0.0.0/30"], Some("25565,25566"), &both), 16),
+            (parse(&["10.0.0.0/30"], None, &both), 8),
+            // overlapping blocks are merged, not counted twice
+            (
+                parse(&["10.0.0.0/30", "10.0.0.2", "10.0.0.1-10.0.0.6"], None, &[]),
+                7,
+            ),
+            (
+                parse(&["play.example.com", "10.0.0.1"], Some("1-3"), &both),
+                12,
+            ),
+            (
+                TargetSet::from_addresses(expand(&["a.example.com"], None, &both)),
+                2,
+            ),
+        ];
+        for (i, (set, len)) in cases.into_iter().enumerate() {
+            assert_eq!(set.len(), len, "case {}", i);
+            assert_eq!(set.expand().count() as u64, len, "case {}", i);
+        }
+
+        let hosts = expand(&["10.0.0.0/30"], Some("25565,25566"), &both);
+        let java = hosts.iter().filter(|h| h.edition == Edition::Java).count();
+        assert_eq!(java, 8);
+        assert!(hosts.iter().any(|h| h.host == "10.0.0.3:25566"));
+    }
+
+    #[test]
+    fn rejects_bad_specs() {
+        let specs = [
+            "10.0.0.5-10.0.0.1",
+            "10.0.0.0/33",
+            "host:port",
+            ":25565",
+            "",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect::<Vec<_>>();
+        let errors = TargetSet::parse(&specs, None, &[]).unwrap_err();
+        assert_eq!(errors.len(), specs.len());
+        assert!(errors[0].reason.contains("backwards"), "{}", errors[0]);
+
+        for ports in ["", "25566-25565", "70000", "a"] {
+            assert!(
+                TargetSet::parse(&[], Some(ports), &[]).is_err(),
+                "{}",
+                ports
+            );
+        }
+    }
+
+    #[test]
+    fn dashed_hostnames_are_hosts() {
+        for spec in [
+            "my-server.example.com",
+            "10.0.0.1-server.example.com",
+            "a-b",
+        ] {
+            let hosts = expand(&[spec], None, &[]);
+            assert_eq!(hosts.len(), 1, "{}", spec);
+            assert_eq!(hosts[0].host, format!("{}:25565", spec));
+        }
+
+        let hosts = expand(&["mc-1.example.com:25570"], None, &[Edition::Bedrock]);
+        assert_eq!(hosts[0].host, "mc-1.example.com:25570");
+        assert_eq!(hosts[0].edition, Edition::Bedrock);
+    }
+}
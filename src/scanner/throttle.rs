@@ -0,0 +1,184 @@
+use std::{sync::Arc, time::Duration};
+
+use log::warn;
+use tokio::{
+    sync::{Mutex, OwnedSemaphorePermit, Semaphore},
+    time::Instant,
+};
+
+/// Connections per second when `VOYAGER_RATE_LIMIT` isn't set, 0 disables the limit
+pub const DEFAULT_RATE_LIMIT: u32 = 500;
+/// Open connections when `VOYAGER_MAX_IN_FLIGHT` isn't set
+pub const DEFAULT_MAX_IN_FLIGHT: usize = 256;
+
+/// Caps how fast and how many connections voyager opens, across every job at once.
+#[derive(Debug)]
+pub struct Throttle {
+    pub rate_limit: u32,
+    pub max_in_flight: usize,
+    interval: Option<Duration>,
+    next_slot: Mutex<Instant>,
+    in_flight: Arc<Semaphore>,
+}
+
+impl Throttle {
+    pub fn new(rate_limit: u32, max_in_flight: usize) -> Self {
+        let max_in_flight = max_in_flight.max(1);
+        let interval = match rate_limit {
+            0 => None,
+            rate => Some(Duration::from_secs(1) / rate),
+        };
+
+        Self {
+            rate_limit,
+            max_in_flight,
+            interval,
+            next_slot: Mutex::new(Instant::now()),
+            in_flight: Arc::new(Semaphore::new(max_in_flight)),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let rate_limit = env_or("VOYAGER_RATE_LIMIT", DEFAULT_RATE_LIMIT);
+        let max_in_flight = env_or("VOYAGER_MAX_IN_FLIGHT", DEFAULT_MAX_IN_FLIGHT);
+
+        Self::new(rate_limit, max_in_flight)
+    }
+
+    /// Waits for a free connection slot and the next rate limit tick.
+    /// The connection counts as in flight until the permit is dropped.
+    pub async fn acquire(&self) -> OwnedSemaphorePermit {
+        let permit = Arc::clone(&self.in_flight)
+            .acquire_owned()
+            .await
+            .expect("throttle semaphore is never closed");
+
+        if let Some(interval) = self.interval {
+            let slot = {
+                let mut next = self.next_slot.lock().await;
+                let slot = (*next).max(Instant::now());
+                *next = slot + interval;
+                slot
+            };
+
+            tokio::time::sleep_until(slot).await;
+        }
+
+        permit
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.max_in_flight - self.in_flight.available_permits()
+    }
+}
+
+fn env_or<T: std::str::FromStr + std::fmt::Display>(key: &str, default: T) -> T {
+    let Ok(value) = std::env::var(key) else {
+        return default;
+    };
+
+    match value.parse() {
+        Ok(value) => value,
+        Err(_) => {
+            warn!("Invalid {} '{}', using {}", key, value, default);
+            default
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn spaces_slots() {
+        let throttle = Throttle::new(10, 100);
+        let start = Instant::now();
+
+        for i in 0..5 {
+            drop(throttle.acquire().await);
+            assert_eq!(
+                start.elapsed(),
+                Duration::from_millis(100) * i,
+                "slot {}",
+                i
+            );
+        }
+
+        // an idle throttle doesn't save up slots for a burst
+        tokio::time::advance(Duration::from_secs(5)).await;
+        let idle = Instant::now();
+        drop(throttle.acquire().await);
+        drop(throttle.acquire().await);
+        assert_eq!(idle.elapsed(), Duration::from_millis(100));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn unlimited_rate() {
+        let throttle = Throttle::new(0, 100);
+        let start = Instant::now();
+        for _ in 0..50 {
+            drop(throttle.acquire().await);
+        }
+        assert_eq!(start.elapsed(), Duration::ZERO);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn caps_in_flight() {
+        let throttle = Throttle::new(0, 2);
+        let first = throttle.acquire().await;
+        let _second = throttle.acquire().await;
+        assert_eq!(throttle.in_flight(), 2);
+
+        let waiting = tokio::time::timeout(Duration::from_secs(60), throttle.acquire()).await;
+        assert!(waiting.is_err());
+
+        drop(first);
+        assert_eq!(throttle.in_flight(), 1);
+        let _third = throttle.acquire().await;
+        assert_eq!(throttle.in_flight(), 2);
+
+        assert_eq!(Throttle::new(0, 0).max_in_flight, 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn both_limits_across_tasks() {
+        let throttle = Arc::new(Throttle::new(20, 3));
+        let open = Arc::new(AtomicUsize::new(0));
+        let most = Arc::new(AtomicUsize::new(0));
+        let start = Instant::now();
+
+        let tasks = (0..12)
+            .map(|_| {
+                let throttle = Arc::clone(&throttle);
+                let open = Arc::clone(&open);
+                let most = Arc::clone(&most);
+                tokio::spawn(async move {
+                    let _permit = throttle.acquire().await;
+                    let at = start.elapsed();
+                    let now_open = open.fetch_add(1, Ordering::SeqCst) + 1;
+                    most.fetch_max(now_open, Ordering::SeqCst);
+
+                    tokio::time::sleep(Duration::from_millis(500)).await;
+                    open.fetch_sub(1, Ordering::SeqCst);
+                    at
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut starts = Vec::new();
+        for task in tasks {
+            starts.push(task.await.unwrap());
+        }
+        starts.sort();
+
+        assert_eq!(most.load(Ordering::SeqCst), 3);
+        for pair in starts.windows(2) {
+            assert!(pair[1] - pair[0] >= Duration::from_millis(50), "{:?}", pair);
+        }
+        // held for 500ms each, three at a time
+        assert!(*starts.last().unwrap() >= Duration::from_millis(1500));
+    }
+}
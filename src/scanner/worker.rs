@@ -1,107 +1,360 @@
-use std::time::Duration;
+use std::{
+    sync::{atomic::Ordering, Arc, Mutex},
+    time::Duration,
+};
 
-use log::{error, info};
-use tokio::task;
+use log::{debug, error, info, warn};
+use once_cell::sync::Lazy;
+use tokio::time::error::Elapsed;
 
 use crate::{
-    util::types::{Entry, OntosAddress},
-    web::server::{Response, WebRequest},
+    database::DbConn,
+    util::{
+        resolver::{Resolver, RESOLVER},
+        types::{
+            Edition, ExclusionList, FailureKind, OntosAddress, PingKind, PingMode, ScanFailure,
+        },
+    },
+};
+
+use super::{
+    jobs::{Job, JobStatus},
+    sink::{EuropaSink, ResultSink},
+    spool::Spool,
+    targets::{TargetIter, TargetSet},
+    throttle::Throttle,
 };
 
+/// Shared by every job in the process, rescans included, so the limits hold globally
+pub static THROTTLE: Lazy<Throttle> = Lazy::new(Throttle::from_env);
+/// Where every job's results wait until europa has them
+pub static SPOOL: Lazy<Spool> = Lazy::new(Spool::from_env);
+
 pub struct ScanJob {
-    pub ips: Vec<String>,
+    pub targets: TargetSet,
     pub timeout: Duration,
     pub workers: usize,
+    pub ping: PingMode,
+    /// Follow up successful Java pings with a query for the full player list and plugins
+    pub query: bool,
+    /// Follow up successful modern pings with a login attempt to find out the auth mode
+    pub login: bool,
+    /// Looks up hostname targets, DNS unless `VOYAGER_STATIC_HOSTS` is set
+    pub resolver: Arc<dyn Resolver>,
+    /// Where results go, europa unless the job asked for something else
+    pub sink: Arc<dyn ResultSink>,
+    /// Tell the sink about targets that didn't answer, only worth it for known addresses
+    pub report_failures: bool,
+    /// The instance's connection, exclusions are loaded through it
+    pub db: Option<DbConn>,
 }
 
 impl ScanJob {
-    pub fn new(ips: Vec<String>, timeout: Option<i32>, workers: Option<usize>) -> Option<Self> {
-        if ips.is_empty() {
+    pub fn new(targets: TargetSet, timeout: Option<i32>, workers: Option<usize>) -> Option<Self> {
+        if targets.is_empty() {
             return None;
         }
 
         Some(Self {
-            ips,
+            targets,
             timeout: Duration::from_secs(timeout.unwrap_or(10) as u64),
-            workers: workers.unwrap_or(1),
+            workers: workers.unwrap_or(1).max(1),
+            ping: PingMode::default(),
+            query: false,
+            login: false,
+            resolver: Arc::clone(&RESOLVER),
+            sink: Arc::new(EuropaSink::new()),
+            report_failures: false,
+            db: None,
         })
     }
 }
 
-pub async fn run_blocking(job: ScanJob) {
-    let ips = job.ips.clone();
+/// Runs `job` to completion, reporting progress through `handle`.
+pub async fn run_blocking(job: ScanJob, handle: Arc<Job>) {
     let timeout = job.timeout;
     let workers = job.workers;
-    let len = ips.len();
-    let mut chunks = ips.chunks((len / workers).max(1));
+    let ping = job.ping;
+    let query = job.query;
+    let login = job.login;
+    let resolver = job.resolver;
+    let sink = job.sink;
+    let report_failures = job.report_failures;
+    let len = job.targets.len();
+    let db = job.db;
+
+    // refuse to scan anything if we can't tell what we aren't allowed to touch
+    let exclusions = match load_exclusions(db.as_ref()).await {
+        Ok(list) => Arc::new(list),
+        Err(e) => {
+            error!("Not scanning, failed to load exclusions: {}", e);
+            handle.finish(JobStatus::Failed);
+            return;
+        }
+    };
+
+    // every worker pulls from the same iterator so ranges are never materialised
+    let targets = Arc::new(Mutex::new(job.targets.expand()));
     let mut futures = Vec::new();
 
-    info!("Scanning {} chunks with {} workers", chunks.len(), workers);
-    for _ in 0..=workers {
-        let Some(list) = chunks.next() else { break };
-        let mut ips = list.to_vec();
+    info!(
+        "Job {}: scanning {} targets with {} workers",
+        handle.id, len, workers
+    );
+    for _ in 0..workers {
+        let targets = Arc::clone(&targets);
+        let exclusions = Arc::clone(&exclusions);
+        let handle = Arc::clone(&handle);
+        let resolver = Arc::clone(&resolver);
+        let sink = Arc::clone(&sink);
 
-        futures.push(tokio::spawn(
-            async move { ping_slice(&mut ips, timeout).await },
-        ));
+        futures.push(tokio::spawn(async move {
+            let probes = Probes {
+                query,
+                login,
+                resolver,
+                report_failures,
+            };
+            ping_slice(targets, timeout, ping, probes, sink, exclusions, handle).await
+        }));
     }
 
     futures::future::join_all(futures).await;
+
+    let status = if handle.is_cancelled() {
+        JobStatus::Cancelled
+    } else {
+        JobStatus::Finished
+    };
+    handle.finish(status);
+
+    let stats = &handle.stats;
+    info!(
+        "Job {} {}: {} done, {} ok, {} failed, {} timed out, {} excluded",
+        handle.id,
+        status,
+        stats.done.load(Ordering::Relaxed),
+        stats.ok.load(Ordering::Relaxed),
+        stats.failed.load(Ordering::Relaxed),
+        stats.timeouts.load(Ordering::Relaxed),
+        stats.excluded.load(Ordering::Relaxed),
+    );
+}
+
+/// Reads `VOYAGER_EXCLUSIONS_FILE` instead of the database when it's set,
+/// so voyager can run without Postgres.
+async fn load_exclusions(db: Option<&DbConn>) -> anyhow::Result<ExclusionList> {
+    if let Ok(path) = std::env::var("VOYAGER_EXCLUSIONS_FILE") {
+        let content = tokio::fs::read_to_string(path).await?;
+        return ExclusionList::parse(&content);
+    }
+
+    let Some(db) = db else {
+        return Err(anyhow::anyhow!(
+            "no database and VOYAGER_EXCLUSIONS_FILE isn't set"
+        ));
+    };
+    let list = db.get_exclusions().await?;
+
+    Ok(ExclusionList::new(&list))
+}
+
+/// Starts `job` in the background and returns straight away.
+pub fn run(job: ScanJob, handle: Arc<Job>) {
+    tokio::spawn(async move { run_blocking(job, handle).await });
+}
+
+/// How each target is looked up and followed up on, beyond the ping itself
+struct Probes {
+    query: bool,
+    login: bool,
+    resolver: Arc<dyn Resolver>,
+    report_failures: bool,
 }
 
-pub async fn run(job: ScanJob) -> anyhow::Result<()> {
-    task::spawn(async move { run_blocking(job).await }).await?;
+/// What a target turned into before it's pinged
+#[derive(Debug)]
+enum Resolution {
+    Target(OntosAddress),
+    Failed(anyhow::Error),
+    TimedOut,
+    Excluded(OntosAddress),
+}
+
+async fn resolve_target(
+    target: &OntosAddress,
+    resolver: &dyn Resolver,
+    exclusions: &ExclusionList,
+    timeout: Duration,
+) -> Resolution {
+    let addr = match tokio::time::timeout(timeout, target.resolve(resolver)).await {
+        Ok(Ok(addr)) => addr,
+        Ok(Err(e)) => return Resolution::Failed(e),
+        Err(_) => return Resolution::TimedOut,
+    };
+
+    // checked after resolving, a hostname can point anywhere
+    if exclusions.contains(&addr.host) {
+        return Resolution::Excluded(addr);
+    }
 
-    Ok(())
+    Resolution::Target(addr)
 }
 
-async fn ping_slice(list: &mut Vec<String>, timeout: Duration) {
-    let mut queue = vec![];
+async fn ping_slice(
+    list: Arc<Mutex<TargetIter>>,
+    timeout: Duration,
+    ping: PingMode,
+    probes: Probes,
+    sink: Arc<dyn ResultSink>,
+    exclusions: Arc<ExclusionList>,
+    handle: Arc<Job>,
+) {
+    let stats = &handle.stats;
     loop {
-        let Some(host) = list.pop() else { break };
+        if handle.is_cancelled() {
+            warn!("Job {} cancelled, stopping worker", handle.id);
+            break;
+        }
 
-        let ontos_addr = OntosAddress { host };
+        let next = list.lock().unwrap().next();
+        let Some(target) = next else { break };
 
-        let scan = match ontos_addr.ping_server(timeout).await {
+        let resolved = resolve_target(&target, probes.resolver.as_ref(), &exclusions, timeout);
+        let ontos_addr = match resolved.await {
+            Resolution::Target(addr) => addr,
+            Resolution::Failed(e) => {
+                debug!("Failed to resolve {}: {}", target.host, e);
+                stats.failed.fetch_add(1, Ordering::Relaxed);
+                stats.done.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+            Resolution::TimedOut => {
+                debug!("Timed out resolving {}", target.host);
+                stats.timeouts.fetch_add(1, Ordering::Relaxed);
+                stats.done.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+            Resolution::Excluded(addr) => {
+                debug!("Skipping excluded target {}", addr.host);
+                stats.excluded.fetch_add(1, Ordering::Relaxed);
+                stats.done.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+        };
+
+        let permit = THROTTLE.acquire().await;
+        let result = ontos_addr.ping_server(timeout, ping).await;
+        drop(permit);
+        stats.done.fetch_add(1, Ordering::Relaxed);
+
+        let mut scan = match result {
             Ok(scan) => scan,
             Err(e) => {
-                error!("Error scanning {}: {}", ontos_addr.host, e);
+                if e.is::<Elapsed>() {
+                    debug!("Timed out scanning {}", ontos_addr.host);
+                    stats.timeouts.fetch_add(1, Ordering::Relaxed);
+                } else {
+                    error!("Error scanning {}: {}", ontos_addr.host, e);
+                    stats.failed.fetch_add(1, Ordering::Relaxed);
+                }
+
+                if probes.report_failures {
+                    let failure = ScanFailure::new(&ontos_addr, FailureKind::classify(&e));
+                    if let Err(e) = sink.push_failure(&failure).await {
+                        error!("Failed to save failure for {}: {}", ontos_addr.host, e);
+                    }
+                }
                 continue;
             }
         };
-        queue.push(scan);
+        stats.ok.fetch_add(1, Ordering::Relaxed);
 
-        if queue.len() >= 10 {
-            if let Err(e) = upload_servers(&queue).await {
-                error!("Error uploading servers: {}", e);
-            };
-            queue.clear();
+        // most servers don't enable query, so a failure here is only worth a debug line
+        if probes.query && ontos_addr.edition == Edition::Java {
+            let permit = THROTTLE.acquire().await;
+            match ontos_addr.query_server(timeout).await {
+                Ok(packet) => scan.merge_query(packet),
+                Err(e) => debug!("Query to {} failed: {}", ontos_addr.host, e),
+            }
+            drop(permit);
+        }
+
+        // pre-netty servers log in completely differently, so only modern pings qualify
+        if probes.login && scan.server.ping == PingKind::Modern {
+            let permit = THROTTLE.acquire().await;
+            match ontos_addr.login_server(timeout, scan.server.protocol).await {
+                Ok(packet) => scan.merge_login(packet),
+                Err(e) => debug!("Login to {} failed: {}", ontos_addr.host, e),
+            }
+            drop(permit);
+        }
+
+        if let Err(e) = sink.push(&scan).await {
+            error!("Failed to save {}: {}", ontos_addr.host, e);
         }
     }
+
+    if let Err(e) = sink.flush().await {
+        error!("Failed to flush results: {}", e);
+    }
 }
 
-async fn upload_servers(queue: &Vec<Entry>) -> anyhow::Result<()> {
-    let client = reqwest::Client::new();
-    let url = std::env::var("WEBSERVER_URL")?;
-    let port = std::env::var("WEBSERVER_PORT")?;
+#[cfg(test)]
+mod tests {
+    use crate::util::resolver::StaticResolver;
 
-    let input = WebRequest {
-        servers: Some(queue.clone()), // kind of an expensive clone, but I don't want to prematurely optimize
-        ..Default::default()
-    };
+    use super::*;
+
+    fn target(host: &str) -> OntosAddress {
+        OntosAddress {
+            host: host.to_string(),
+            edition: Edition::Java,
+            hostname: None,
+            srv: true,
+        }
+    }
+
+    async fn resolve(host: &str) -> Resolution {
+        let resolver =
+            StaticResolver::parse("excluded.example.com=10.0.0.5:25565,ok.example.com=10.1.0.5")
+                .unwrap();
+        let exclusions = ExclusionList::parse("10.0.0.0/24\n").unwrap();
 
-    let res = client
-        .post(format!("http://{}:{}/upload", url, port))
-        .json(&input)
-        .send()
-        .await?
-        .json::<Response>()
-        .await?;
-
-    match res.status {
-        200 => info!("Uploaded {} servers", queue.len()),
-        _ => error!("Failed to upload servers: {}", res.status),
+        resolve_target(
+            &target(host),
+            &resolver,
+            &exclusions,
+            Duration::from_secs(1),
+        )
+        .await
     }
 
-    Ok(())
+    #[tokio::test]
+    async fn hostnames_are_checked_after_resolving() {
+        match resolve("excluded.example.com:25565").await {
+            Resolution::Excluded(addr) => assert_eq!(addr.host, "10.0.0.5:25565"),
+            other => panic!("expected excluded, got {:?}", other),
+        }
+
+        match resolve("ok.example.com:25565").await {
+            Resolution::Target(addr) => {
+                assert_eq!(addr.host, "10.1.0.5:25565");
+                assert_eq!(addr.hostname.as_deref(), Some("ok.example.com"));
+            }
+            other => panic!("expected a target, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn excluded_ips_and_unknown_names() {
+        assert!(matches!(
+            resolve("10.0.0.9:25565").await,
+            Resolution::Excluded(_)
+        ));
+        assert!(matches!(
+            resolve("missing.example.com:25565").await,
+            Resolution::Failed(_)
+        ));
+    }
 }
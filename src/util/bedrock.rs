@@ -0,0 +1,190 @@
+//! Bedrock Edition discovery through the RakNet unconnected ping.
+//! See <https://wiki.vg/Raknet_Protocol#Unconnected_Ping>
+
+use anyhow::anyhow;
+use tokio::net::UdpSocket;
+
+pub const DEFAULT_PORT: u16 = 19132;
+
+const UNCONNECTED_PING: u8 = 0x01;
+const UNCONNECTED_PONG: u8 = 0x1c;
+const MAGIC: [u8; 16] = [
+    0x00, 0xff, 0xff, 0x00, 0xfe, 0xfe, 0xfe, 0xfe, 0xfd, 0xfd, 0xfd, 0xfd, 0x12, 0x34, 0x56, 0x78,
+];
+/// id + time + server guid + magic + string length
+const PONG_HEADER_LEN: usize = 1 + 8 + 8 + 16 + 2;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BedrockResponse {
+    /// `MCPE` for Bedrock, `MCEE` for Education Edition
+    pub edition: String,
+    pub motd: Vec<String>,
+    pub protocol: i32,
+    pub version: String,
+    pub online_players: usize,
+    pub max_players: usize,
+    pub server_guid: Option<String>,
+    pub gamemode: Option<String>,
+}
+
+pub async fn ping(host: &str, port: u16) -> anyhow::Result<BedrockResponse> {
+    let socket = UdpSocket::bind(("0.0.0.0", 0)).await?;
+    socket.connect((host, port)).await?;
+    socket.send(&build_request()).await?;
+
+    let mut buf = vec![0; 2048];
+    let len = socket.recv(&mut buf).await?;
+
+    parse(&buf[..len])
+}
+
+fn build_request() -> Vec<u8> {
+    let time = chrono::Utc::now().timestamp_millis();
+    let client_guid = rand::random::<i64>();
+
+    let mut buf = vec![UNCONNECTED_PING];
+    buf.extend_from_slice(&time.to_be_bytes());
+    buf.extend_from_slice(&MAGIC);
+    buf.extend_from_slice(&client_guid.to_be_bytes());
+
+    buf
+}
+
+pub fn parse(packet: &[u8]) -> anyhow::Result<BedrockResponse> {
+    if packet.len() < PONG_HEADER_LEN || packet[0] != UNCONNECTED_PONG {
+        return Err(anyhow!("not an unconnected pong"));
+    }
+
+    if packet[17..33] != MAGIC {
+        return Err(anyhow!("bad RakNet magic"));
+    }
+
+    let len = u16::from_be_bytes([packet[33], packet[34]]) as usize;
+    let Some(body) = packet.get(PONG_HEADER_LEN..PONG_HEADER_LEN + len) else {
+        return Err(anyhow!("truncated unconnected pong"));
+    };
+
+    parse_motd(&String::from_utf8_lossy(body))
+}
+
+/// `edition;motd line 1;protocol;version;online;max;guid;motd line 2;gamemode;...`
+fn parse_motd(input: &str) -> anyhow::Result<BedrockResponse> {
+    let fields = input.split(';').collect::<Vec<_>>();
+    if fields.len() < 6 {
+        return Err(anyhow!("expected at least 6 fields, got {}", fields.len()));
+    }
+
+    let optional = |i: usize| {
+        fields
+            .get(i)
+            .filter(|field| !field.is_empty())
+            .map(|field| field.to_string())
+    };
+
+    let mut motd = vec![fields[1].to_string()];
+    motd.extend(optional(7));
+
+    Ok(BedrockResponse {
+        edition: fields[0].to_string(),
+        motd,
+        protocol: fields[2].parse()?,
+        version: fields[3].to_string(),
+        online_players: fields[4].parse()?,
+        max_players: fields[5].parse()?,
+        server_guid: optional(6),
+        gamemode: optional(8),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What a 1.20 dedicated server answers with, time and guid included
+    const PONG_HEADER: [u8; PONG_HEADER_LEN] = [
+        0x1c, 0x00, 0x00, 0x01, 0x89, 0x6b, 0x2e, 0x4c, 0x11, 0xb7, 0xee, 0x5c, 0x4b, 0x51, 0x9d,
+        0x31, 0x31, 0x00, 0xff, 0xff, 0x00, 0xfe, 0xfe, 0xfe, 0xfe, 0xfd, 0xfd, 0xfd, 0xfd, 0x12,
+        0x34, 0x56, 0x78, 0x00, 0x60,
+    ];
+    const PONG_BODY: &[u8] =
+        b"MCPE;Dedicated Server;589;1.20.0;2;10;13253860892328930865;Bedrock level;Survival;1;19132;19133;";
+
+    fn pong() -> Vec<u8> {
+        let mut packet = PONG_HEADER.to_vec();
+        packet.extend_from_slice(PONG_BODY);
+        packet
+    }
+
+    #[test]
+    fn parses_captured_pong() {
+        assert_eq!(PONG_BODY.len(), 0x60);
+
+        let response = parse(&pong()).unwrap();
+        assert_eq!(
+            response,
+            BedrockResponse {
+                edition: "MCPE".to_string(),
+                motd: vec!["Dedicated Server".to_string(), "Bedrock level".to_string()],
+                protocol: 589,
+                version: "1.20.0".to_string(),
+                online_players: 2,
+                max_players: 10,
+                server_guid: Some("13253860892328930865".to_string()),
+                gamemode: Some("Survival".to_string()),
+            }
+        );
+
+        // anything after the string is ignored
+        let mut trailing = pong();
+        trailing.extend_from_slice(&[0; 4]);
+        assert_eq!(parse(&trailing).unwrap(), response);
+    }
+
+    #[test]
+    fn short_motd() {
+        let response = parse_motd("MCEE;Classroom;390;1.14.50;0;40").unwrap();
+        assert_eq!(response.edition, "MCEE");
+        assert_eq!(response.motd, ["Classroom"]);
+        assert_eq!(response.server_guid, None);
+        assert_eq!(response.gamemode, None);
+
+        assert!(parse_motd("MCPE;motd;589;1.20.0;2").is_err());
+        assert!(parse_motd("MCPE;motd;new;1.20.0;2;10").is_err());
+    }
+
+    #[test]
+    fn rejects_bad_packets() {
+        let mut id = pong();
+        id[0] = 0x1d;
+        let mut magic = pong();
+        magic[17] = 0x01;
+        let mut length = pong();
+        length[34] += 1;
+        let truncated = pong()[..PONG_HEADER_LEN + 10].to_vec();
+
+        for (packet, message) in [
+            (Vec::new(), "not an unconnected pong"),
+            (PONG_HEADER[..20].to_vec(), "not an unconnected pong"),
+            (id, "not an unconnected pong"),
+            (magic, "bad RakNet magic"),
+            (length, "truncated"),
+            (truncated, "truncated"),
+        ] {
+            let err = parse(&packet).unwrap_err();
+            assert!(
+                err.to_string().contains(message),
+                "expected {}, got {}",
+                message,
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn request_layout() {
+        let request = build_request();
+        assert_eq!(request.len(), 1 + 8 + 16 + 8);
+        assert_eq!(request[0], UNCONNECTED_PING);
+        assert_eq!(request[9..25], MAGIC);
+    }
+}
@@ -0,0 +1,562 @@
+//! Chat components, the JSON text format MOTDs come in, and the `§` codes older
+//! servers (and plenty of newer ones) put straight into the text.
+//!
+//! A description can be a plain string, an array or a component with `extra`
+//! children, all of them end up as one `Component` tree. Strings with `§` codes are
+//! split into children so their colours and styles are kept too.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+const SECTION: char = '§';
+
+/// The sixteen named colours and their `§` codes
+const COLOURS: [(&str, char); 16] = [
+    ("black", '0'),
+    ("dark_blue", '1'),
+    ("dark_green", '2'),
+    ("dark_aqua", '3'),
+    ("dark_red", '4'),
+    ("dark_purple", '5'),
+    ("gold", '6'),
+    ("gray", '7'),
+    ("dark_gray", '8'),
+    ("blue", '9'),
+    ("green", 'a'),
+    ("aqua", 'b'),
+    ("red", 'c'),
+    ("light_purple", 'd'),
+    ("yellow", 'e'),
+    ("white", 'f'),
+];
+
+/// One component, children inherit whatever they don't set themselves
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Component {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub text: String,
+    /// Translation key, the client fills in `with`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub translate: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub with: Vec<Component>,
+    /// A colour name or `#rrggbb`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bold: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub italic: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub underlined: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strikethrough: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub obfuscated: Option<bool>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extra: Vec<Component>,
+}
+
+/// What a run of text ends up looking like once everything is inherited
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Style {
+    color: Option<String>,
+    bold: bool,
+    italic: bool,
+    underlined: bool,
+    strikethrough: bool,
+    obfuscated: bool,
+}
+
+impl Style {
+    fn inherit(&self, component: &Component) -> Self {
+        Self {
+            color: component.color.clone().or_else(|| self.color.clone()),
+            bold: component.bold.unwrap_or(self.bold),
+            italic: component.italic.unwrap_or(self.italic),
+            underlined: component.underlined.unwrap_or(self.underlined),
+            strikethrough: component.strikethrough.unwrap_or(self.strikethrough),
+            obfuscated: component.obfuscated.unwrap_or(self.obfuscated),
+        }
+    }
+
+    /// The codes that switch to this style from nothing. A colour code resets the
+    /// styles, so it goes first.
+    fn codes(&self) -> String {
+        let mut out = String::new();
+        match self.color.as_deref() {
+            Some(hex) if hex.starts_with('#') => {
+                out.push(SECTION);
+                out.push('x');
+                for c in hex[1..].chars() {
+                    out.push(SECTION);
+                    out.push(c);
+                }
+            }
+            Some(name) => {
+                if let Some((_, code)) = COLOURS.iter().find(|(colour, _)| *colour == name) {
+                    out.push(SECTION);
+                    out.push(*code);
+                }
+            }
+            None => {}
+        }
+
+        let styles = [
+            (self.obfuscated, 'k'),
+            (self.bold, 'l'),
+            (self.strikethrough, 'm'),
+            (self.underlined, 'n'),
+            (self.italic, 'o'),
+        ];
+        for (_, code) in styles.iter().filter(|(on, _)| *on) {
+            out.push(SECTION);
+            out.push(*code);
+        }
+
+        out
+    }
+}
+
+impl Component {
+    pub fn text(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Whatever a server sent as its description. Anything that isn't a component
+    /// is taken as its text, the client doesn't reject those either.
+    pub fn from_json(value: &Value) -> Self {
+        match value {
+            Value::String(text) => Self::from_legacy(text),
+            Value::Array(items) => {
+                let mut items = items.iter().map(Self::from_json);
+                let mut first = items.next().unwrap_or_default();
+                first.extra.extend(items);
+                first
+            }
+            Value::Object(map) => {
+                let mut component = Self {
+                    translate: map
+                        .get("translate")
+                        .and_then(Value::as_str)
+                        .map(str::to_string),
+                    with: list(map.get("with")),
+                    color: map.get("color").and_then(Value::as_str).and_then(colour),
+                    bold: map.get("bold").and_then(flag),
+                    italic: map.get("italic").and_then(flag),
+                    underlined: map.get("underlined").and_then(flag),
+                    strikethrough: map.get("strikethrough").and_then(flag),
+                    obfuscated: map.get("obfuscated").and_then(flag),
+                    extra: list(map.get("extra")),
+                    ..Default::default()
+                };
+
+                // keybinds can't be resolved without the client, the key is the best
+                // there is
+                let text = map.get("text").or_else(|| map.get("keybind"));
+                let text = Self::from_json(text.unwrap_or(&Value::Null));
+                if text.extra.is_empty() {
+                    component.text = text.text;
+                } else {
+                    component.extra.splice(0..0, text.extra);
+                }
+
+                component
+            }
+            Value::Null => Self::default(),
+            other => Self::text(other.to_string()),
+        }
+    }
+
+    /// Splits text with `§` codes into a child per run. A colour code resets the
+    /// styles and `§r` resets everything, `§x` followed by six `§` digits is a hex
+    /// colour. Text without codes stays a single component.
+    pub fn from_legacy(text: &str) -> Self {
+        if !text.contains(SECTION) {
+            return Self::text(text);
+        }
+
+        let mut runs = Vec::new();
+        let mut current = Self::default();
+        let mut chars = text.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            if c != SECTION {
+                current.text.push(c);
+                continue;
+            }
+            let Some(code) = chars.next().map(|code| code.to_ascii_lowercase()) else {
+                break;
+            };
+
+            let mut next = Self::default();
+            match code {
+                'x' => {
+                    let digits = (0..6)
+                        .map_while(|_| {
+                            chars.next_if_eq(&SECTION)?;
+                            chars.next_if(char::is_ascii_hexdigit)
+                        })
+                        .collect::<String>();
+                    if digits.len() == 6 {
+                        next.color = Some(format!("#{}", digits.to_lowercase()));
+                    }
+                }
+                'r' => {}
+                'k' | 'l' | 'm' | 'n' | 'o' => {
+                    next = Self {
+                        text: String::new(),
+                        extra: Vec::new(),
+                        ..current.clone()
+                    };
+                    let on = Some(true);
+                    match code {
+                        'k' => next.obfuscated = on,
+                        'l' => next.bold = on,
+                        'm' => next.strikethrough = on,
+                        'n' => next.underlined = on,
+                        _ => next.italic = on,
+                    }
+                }
+                code => match COLOURS.iter().find(|(_, c)| *c == code) {
+                    Some((name, _)) => next.color = Some(name.to_string()),
+                    // not a code, the client shows neither character
+                    None => continue,
+                },
+            }
+
+            if !current.text.is_empty() {
+                runs.push(current);
+            }
+            current = next;
+        }
+        if !current.text.is_empty() {
+            runs.push(current);
+        }
+
+        Self {
+            extra: runs,
+            ..Default::default()
+        }
+    }
+
+    /// Just the text, what a player would read with formatting stripped
+    pub fn plain(&self) -> String {
+        let mut out = String::new();
+        self.write_plain(&mut out);
+        out
+    }
+
+    fn write_plain(&self, out: &mut String) {
+        out.push_str(&self.text);
+        if let Some(key) = &self.translate {
+            out.push_str(&translate(key, &self.with));
+        }
+        for child in &self.extra {
+            child.write_plain(out);
+        }
+    }
+
+    /// The text with `§` codes wherever the style changes, the way older servers
+    /// send their MOTD. Hex colours use the `§x` form.
+    pub fn to_legacy(&self) -> String {
+        let mut out = String::new();
+        let mut current = Style::default();
+        self.write_legacy(&Style::default(), &mut current, &mut out);
+        out
+    }
+
+    fn write_legacy(&self, parent: &Style, current: &mut Style, out: &mut String) {
+        let style = parent.inherit(self);
+        let mut text = self.text.clone();
+        if let Some(key) = &self.translate {
+            text.push_str(&translate(key, &self.with));
+        }
+
+        if !text.is_empty() {
+            if style != *current {
+                // styles can only be added, so anything else starts over
+                if style.color.is_none() && *current != Style::default() {
+                    out.push(SECTION);
+                    out.push('r');
+                }
+                out.push_str(&style.codes());
+                *current = style.clone();
+            }
+            out.push_str(&text);
+        }
+
+        for child in &self.extra {
+            child.write_legacy(&style, current, out);
+        }
+    }
+}
+
+fn list(value: Option<&Value>) -> Vec<Component> {
+    match value {
+        Some(Value::Array(items)) => items.iter().map(Component::from_json).collect(),
+        Some(Value::Null) | None => Vec::new(),
+        Some(other) => vec![Component::from_json(other)],
+    }
+}
+
+fn flag(value: &Value) -> Option<bool> {
+    match value {
+        Value::Bool(on) => Some(*on),
+        Value::String(on) => on.parse().ok(),
+        _ => None,
+    }
+}
+
+/// A known colour name or `#rrggbb`, lowercased. Anything else is dropped, the
+/// client does the same.
+fn colour(input: &str) -> Option<String> {
+    let input = input.trim().to_lowercase();
+    let hex = input.len() == 7
+        && input.starts_with('#')
+        && input[1..].chars().all(|c| c.is_ascii_hexdigit());
+    let named = COLOURS.iter().any(|(name, _)| *name == input);
+
+    (hex || named).then_some(input)
+}
+
+/// Fills `%s` and `%1$s` style placeholders, the translations themselves are only
+/// known to the client so the key stands in for them
+fn translate(key: &str, with: &[Component]) -> String {
+    let args = with.iter().map(Component::plain).collect::<Vec<_>>();
+    if args.is_empty() {
+        return key.to_string();
+    }
+
+    let mut out = key.to_string();
+    for (i, arg) in args.iter().enumerate() {
+        out = out.replace(&format!("%{}$s", i + 1), arg);
+    }
+    let mut args = args.iter();
+    while let (Some(at), Some(arg)) = (out.find("%s"), args.next()) {
+        out.replace_range(at..at + 2, arg);
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn json(input: &str) -> Component {
+        Component::from_json(&serde_json::from_str(input).unwrap())
+    }
+
+    fn coloured(text: &str, color: &str) -> Component {
+        Component {
+            color: Some(color.to_string()),
+            ..Component::text(text)
+        }
+    }
+
+    #[test]
+    fn from_json_shapes() {
+        assert_eq!(json(r#""hello""#), Component::text("hello"));
+        assert_eq!(json("5"), Component::text("5"));
+        assert_eq!(json("null"), Component::default());
+
+        let array = json(r#"["a", {"text": "b", "color": "red"}]"#);
+        assert_eq!(
+            array,
+            Component {
+                extra: vec![coloured("b", "red")],
+                ..Component::text("a")
+            }
+        );
+        assert_eq!(array.plain(), "ab");
+
+        let object = json(r#"{"text": "a", "bold": true, "extra": ["b", {"text": "c"}]}"#);
+        assert_eq!(object.bold, Some(true));
+        assert_eq!(
+            object.extra,
+            vec![Component::text("b"), Component::text("c")]
+        );
+        assert_eq!(object.plain(), "abc");
+    }
+
+    #[test]
+    fn from_json_legacy_text_in_object() {
+        let component = json(r#"{"text": "§aGreen", "bold": true, "extra": ["x"]}"#);
+        assert_eq!(component.text, "");
+        assert_eq!(
+            component.extra,
+            vec![coloured("Green", "green"), Component::text("x")]
+        );
+        assert_eq!(component.plain(), "Greenx");
+        assert_eq!(component.to_legacy(), "§a§lGreen§r§lx");
+    }
+
+    #[test]
+    fn from_json_translate() {
+        let component = json(r#"{"translate": "Hi %s and %2$s", "with": ["x", {"text": "y"}]}"#);
+        assert_eq!(component.translate.as_deref(), Some("Hi %s and %2$s"));
+        assert_eq!(
+            component.with,
+            vec![Component::text("x"), Component::text("y")]
+        );
+        assert_eq!(component.plain(), "Hi x and y");
+
+        // without arguments the key is all there is
+        assert_eq!(json(r#"{"translate": "menu.title"}"#).plain(), "menu.title");
+    }
+
+    #[test]
+    fn from_json_keybind_and_fields() {
+        let component = json(
+            r##"{"keybind": "key.jump", "color": "#FFAA00", "bold": "true", "italic": "nah"}"##,
+        );
+        assert_eq!(component.text, "key.jump");
+        assert_eq!(component.color.as_deref(), Some("#ffaa00"));
+        assert_eq!(component.bold, Some(true));
+        assert_eq!(component.italic, None);
+
+        for input in [
+            r#"{"text": "a", "color": "notacolour"}"#,
+            r##"{"text": "a", "color": "#ffaa0"}"##,
+            r##"{"text": "a", "color": "#gggggg"}"##,
+        ] {
+            assert_eq!(json(input).color, None, "{}", input);
+        }
+        assert_eq!(
+            json(r#"{"text": "a", "color": " Red "}"#).color.as_deref(),
+            Some("red")
+        );
+    }
+
+    #[test]
+    fn from_legacy_runs() {
+        assert_eq!(Component::from_legacy("plain"), Component::text("plain"));
+
+        let cases: [(&str, Vec<Component>); 7] = [
+            (
+                "§x§F§F§a§a§0§0Hex§r!",
+                vec![coloured("Hex", "#ffaa00"), Component::text("!")],
+            ),
+            (
+                "§cRed§r plain",
+                vec![coloured("Red", "red"), Component::text(" plain")],
+            ),
+            (
+                // styles stay on until the next colour
+                "§c§lA§oB§9C",
+                vec![
+                    Component {
+                        bold: Some(true),
+                        ..coloured("A", "red")
+                    },
+                    Component {
+                        bold: Some(true),
+                        italic: Some(true),
+                        ..coloured("B", "red")
+                    },
+                    coloured("C", "blue"),
+                ],
+            ),
+            (
+                "§lBold§cRed",
+                vec![
+                    Component {
+                        bold: Some(true),
+                        ..Component::text("Bold")
+                    },
+                    coloured("Red", "red"),
+                ],
+            ),
+            (
+                "§C§LUp",
+                vec![Component {
+                    bold: Some(true),
+                    ..coloured("Up", "red")
+                }],
+            ),
+            // neither an unknown code nor a short hex colour shows up
+            ("a§zb", vec![Component::text("ab")]),
+            ("§x§f§fHalf", vec![Component::text("Half")]),
+        ];
+        for (input, runs) in cases {
+            assert_eq!(Component::from_legacy(input).extra, runs, "{}", input);
+        }
+
+        assert_eq!(Component::from_legacy("trail§").plain(), "trail");
+    }
+
+    #[test]
+    fn to_legacy_resets() {
+        let cases = [
+            // a style is dropped, only a reset gets rid of it
+            (
+                vec![
+                    Component {
+                        bold: Some(true),
+                        ..Component::text("a")
+                    },
+                    Component::text("b"),
+                ],
+                "§la§rb",
+            ),
+            // a new colour resets the styles by itself
+            (
+                vec![
+                    Component {
+                        bold: Some(true),
+                        ..coloured("a", "red")
+                    },
+                    coloured("b", "blue"),
+                ],
+                "§c§la§9b",
+            ),
+            (vec![coloured("a", "red"), Component::text("b")], "§ca§rb"),
+            (
+                vec![Component::text("a"), coloured("b", "#00ff00")],
+                "a§x§0§0§f§f§0§0b",
+            ),
+            (vec![Component::text("a"), Component::text("b")], "ab"),
+        ];
+        for (extra, legacy) in cases {
+            let component = Component {
+                extra,
+                ..Default::default()
+            };
+            assert_eq!(component.to_legacy(), legacy);
+        }
+
+        // children inherit from their parent
+        let nested =
+            json(r#"{"text": "a", "color": "red", "extra": [{"text": "b", "bold": true}]}"#);
+        assert_eq!(nested.to_legacy(), "§ca§c§lb");
+    }
+
+    #[test]
+    fn legacy_round_trip() {
+        // descriptions are keyed on this text, it has to come out the same every time
+        for input in [
+            "plain",
+            "§cRed§lBold§r plain",
+            "§lBold§cRed",
+            "§x§F§F§a§a§0§0Hex§r!",
+            "§C§LUp",
+            "a§zb",
+            "§k§l§m§n§oAll§rnone",
+            "§6A\n§eB",
+        ] {
+            let legacy = Component::from_legacy(input).to_legacy();
+            let again = Component::from_legacy(&legacy);
+            assert_eq!(again.to_legacy(), legacy, "{}", input);
+            assert_eq!(
+                again.plain(),
+                Component::from_legacy(input).plain(),
+                "{}",
+                input
+            );
+        }
+    }
+}
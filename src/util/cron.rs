@@ -0,0 +1,265 @@
+//! Five field cron expressions, `minute hour day-of-month month day-of-week`, always in UTC.
+//! Each field takes `*`, numbers, `a-b` ranges, `/n` steps and comma separated lists.
+//! `@hourly`, `@daily`, `@weekly` and `@monthly` are accepted as shorthands.
+
+use std::{fmt, str::FromStr};
+
+use anyhow::anyhow;
+use chrono::{Datelike, NaiveDateTime, Timelike};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cron {
+    source: String,
+    /// One bit per allowed value
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether day-of-month / day-of-week started with anything but `*`, see `matches`
+    days_restricted: bool,
+    weekdays_restricted: bool,
+}
+
+impl Cron {
+    pub fn parse(input: &str) -> anyhow::Result<Self> {
+        let source = input.trim().to_string();
+        let expanded = match source.as_str() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            other => other,
+        };
+
+        let fields = expanded.split_whitespace().collect::<Vec<_>>();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(anyhow!("expected 5 fields in cron expression: {}", source));
+        };
+
+        // sunday is both 0 and 7
+        let mut weekdays = parse_field(weekday, 0, 7)?;
+        if weekdays & (1 << 7) != 0 {
+            weekdays |= 1;
+        }
+
+        Ok(Self {
+            minutes: parse_field(minute, 0, 59)?,
+            hours: parse_field(hour, 0, 23)?,
+            days: parse_field(day, 1, 31)?,
+            months: parse_field(month, 1, 12)?,
+            weekdays,
+            // `*/2` is still unrestricted, the same as every other cron
+            days_restricted: !day.starts_with('*'),
+            weekdays_restricted: !weekday.starts_with('*'),
+            source,
+        })
+    }
+
+    /// Whether the minute `time` falls in is one this expression fires on
+    pub fn matches(&self, time: &NaiveDateTime) -> bool {
+        let bit = |mask: u64, value: u32| mask & (1 << value) != 0;
+
+        let day = bit(self.days, time.day());
+        let weekday = bit(self.weekdays, time.weekday().num_days_from_sunday());
+        // like every other cron, restricting both days means either one is enough
+        let day_matches = match (self.days_restricted, self.weekdays_restricted) {
+            (true, true) => day || weekday,
+            _ => day && weekday,
+        };
+
+        day_matches
+            && bit(self.minutes, time.minute())
+            && bit(self.hours, time.hour())
+            && bit(self.months, time.month())
+    }
+}
+
+fn parse_field(field: &str, min: u32, max: u32) -> anyhow::Result<u64> {
+    let mut mask = 0;
+
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, Some(step.parse::<u32>()?)),
+            None => (part, None),
+        };
+        if step == Some(0) {
+            return Err(anyhow!("cron step can't be 0: {}", part));
+        }
+
+        let (start, end) = match range {
+            "*" => (min, max),
+            range => match range.split_once('-') {
+                Some((start, end)) => (start.parse()?, end.parse()?),
+                // `5/15` means every 15 starting at 5
+                None if step.is_some() => (range.parse()?, max),
+                None => {
+                    let value = range.parse()?;
+                    (value, value)
+                }
+            },
+        };
+
+        if start < min || end > max || start > end {
+            return Err(anyhow!("cron field {} out of range {}-{}", part, min, max));
+        }
+
+        for value in (start..=end).step_by(step.unwrap_or(1) as usize) {
+            mask |= 1 << value;
+        }
+    }
+
+    Ok(mask)
+}
+
+impl FromStr for Cron {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl fmt::Display for Cron {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl Serialize for Cron {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.source)
+    }
+}
+
+impl<'de> Deserialize<'de> for Cron {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let source = String::deserialize(deserializer)?;
+        Self::parse(&source).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    fn values(mask: u64) -> Vec<u32> {
+        (0..64).filter(|value| mask & (1 << value) != 0).collect()
+    }
+
+    fn at(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        // 2023-10-01 is a Sunday
+        NaiveDate::from_ymd_opt(2023, 10, day)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn fields() {
+        let cases: [(&str, u32, u32, &[u32]); 9] = [
+            ("*", 1, 5, &[1, 2, 3, 4, 5]),
+            ("3", 0, 59, &[3]),
+            ("1-4", 0, 59, &[1, 2, 3, 4]),
+            ("*/15", 0, 59, &[0, 15, 30, 45]),
+            ("5/15", 0, 59, &[5, 20, 35, 50]),
+            ("5/1", 0, 7, &[5, 6, 7]),
+            ("10-20/5", 0, 59, &[10, 15, 20]),
+            ("1,3-4,9", 0, 59, &[1, 3, 4, 9]),
+            ("*/10", 1, 31, &[1, 11, 21, 31]),
+        ];
+        for (field, min, max, expected) in cases {
+            let mask = parse_field(field, min, max).unwrap();
+            assert_eq!(values(mask), expected, "{}", field);
+        }
+
+        for field in ["", "60", "4-2", "*/0", "a", "1-", "-1", "0/x"] {
+            assert!(parse_field(field, 0, 59).is_err(), "{}", field);
+        }
+        assert!(parse_field("0", 1, 31).is_err());
+    }
+
+    #[test]
+    fn parse() {
+        assert!(Cron::parse("* * * *").is_err());
+        assert!(Cron::parse("* * * * * *").is_err());
+        assert!(Cron::parse("* * * 13 *").is_err());
+        assert!(Cron::parse("@yearly").is_err());
+
+        let cron = Cron::parse(" */5 * * * * ").unwrap();
+        assert_eq!(cron.to_string(), "*/5 * * * *");
+        assert_eq!(serde_json::to_string(&cron).unwrap(), r#""*/5 * * * *""#);
+        let again: Cron = serde_json::from_str(r#""*/5 * * * *""#).unwrap();
+        assert_eq!(again, cron);
+
+        let shorthands = [
+            ("@hourly", "0 * * * *"),
+            ("@daily", "0 0 * * *"),
+            ("@midnight", "0 0 * * *"),
+            ("@weekly", "0 0 * * 0"),
+            ("@monthly", "0 0 1 * *"),
+        ];
+        for (shorthand, expanded) in shorthands {
+            let cron = Cron::parse(shorthand).unwrap();
+            let expanded = Cron::parse(expanded).unwrap();
+            assert_eq!(cron.to_string(), shorthand);
+            assert_eq!(
+                (
+                    cron.minutes,
+                    cron.hours,
+                    cron.days,
+                    cron.months,
+                    cron.weekdays
+                ),
+                (
+                    expanded.minutes,
+                    expanded.hours,
+                    expanded.days,
+                    expanded.months,
+                    expanded.weekdays
+                ),
+                "{}",
+                shorthand
+            );
+        }
+    }
+
+    #[test]
+    fn matches() {
+        let cases = [
+            ("*/15 * * * *", at(2, 10, 30), true),
+            ("*/15 * * * *", at(2, 10, 31), false),
+            ("5/15 * * * *", at(2, 10, 50), true),
+            ("5/15 * * * *", at(2, 10, 0), false),
+            ("0 9-17 * * *", at(2, 17, 0), true),
+            ("0 9-17 * * *", at(2, 18, 0), false),
+            ("@daily", at(2, 0, 0), true),
+            ("@daily", at(2, 0, 1), false),
+            ("@monthly", at(1, 0, 0), true),
+            ("@monthly", at(2, 0, 0), false),
+            // sunday is 0 and 7
+            ("@weekly", at(1, 0, 0), true),
+            ("0 0 * * 7", at(1, 0, 0), true),
+            ("0 0 * * 7", at(2, 0, 0), false),
+            ("0 0 * * 5-7", at(1, 0, 0), true),
+            ("0 0 * * 1-5", at(1, 0, 0), false),
+            // both days restricted, either one is enough
+            ("0 0 15 * 1", at(2, 0, 0), true),
+            ("0 0 15 * 1", at(15, 0, 0), true),
+            ("0 0 15 * 1", at(3, 0, 0), false),
+            // a stepped `*` doesn't restrict, so the other field has to match
+            ("0 0 */2 * 1", at(3, 0, 0), false),
+            ("0 0 */2 * 1", at(9, 0, 0), true),
+            ("0 0 15 * */7", at(1, 0, 0), false),
+            ("0 0 15 * */7", at(15, 0, 0), true),
+            ("0 0 * 11 *", at(1, 0, 0), false),
+        ];
+        for (cron, time, expected) in cases {
+            let parsed = Cron::parse(cron).unwrap();
+            assert_eq!(parsed.matches(&time), expected, "{} at {}", cron, time);
+        }
+    }
+}
@@ -0,0 +1,207 @@
+//! Server list ping for 1.6 and older servers, which only answer the `0xFE 0x01` request.
+//! See <https://wiki.vg/Server_List_Ping#1.6>
+
+use anyhow::anyhow;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Protocol version sent in the request, 74 is 1.6.2
+const REQUEST_PROTOCOL: u8 = 0x4a;
+/// The kick packet is capped at 256 characters by the vanilla server, this leaves plenty of room
+const MAX_RESPONSE_CHARS: usize = 4096;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LegacyResponse {
+    pub protocol: i32,
+    pub version: String,
+    pub motd: String,
+    pub online_players: usize,
+    pub max_players: usize,
+}
+
+pub async fn ping<S>(stream: &mut S, host: &str, port: u16) -> anyhow::Result<LegacyResponse>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    stream.write_all(&build_request(host, port)).await?;
+    stream.flush().await?;
+
+    let packet_id = stream.read_u8().await?;
+    if packet_id != 0xff {
+        return Err(anyhow!("unexpected legacy packet id {:#04x}", packet_id));
+    }
+
+    let len = stream.read_u16().await? as usize;
+    if len > MAX_RESPONSE_CHARS {
+        return Err(anyhow!("legacy response too long ({} chars)", len));
+    }
+
+    let mut buf = vec![0; len * 2];
+    stream.read_exact(&mut buf).await?;
+
+    parse(&decode_utf16(&buf)?)
+}
+
+fn build_request(host: &str, port: u16) -> Vec<u8> {
+    let channel = encode_utf16("MC|PingHost");
+    let host = encode_utf16(host);
+
+    let mut buf = vec![0xfe, 0x01, 0xfa];
+    buf.extend_from_slice(&((channel.len() / 2) as u16).to_be_bytes());
+    buf.extend_from_slice(&channel);
+    buf.extend_from_slice(&((7 + host.len()) as u16).to_be_bytes());
+    buf.push(REQUEST_PROTOCOL);
+    buf.extend_from_slice(&((host.len() / 2) as u16).to_be_bytes());
+    buf.extend_from_slice(&host);
+    buf.extend_from_slice(&(port as i32).to_be_bytes());
+
+    buf
+}
+
+/// Parses the kick string, either the 1.4+ `§1\0protocol\0version\0motd\0online\0max`
+/// format or the beta 1.8 - 1.3 `motd§online§max` one.
+pub fn parse(input: &str) -> anyhow::Result<LegacyResponse> {
+    if let Some(rest) = input.strip_prefix("\u{a7}1\0") {
+        let fields = rest.split('\0').collect::<Vec<_>>();
+        let [protocol, version, motd, online, max] = fields[..] else {
+            return Err(anyhow!("expected 5 legacy fields, got {}", fields.len()));
+        };
+
+        return Ok(LegacyResponse {
+            protocol: protocol.parse()?,
+            version: version.to_string(),
+            motd: motd.to_string(),
+            online_players: online.parse()?,
+            max_players: max.parse()?,
+        });
+    }
+
+    // the motd itself can't contain a section sign here, so the last two are the counts
+    let mut fields = input.rsplitn(3, '\u{a7}');
+    let (Some(max), Some(online), Some(motd)) = (fields.next(), fields.next(), fields.next())
+    else {
+        return Err(anyhow!("unrecognised legacy response"));
+    };
+
+    Ok(LegacyResponse {
+        protocol: -1,
+        version: "Beta 1.8 - 1.3".to_string(),
+        motd: motd.to_string(),
+        online_players: online.parse()?,
+        max_players: max.parse()?,
+    })
+}
+
+fn encode_utf16(input: &str) -> Vec<u8> {
+    input.encode_utf16().flat_map(u16::to_be_bytes).collect()
+}
+
+fn decode_utf16(bytes: &[u8]) -> anyhow::Result<String> {
+    let units = bytes
+        .chunks_exact(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+        .collect::<Vec<_>>();
+
+    Ok(String::from_utf16(&units)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The kick packet a server sends back for `kick`
+    fn kick(kick: &str) -> Vec<u8> {
+        let body = encode_utf16(kick);
+        let mut packet = vec![0xff];
+        packet.extend_from_slice(&((body.len() / 2) as u16).to_be_bytes());
+        packet.extend_from_slice(&body);
+        packet
+    }
+
+    #[test]
+    fn parses_modern_kick() {
+        let response = parse("§1\x0078\x001.6.4\x00§aA Minecraft Server\x003\x0020").unwrap();
+        assert_eq!(
+            response,
+            LegacyResponse {
+                protocol: 78,
+                version: "1.6.4".to_string(),
+                motd: "§aA Minecraft Server".to_string(),
+                online_players: 3,
+                max_players: 20,
+            }
+        );
+
+        for input in [
+            "§1\x0078\x001.6.4\x00motd\x003",
+            "§1\x0078\x001.6.4\x00motd\x003\x0020\x00extra",
+            "§1\x00x\x001.6.4\x00motd\x003\x0020",
+            "§1\x0078\x001.6.4\x00motd\x00-1\x0020",
+        ] {
+            assert!(parse(input).is_err(), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn parses_beta_kick() {
+        let response = parse("A Minecraft Server§3§20").unwrap();
+        assert_eq!(
+            response,
+            LegacyResponse {
+                protocol: -1,
+                version: "Beta 1.8 - 1.3".to_string(),
+                motd: "A Minecraft Server".to_string(),
+                online_players: 3,
+                max_players: 20,
+            }
+        );
+        assert_eq!(parse("§0§0").unwrap().motd, "");
+
+        for input in ["", "A Minecraft Server", "motd§3", "motd§a§20"] {
+            assert!(parse(input).is_err(), "{:?}", input);
+        }
+    }
+
+    #[tokio::test]
+    async fn ping_round_trip() {
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        let reply = kick("§1\x0074\x001.6.2\x00Hello\x000\x0010");
+        let expected = build_request("localhost", 25565);
+
+        let server = tokio::spawn(async move {
+            let mut request = vec![0; expected.len()];
+            server.read_exact(&mut request).await.unwrap();
+            assert_eq!(request, expected);
+            server.write_all(&reply).await.unwrap();
+        });
+
+        let response = ping(&mut client, "localhost", 25565).await.unwrap();
+        server.await.unwrap();
+        assert_eq!(response.version, "1.6.2");
+        assert_eq!(response.max_players, 10);
+    }
+
+    #[tokio::test]
+    async fn rejects_bad_packets() {
+        let mut long = vec![0xff];
+        long.extend_from_slice(&(MAX_RESPONSE_CHARS as u16 + 1).to_be_bytes());
+        let mut truncated = kick("motd§1§2");
+        truncated.pop();
+
+        for (reply, message) in [
+            (vec![0x00, 0x00, 0x00], "unexpected legacy packet id"),
+            (long, "too long"),
+            (truncated, "eof"),
+            // a lone surrogate isn't valid UTF-16
+            (vec![0xff, 0x00, 0x01, 0xd8, 0x00], "utf-16"),
+        ] {
+            let (mut client, mut server) = tokio::io::duplex(8192);
+            server.write_all(&reply).await.unwrap();
+            // nothing more is coming, the request still goes through
+            server.shutdown().await.unwrap();
+
+            let err = ping(&mut client, "localhost", 25565).await.unwrap_err();
+            let err = err.to_string().to_lowercase();
+            assert!(err.contains(message), "expected {}, got {}", message, err);
+        }
+    }
+}
@@ -0,0 +1,312 @@
+//! Login probe, tells online mode, offline mode, whitelisted and modded servers apart
+//! by starting a login and looking at how the server answers.
+//! See <https://wiki.vg/Protocol#Login>
+
+use azalea_protocol::{
+    connect::Connection,
+    packets::login::{
+        serverbound_custom_query_packet::ServerboundCustomQueryPacket, ClientboundLoginPacket,
+        ServerboundLoginPacket,
+    },
+    read::ReadPacketError,
+};
+use tokio::{io::AsyncWriteExt, net::TcpStream};
+
+use crate::util::types::{offline_uuid, OnlineStatus};
+
+/// Name used for the login when `VOYAGER_LOGIN_NAME` isn't set
+pub const DEFAULT_LOGIN_NAME: &str = "Voyager";
+/// The server can send any number of plugin requests before it makes up its mind
+const MAX_PACKETS: usize = 16;
+/// Kick messages containing any of these (lowercased) mean the name isn't whitelisted
+const WHITELIST_PHRASES: &[&str] = &["whitelist", "white-list", "white list"];
+/// Forge and friends kicking a client without the right mods
+const MODDED_PHRASES: &[&str] = &["forge", "fml", "mods", "mod channel", "mod rejection"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoginResponse {
+    pub auth: OnlineStatus,
+    /// Plain text of the kick message, if the server sent one
+    pub disconnect_reason: Option<String>,
+}
+
+impl LoginResponse {
+    fn new(auth: OnlineStatus) -> Self {
+        Self {
+            auth,
+            disconnect_reason: None,
+        }
+    }
+
+    fn disconnected(reason: String) -> Self {
+        let lower = reason.to_lowercase();
+        let mentions = |phrases: &[&str]| phrases.iter().any(|phrase| lower.contains(phrase));
+        let auth = if mentions(WHITELIST_PHRASES) {
+            OnlineStatus::Whitelisted
+        } else if mentions(MODDED_PHRASES) {
+            OnlineStatus::Modded
+        } else {
+            OnlineStatus::Unknown
+        };
+
+        Self {
+            auth,
+            disconnect_reason: Some(reason),
+        }
+    }
+}
+
+/// `protocol` should be the one the server reported in its ping, so we aren't
+/// kicked for being on the wrong version.
+pub async fn login(
+    mut stream: TcpStream,
+    host: &str,
+    port: u16,
+    protocol: i32,
+) -> anyhow::Result<LoginResponse> {
+    let name = std::env::var("VOYAGER_LOGIN_NAME").unwrap_or(DEFAULT_LOGIN_NAME.to_string());
+
+    // login start changed shape a few times, so it's written by hand rather than
+    // through azalea, which only knows the latest one
+    stream
+        .write_all(&build_handshake(host, port, protocol))
+        .await?;
+    stream.write_all(&build_hello(&name, protocol)).await?;
+    stream.flush().await?;
+
+    let mut conn: Connection<ClientboundLoginPacket, ServerboundLoginPacket> =
+        Connection::wrap(stream);
+
+    for _ in 0..MAX_PACKETS {
+        let packet = match conn.read().await {
+            Ok(packet) => packet,
+            Err(e) => match answered_by(&e) {
+                Some(auth) => return Ok(LoginResponse::new(auth)),
+                None => return Err(e.into()),
+            },
+        };
+
+        match packet {
+            ClientboundLoginPacket::Hello(_) => {
+                return Ok(LoginResponse::new(OnlineStatus::Online))
+            }
+            ClientboundLoginPacket::GameProfile(_) => {
+                return Ok(LoginResponse::new(OnlineStatus::Offline))
+            }
+            ClientboundLoginPacket::LoginDisconnect(p) => {
+                return Ok(LoginResponse::disconnected(p.reason.to_string()))
+            }
+            ClientboundLoginPacket::LoginCompression(p) => {
+                conn.set_compression_threshold(p.compression_threshold)
+            }
+            ClientboundLoginPacket::CustomQuery(p) => {
+                // forge asks for its mod list here and won't let us in without one
+                if p.identifier.namespace == "fml" || p.identifier.namespace == "forge" {
+                    return Ok(LoginResponse::new(OnlineStatus::Modded));
+                }
+
+                let reply = ServerboundCustomQueryPacket {
+                    transaction_id: p.transaction_id,
+                    data: None,
+                };
+                conn.write(reply.get()).await?;
+            }
+        }
+    }
+
+    Ok(LoginResponse::new(OnlineStatus::Unknown))
+}
+
+/// azalea only parses the latest version of each packet, but for the encryption
+/// request and login success the packet id alone is enough to go on.
+fn answered_by(e: &ReadPacketError) -> Option<OnlineStatus> {
+    let name = match e {
+        ReadPacketError::Parse { packet_name, .. } => packet_name,
+        ReadPacketError::LeftoverData { packet_name, .. } => packet_name,
+        _ => return None,
+    };
+
+    match name.as_str() {
+        "ClientboundHelloPacket" => Some(OnlineStatus::Online),
+        "ClientboundGameProfilePacket" => Some(OnlineStatus::Offline),
+        _ => None,
+    }
+}
+
+fn build_handshake(host: &str, port: u16, protocol: i32) -> Vec<u8> {
+    let mut data = vec![0x00];
+    write_varint(&mut data, protocol);
+    write_string(&mut data, host);
+    data.extend_from_slice(&port.to_be_bytes());
+    // next state: login
+    write_varint(&mut data, 2);
+
+    frame(data)
+}
+
+fn build_hello(name: &str, protocol: i32) -> Vec<u8> {
+    let mut data = vec![0x00];
+    write_string(&mut data, name);
+
+    match protocol {
+        // 1.20.2+, uuid is required
+        764.. => data.extend_from_slice(offline_uuid(name).as_bytes()),
+        // 1.19.3 - 1.20.1, optional uuid
+        761..=763 => data.push(0),
+        // 1.19.1 - 1.19.2, optional signature data and optional uuid
+        760 => data.extend_from_slice(&[0, 0]),
+        // 1.19, optional signature data
+        759 => data.push(0),
+        _ => {}
+    }
+
+    frame(data)
+}
+
+/// Prefixes the packet with its length, compression is never on this early
+fn frame(data: Vec<u8>) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(data.len() + 5);
+    write_varint(&mut buf, data.len() as i32);
+    buf.extend_from_slice(&data);
+
+    buf
+}
+
+fn write_string(buf: &mut Vec<u8>, input: &str) {
+    write_varint(buf, input.len() as i32);
+    buf.extend_from_slice(input.as_bytes());
+}
+
+fn write_varint(buf: &mut Vec<u8>, value: i32) {
+    let mut value = value as u32;
+    loop {
+        if value & !0x7f == 0 {
+            buf.push(value as u8);
+            return;
+        }
+
+        buf.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{io::AsyncReadExt, net::TcpListener};
+
+    use super::*;
+
+    /// Answers one login with `packets` and keeps the connection open until the
+    /// probe hangs up
+    async fn probe(packets: Vec<Vec<u8>>) -> LoginResponse {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 512];
+            // the handshake and login start, their contents don't matter here
+            assert!(socket.read(&mut buf).await.unwrap() > 0);
+            for packet in packets {
+                socket.write_all(&packet).await.unwrap();
+            }
+            while socket.read(&mut buf).await.unwrap_or(0) > 0 {}
+        });
+
+        let stream = TcpStream::connect(addr).await.unwrap();
+        login(stream, "localhost", addr.port(), 763).await.unwrap()
+    }
+
+    fn disconnect(reason: &str) -> Vec<u8> {
+        let mut data = vec![0x00];
+        write_string(
+            &mut data,
+            &serde_json::json!({ "text": reason }).to_string(),
+        );
+        frame(data)
+    }
+
+    fn encryption_request() -> Vec<u8> {
+        let mut data = vec![0x01];
+        write_string(&mut data, "");
+        write_varint(&mut data, 4);
+        data.extend_from_slice(b"abcd");
+        write_varint(&mut data, 4);
+        data.extend_from_slice(b"wxyz");
+        frame(data)
+    }
+
+    fn login_success() -> Vec<u8> {
+        let mut data = vec![0x02];
+        data.extend_from_slice(offline_uuid(DEFAULT_LOGIN_NAME).as_bytes());
+        write_string(&mut data, DEFAULT_LOGIN_NAME);
+        // no properties
+        write_varint(&mut data, 0);
+        frame(data)
+    }
+
+    fn custom_query(identifier: &str) -> Vec<u8> {
+        let mut data = vec![0x04];
+        write_varint(&mut data, 1);
+        write_string(&mut data, identifier);
+        frame(data)
+    }
+
+    #[tokio::test]
+    async fn encryption_request_is_online() {
+        let res = probe(vec![encryption_request()]).await;
+        assert_eq!(res, LoginResponse::new(OnlineStatus::Online));
+    }
+
+    #[tokio::test]
+    async fn login_success_is_offline() {
+        let res = probe(vec![login_success()]).await;
+        assert_eq!(res, LoginResponse::new(OnlineStatus::Offline));
+    }
+
+    #[tokio::test]
+    async fn whitelist_kick_is_whitelisted() {
+        for reason in [
+            "You are not white-listed on this server!",
+            "You are not whitelisted on this server!",
+            "Sorry, you're not on the White List",
+        ] {
+            let res = probe(vec![disconnect(reason)]).await;
+            assert_eq!(res.auth, OnlineStatus::Whitelisted, "{}", reason);
+            assert_eq!(res.disconnect_reason.as_deref(), Some(reason));
+        }
+    }
+
+    #[tokio::test]
+    async fn mod_kick_is_modded() {
+        for reason in [
+            "This server has mods that require Forge to be installed on the client",
+            "Mismatched mod channel list",
+            "FML handshake failed",
+            "Server Mod rejections:\nexamplemod: missing",
+        ] {
+            let res = probe(vec![disconnect(reason)]).await;
+            assert_eq!(res.auth, OnlineStatus::Modded, "{}", reason);
+        }
+    }
+
+    #[tokio::test]
+    async fn other_kick_is_unknown() {
+        let reason = "Server is restarting";
+        let res = probe(vec![disconnect(reason)]).await;
+        assert_eq!(res.auth, OnlineStatus::Unknown);
+        assert_eq!(res.disconnect_reason.as_deref(), Some(reason));
+    }
+
+    #[tokio::test]
+    async fn forge_query_is_modded() {
+        let res = probe(vec![custom_query("fml:loginwrapper")]).await;
+        assert_eq!(res, LoginResponse::new(OnlineStatus::Modded));
+    }
+
+    #[tokio::test]
+    async fn other_queries_are_answered() {
+        let res = probe(vec![custom_query("velocity:player_info"), login_success()]).await;
+        assert_eq!(res, LoginResponse::new(OnlineStatus::Offline));
+    }
+}
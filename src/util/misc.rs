@@ -72,14 +72,22 @@ pub async fn upload_image(bytes: Vec<u8>) -> anyhow::Result<String> {
     Ok(ret_url)
 }
 
-pub fn decode_favicon(favicon: String) -> Vec<u8> {
-    let favicon = favicon.replace("data:image/png;base64,", "");
-    let bytes = favicon.into_bytes();
-
-    match general_purpose::STANDARD.decode(bytes) {
-        Ok(bytes) => bytes,
-        Err(_) => vec![],
-    }
+/// Servers send a data url, but what's stored is plain base64 with or without padding.
+/// `None` if it isn't base64.
+pub fn decode_favicon(favicon: &str) -> Option<Vec<u8>> {
+    let favicon = match favicon.strip_prefix("data:") {
+        Some(url) => url.split_once(',')?.1,
+        None => favicon,
+    };
+    let favicon = favicon
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>();
+
+    // padding is optional, but only ever at the end
+    general_purpose::STANDARD_NO_PAD
+        .decode(favicon.trim_end_matches('='))
+        .ok()
 }
 
 pub enum WHLog {
@@ -115,3 +123,48 @@ pub async fn wh_send(level: WHLog, msg: &str, username: Option<&str>) {
         .await
         .expect("Could not execute webhook.");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn favicons_decode_with_or_without_padding() {
+        let bytes = b"\x89PNG\r\n".to_vec();
+        for favicon in [
+            "iVBORw0K",
+            "iVBORw0K==",
+            "data:image/png;base64,iVBORw0K",
+            "data:image/png;base64,iVBORw0K==",
+            "iVBO\nRw0K\n",
+        ] {
+            assert_eq!(
+                decode_favicon(favicon),
+                Some(bytes.clone()),
+                "{:?}",
+                favicon
+            );
+        }
+
+        assert_eq!(
+            decode_favicon("iVBORw0KGgo="),
+            Some(b"\x89PNG\r\n\x1a\n".to_vec())
+        );
+        assert_eq!(
+            decode_favicon("iVBORw0KGgo"),
+            Some(b"\x89PNG\r\n\x1a\n".to_vec())
+        );
+    }
+
+    #[test]
+    fn favicons_that_arent_base64() {
+        for favicon in [
+            "not base64!",
+            "iVBO=Rw0K",
+            "iVBORw0KG",
+            "data:image/png;base64",
+        ] {
+            assert_eq!(decode_favicon(favicon), None, "{:?}", favicon);
+        }
+    }
+}
@@ -1,3 +1,11 @@
+pub mod bedrock;
+pub mod chat;
+pub mod cron;
+pub mod legacy;
+pub mod login;
 pub mod logs;
 pub mod misc;
+pub mod png;
+pub mod query;
+pub mod resolver;
 pub mod types;
@@ -0,0 +1,841 @@
+//! Just enough of a PNG codec to look at server favicons, and the perceptual hash
+//! that finds icons that look alike.
+//!
+//! Every colour type, bit depth and Adam7 interlacing is decoded, ancillary chunks
+//! other than `tRNS` are ignored and checksums aren't checked.
+
+use std::io::Read;
+
+use anyhow::anyhow;
+use flate2::read::ZlibDecoder;
+
+pub const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+/// Favicons are 64x64, anything past this isn't worth decoding
+const MAX_DIMENSION: u32 = 1024;
+/// Side of the grey image the perceptual hash is taken from, and of the corner of
+/// its DCT that ends up in the hash
+const PHASH_SIZE: usize = 32;
+const PHASH_BITS: usize = 8;
+
+/// (x start, y start, x step, y step) of each Adam7 pass
+const ADAM7: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+/// A decoded image, 8 bit RGBA row by row
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Header {
+    width: usize,
+    height: usize,
+    bit_depth: u8,
+    color_type: u8,
+    interlaced: bool,
+}
+
+impl Header {
+    fn channels(&self) -> usize {
+        match self.color_type {
+            2 => 3,
+            4 => 2,
+            6 => 4,
+            _ => 1,
+        }
+    }
+
+    /// Bytes per complete pixel, rounded up to 1, which is what filters look back by
+    fn filter_bpp(&self) -> usize {
+        (self.channels() * self.bit_depth as usize + 7) / 8
+    }
+
+    fn stride(&self, width: usize) -> usize {
+        (width * self.channels() * self.bit_depth as usize + 7) / 8
+    }
+
+    /// Size of the filtered image data, a filter byte per row of each pass
+    fn data_len(&self) -> usize {
+        self.passes()
+            .into_iter()
+            .filter(|(w, h, ..)| *w > 0 && *h > 0)
+            .map(|(w, h, ..)| (self.stride(w) + 1) * h)
+            .sum()
+    }
+
+    /// (width, height, x start, y start, x step, y step) of each pass, one for
+    /// images that aren't interlaced
+    fn passes(&self) -> Vec<(usize, usize, usize, usize, usize, usize)> {
+        if !self.interlaced {
+            return vec![(self.width, self.height, 0, 0, 1, 1)];
+        }
+
+        ADAM7
+            .iter()
+            .map(|&(x0, y0, dx, dy)| {
+                let w = (self.width + dx - 1 - x0) / dx;
+                let h = (self.height + dy - 1 - y0) / dy;
+                (w, h, x0, y0, dx, dy)
+            })
+            .collect()
+    }
+}
+
+impl Image {
+    pub fn decode(png: &[u8]) -> anyhow::Result<Self> {
+        let Some(mut rest) = png.strip_prefix(&SIGNATURE) else {
+            return Err(anyhow!("missing PNG signature"));
+        };
+
+        let mut header = None;
+        let mut palette = Vec::new();
+        let mut transparency = Vec::new();
+        let mut idat = Vec::new();
+
+        loop {
+            if rest.len() < 12 {
+                return Err(anyhow!("PNG ends before IEND"));
+            }
+            let len = u32::from_be_bytes(rest[..4].try_into()?) as usize;
+            let kind = &rest[4..8];
+            let Some(data) = rest.get(8..8 + len) else {
+                return Err(anyhow!("truncated {} chunk", String::from_utf8_lossy(kind)));
+            };
+            rest = rest.get(12 + len..).unwrap_or_default();
+
+            if header.is_none() && kind != b"IHDR" {
+                return Err(anyhow!("PNG doesn't start with IHDR"));
+            }
+            match kind {
+                b"IHDR" => header = Some(parse_header(data)?),
+                b"PLTE" => palette = data.to_vec(),
+                b"tRNS" => transparency = data.to_vec(),
+                b"IDAT" => idat.extend_from_slice(data),
+                b"IEND" => break,
+                _ => {}
+            }
+        }
+
+        let header = header.ok_or_else(|| anyhow!("PNG has no IHDR"))?;
+        if header.color_type == 3 && palette.is_empty() {
+            return Err(anyhow!("paletted PNG has no PLTE"));
+        }
+
+        // never inflate more than the image can hold, so a tiny file can't expand
+        // into gigabytes
+        let expected = header.data_len();
+        let mut filtered = Vec::with_capacity(expected);
+        ZlibDecoder::new(idat.as_slice())
+            .take(expected as u64)
+            .read_to_end(&mut filtered)?;
+        if filtered.len() < expected {
+            return Err(anyhow!("PNG image data is too short"));
+        }
+
+        let mut rgba = vec![0; header.width * header.height * 4];
+        let mut offset = 0;
+        for (w, h, x0, y0, dx, dy) in header.passes() {
+            if w == 0 || h == 0 {
+                continue;
+            }
+            let len = (header.stride(w) + 1) * h;
+            let rows = unfilter(&filtered[offset..offset + len], &header, w, h)?;
+            offset += len;
+
+            for (y, row) in rows.chunks(header.stride(w)).enumerate() {
+                for x in 0..w {
+                    let pixel = pixel(row, x, &header, &palette, &transparency);
+                    let i = ((y0 + y * dy) * header.width + x0 + x * dx) * 4;
+                    rgba[i..i + 4].copy_from_slice(&pixel);
+                }
+            }
+        }
+
+        Ok(Self {
+            width: header.width as u32,
+            height: header.height as u32,
+            rgba,
+        })
+    }
+
+    /// 64 bit perceptual hash: the image is shrunk to 32x32 greys, and each bit is
+    /// whether one of the 8x8 lowest frequencies of its DCT is above their median.
+    /// That keeps the overall shape, so re-saving, recolouring or a little cropping
+    /// flips few bits.
+    pub fn phash(&self) -> u64 {
+        let grey = self.resize_grey(PHASH_SIZE, PHASH_SIZE);
+
+        let mut cos = [[0.0; PHASH_SIZE]; PHASH_BITS];
+        for (u, row) in cos.iter_mut().enumerate() {
+            for (x, value) in row.iter_mut().enumerate() {
+                let angle = (2 * x + 1) as f64 * u as f64 * std::f64::consts::PI;
+                *value = (angle / (2 * PHASH_SIZE) as f64).cos();
+            }
+        }
+
+        // the DCT is separable, rows first then columns, only keeping the corner
+        let mut rows = [[0.0; PHASH_BITS]; PHASH_SIZE];
+        for (y, row) in rows.iter_mut().enumerate() {
+            for (u, value) in row.iter_mut().enumerate() {
+                *value = (0..PHASH_SIZE)
+                    .map(|x| grey[y * PHASH_SIZE + x] * cos[u][x])
+                    .sum();
+            }
+        }
+        let mut coefficients = Vec::with_capacity(PHASH_BITS * PHASH_BITS);
+        for cos in &cos {
+            for u in 0..PHASH_BITS {
+                let value = (0..PHASH_SIZE).map(|y| rows[y][u] * cos[y]).sum::<f64>();
+                // rounding error, so every flat icon hashes the same rather than to noise
+                coefficients.push(if value.abs() < 1e-6 { 0.0 } else { value });
+            }
+        }
+
+        // the first one is the average brightness, which would skew the median
+        let mut sorted = coefficients[1..].to_vec();
+        sorted.sort_by(f64::total_cmp);
+        let median = sorted[sorted.len() / 2];
+
+        coefficients
+            .iter()
+            .fold(0, |hash, &c| (hash << 1) | (c > median) as u64)
+    }
+
+    /// Averages the image into `width` x `height` luma values, each covering at least
+    /// one pixel so small images are stretched. Transparent pixels count as black.
+    fn resize_grey(&self, width: usize, height: usize) -> Vec<f64> {
+        let (src_w, src_h) = (self.width as usize, self.height as usize);
+        let span = |cell: usize, cells: usize, size: usize| {
+            let start = cell * size / cells;
+            start..((cell + 1) * size / cells).max(start + 1)
+        };
+
+        let mut out = Vec::with_capacity(width * height);
+        for cy in 0..height {
+            for cx in 0..width {
+                let (xs, ys) = (span(cx, width, src_w), span(cy, height, src_h));
+                let count = (xs.len() * ys.len()) as f64;
+                let sum = ys
+                    .flat_map(|y| xs.clone().map(move |x| (y * src_w + x) * 4))
+                    .map(|i| {
+                        let px = &self.rgba[i..i + 4];
+                        let luma =
+                            0.299 * px[0] as f64 + 0.587 * px[1] as f64 + 0.114 * px[2] as f64;
+                        luma * px[3] as f64 / 255.0
+                    })
+                    .sum::<f64>();
+                out.push(sum / count);
+            }
+        }
+
+        out
+    }
+}
+
+/// Width and height from the header, without decoding the rest. `None` if it doesn't
+/// start like a PNG.
+pub fn dimensions(png: &[u8]) -> Option<(u32, u32)> {
+    let rest = png.strip_prefix(&SIGNATURE)?;
+    if rest.get(4..8)? != b"IHDR" {
+        return None;
+    }
+
+    let width = u32::from_be_bytes(rest.get(8..12)?.try_into().ok()?);
+    let height = u32::from_be_bytes(rest.get(12..16)?.try_into().ok()?);
+    Some((width, height))
+}
+
+fn parse_header(data: &[u8]) -> anyhow::Result<Header> {
+    if data.len() != 13 {
+        return Err(anyhow!("IHDR is {} bytes long", data.len()));
+    }
+
+    let width = u32::from_be_bytes(data[0..4].try_into()?);
+    let height = u32::from_be_bytes(data[4..8].try_into()?);
+    if width == 0 || height == 0 || width > MAX_DIMENSION || height > MAX_DIMENSION {
+        return Err(anyhow!("unsupported PNG size {}x{}", width, height));
+    }
+
+    let (bit_depth, color_type) = (data[8], data[9]);
+    let valid = match color_type {
+        0 => matches!(bit_depth, 1 | 2 | 4 | 8 | 16),
+        3 => matches!(bit_depth, 1 | 2 | 4 | 8),
+        2 | 4 | 6 => matches!(bit_depth, 8 | 16),
+        _ => false,
+    };
+    if !valid {
+        return Err(anyhow!(
+            "invalid PNG colour type {} with bit depth {}",
+            color_type,
+            bit_depth
+        ));
+    }
+    if data[10] != 0 || data[11] != 0 || data[12] > 1 {
+        return Err(anyhow!(
+            "unknown PNG compression, filter or interlace method"
+        ));
+    }
+
+    Ok(Header {
+        width: width as usize,
+        height: height as usize,
+        bit_depth,
+        color_type,
+        interlaced: data[12] == 1,
+    })
+}
+
+/// Undoes the per row filters of one pass, returning its rows back to back without
+/// the filter bytes
+fn unfilter(data: &[u8], header: &Header, width: usize, height: usize) -> anyhow::Result<Vec<u8>> {
+    let stride = header.stride(width);
+    let bpp = header.filter_bpp();
+    let mut out = vec![0u8; stride * height];
+
+    for y in 0..height {
+        let filter = data[y * (stride + 1)];
+        let line = &data[y * (stride + 1) + 1..(y + 1) * (stride + 1)];
+        let (done, current) = out.split_at_mut(y * stride);
+        let prev = if y > 0 {
+            &done[(y - 1) * stride..]
+        } else {
+            &[][..]
+        };
+        let row = &mut current[..stride];
+
+        for x in 0..stride {
+            let a = if x >= bpp { row[x - bpp] } else { 0 };
+            let b = prev.get(x).copied().unwrap_or(0);
+            let c = if x >= bpp {
+                prev.get(x - bpp).copied().unwrap_or(0)
+            } else {
+                0
+            };
+
+            row[x] = match filter {
+                0 => line[x],
+                1 => line[x].wrapping_add(a),
+                2 => line[x].wrapping_add(b),
+                3 => line[x].wrapping_add(((a as u16 + b as u16) / 2) as u8),
+                4 => line[x].wrapping_add(paeth(a, b, c)),
+                other => return Err(anyhow!("unknown PNG filter {}", other)),
+            };
+        }
+    }
+
+    Ok(out)
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = (
+        (p - a as i16).abs(),
+        (p - b as i16).abs(),
+        (p - c as i16).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// The `x`th pixel of an unfiltered row as 8 bit RGBA
+fn pixel(row: &[u8], x: usize, header: &Header, palette: &[u8], transparency: &[u8]) -> [u8; 4] {
+    let depth = header.bit_depth as usize;
+    let channels = header.channels();
+
+    // each sample scaled to 8 bits, and at full precision for matching tRNS
+    let sample = |channel: usize| -> (u8, u16) {
+        let index = x * channels + channel;
+        match depth {
+            16 => {
+                let value = u16::from_be_bytes([row[index * 2], row[index * 2 + 1]]);
+                ((value >> 8) as u8, value)
+            }
+            8 => (row[index], row[index] as u16),
+            _ => {
+                let bit = index * depth;
+                let value = (row[bit / 8] >> (8 - depth - bit % 8)) & ((1 << depth) - 1);
+                let scaled = if header.color_type == 3 {
+                    value
+                } else {
+                    value * (255 / ((1 << depth) - 1))
+                };
+                (scaled, value as u16)
+            }
+        }
+    };
+    // tRNS holds one 16 bit value per channel for grey and RGB images
+    let key = |channel: usize| -> Option<u16> {
+        let bytes = transparency.get(channel * 2..channel * 2 + 2)?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    };
+
+    match header.color_type {
+        0 => {
+            let (grey, raw) = sample(0);
+            let alpha = if key(0) == Some(raw) { 0 } else { 255 };
+            [grey, grey, grey, alpha]
+        }
+        2 => {
+            let (r, g, b) = (sample(0), sample(1), sample(2));
+            let transparent = key(0) == Some(r.1) && key(1) == Some(g.1) && key(2) == Some(b.1);
+            [r.0, g.0, b.0, if transparent { 0 } else { 255 }]
+        }
+        3 => {
+            let index = sample(0).0 as usize;
+            let rgb = palette.get(index * 3..index * 3 + 3).unwrap_or(&[0, 0, 0]);
+            let alpha = transparency.get(index).copied().unwrap_or(255);
+            [rgb[0], rgb[1], rgb[2], alpha]
+        }
+        4 => {
+            let grey = sample(0).0;
+            [grey, grey, grey, sample(1).0]
+        }
+        _ => [sample(0).0, sample(1).0, sample(2).0, sample(3).0],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{write::ZlibEncoder, Compression, Crc};
+
+    use crate::database::similarity::{DEFAULT_DISTANCE, MAX_DISTANCE};
+
+    use super::*;
+
+    fn ihdr(width: u32, height: u32, bit_depth: u8, color_type: u8, interlace: u8) -> Vec<u8> {
+        let mut data = width.to_be_bytes().to_vec();
+        data.extend_from_slice(&height.to_be_bytes());
+        data.extend_from_slice(&[bit_depth, color_type, 0, 0, interlace]);
+        data
+    }
+
+    fn zlib(data: &[u8]) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn build(chunks: &[(&[u8; 4], Vec<u8>)]) -> Vec<u8> {
+        let mut png = SIGNATURE.to_vec();
+        for (kind, data) in chunks {
+            let mut crc = Crc::new();
+            crc.update(&kind[..]);
+            crc.update(data);
+
+            png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            png.extend_from_slice(&kind[..]);
+            png.extend_from_slice(data);
+            png.extend_from_slice(&crc.sum().to_be_bytes());
+        }
+        png
+    }
+
+    /// A PNG with `extra` chunks between IHDR and IDAT, `data` is already filtered
+    fn png(header: Vec<u8>, extra: &[(&[u8; 4], Vec<u8>)], data: &[u8]) -> Vec<u8> {
+        let mut chunks = vec![(b"IHDR", header)];
+        chunks.extend_from_slice(extra);
+        chunks.push((b"IDAT", zlib(data)));
+        chunks.push((b"IEND", Vec::new()));
+        build(&chunks)
+    }
+
+    /// Rows with filter type 0 in front of each
+    fn unfiltered(rows: &[&[u8]]) -> Vec<u8> {
+        rows.iter()
+            .flat_map(|row| [&[0][..], row].concat())
+            .collect()
+    }
+
+    fn pixels(image: &Image) -> Vec<[u8; 4]> {
+        image
+            .rgba
+            .chunks_exact(4)
+            .map(|px| px.try_into().unwrap())
+            .collect()
+    }
+
+    fn grey(values: &[u8]) -> Vec<[u8; 4]> {
+        values.iter().map(|&g| [g, g, g, 255]).collect()
+    }
+
+    #[test]
+    fn greyscale_at_every_depth() {
+        let cases: [(u8, &[u8], &[u8]); 5] = [
+            (1, &[0b0100_0000], &[0, 255]),
+            (2, &[0b0001_1100], &[0, 85, 255]),
+            (4, &[0x7f], &[119, 255]),
+            (8, &[0x12, 0xff], &[0x12, 0xff]),
+            (16, &[0x12, 0x34, 0xff, 0x00], &[0x12, 0xff]),
+        ];
+
+        for (depth, row, expected) in cases {
+            let width = expected.len() as u32;
+            let image = Image::decode(&png(ihdr(width, 1, depth, 0, 0), &[], &unfiltered(&[row])))
+                .unwrap_or_else(|e| panic!("{} bit: {}", depth, e));
+            assert_eq!((image.width, image.height), (width, 1));
+            assert_eq!(pixels(&image), grey(expected), "{} bit", depth);
+        }
+    }
+
+    #[test]
+    fn palette_at_every_depth() {
+        let palette = vec![255, 0, 0, 0, 255, 0, 0, 0, 255, 255, 255, 255];
+        let alpha = vec![0, 128];
+        let expected = [
+            [255, 0, 0, 0],
+            [0, 255, 0, 128],
+            [0, 0, 255, 255],
+            [255, 255, 255, 255],
+        ];
+        let cases: [(u8, &[u8], usize); 4] = [
+            (1, &[0b0100_0000], 2),
+            (2, &[0b0001_1011], 4),
+            (4, &[0x01, 0x23], 4),
+            (8, &[0, 1, 2, 3], 4),
+        ];
+
+        for (depth, row, width) in cases {
+            let extra = [(b"PLTE", palette.clone()), (b"tRNS", alpha.clone())];
+            let data = png(
+                ihdr(width as u32, 1, depth, 3, 0),
+                &extra,
+                &unfiltered(&[row]),
+            );
+            let image = Image::decode(&data).unwrap_or_else(|e| panic!("{} bit: {}", depth, e));
+            assert_eq!(pixels(&image), expected[..width], "{} bit", depth);
+        }
+
+        // an index past the palette is black rather than a panic
+        let extra = [(b"PLTE", palette)];
+        let image = Image::decode(&png(ihdr(1, 1, 8, 3, 0), &extra, &unfiltered(&[&[9]]))).unwrap();
+        assert_eq!(pixels(&image), [[0, 0, 0, 255]]);
+    }
+
+    #[test]
+    fn truecolour_and_alpha_at_both_depths() {
+        let cases: [(u8, u8, &[u8], [u8; 4]); 6] = [
+            (2, 8, &[1, 2, 3], [1, 2, 3, 255]),
+            (2, 16, &[1, 0xff, 2, 0, 3, 0], [1, 2, 3, 255]),
+            (4, 8, &[7, 9], [7, 7, 7, 9]),
+            (4, 16, &[7, 0, 9, 0xff], [7, 7, 7, 9]),
+            (6, 8, &[1, 2, 3, 4], [1, 2, 3, 4]),
+            (6, 16, &[1, 0, 2, 0, 3, 0, 4, 0xff], [1, 2, 3, 4]),
+        ];
+
+        for (color_type, depth, row, expected) in cases {
+            let data = png(ihdr(1, 1, depth, color_type, 0), &[], &unfiltered(&[row]));
+            let image = Image::decode(&data)
+                .unwrap_or_else(|e| panic!("type {} {} bit: {}", color_type, depth, e));
+            assert_eq!(
+                pixels(&image),
+                [expected],
+                "type {} {} bit",
+                color_type,
+                depth
+            );
+        }
+    }
+
+    #[test]
+    fn transparency_keys_match_at_full_precision() {
+        let extra = [(b"tRNS", vec![0, 0x12])];
+        let data = png(ihdr(2, 1, 8, 0, 0), &extra, &unfiltered(&[&[0x12, 0x13]]));
+        assert_eq!(
+            pixels(&Image::decode(&data).unwrap()),
+            [[0x12, 0x12, 0x12, 0], [0x13, 0x13, 0x13, 255]]
+        );
+
+        // both scale to 0x12, only the exact sample is transparent
+        let extra = [(b"tRNS", vec![0x12, 0x34])];
+        let row = [0x12, 0x34, 0x12, 0x00];
+        let data = png(ihdr(2, 1, 16, 0, 0), &extra, &unfiltered(&[&row]));
+        assert_eq!(
+            pixels(&Image::decode(&data).unwrap()),
+            [[0x12, 0x12, 0x12, 0], [0x12, 0x12, 0x12, 255]]
+        );
+
+        let extra = [(b"tRNS", vec![0, 1, 0, 2, 0, 3])];
+        let data = png(
+            ihdr(2, 1, 8, 2, 0),
+            &extra,
+            &unfiltered(&[&[1, 2, 3, 1, 2, 4]]),
+        );
+        assert_eq!(
+            pixels(&Image::decode(&data).unwrap()),
+            [[1, 2, 3, 0], [1, 2, 4, 255]]
+        );
+    }
+
+    #[test]
+    fn every_filter_type() {
+        // 2x4 RGB, each row filtered with sub, up, average and paeth in turn
+        let data = [
+            &[1, 10, 20, 30, 5, 5, 5][..],
+            &[2, 1, 1, 1, 1, 1, 1],
+            &[3, 15, 30, 45, 12, 17, 22],
+            &[4, 5, 5, 5, 5, 5, 5],
+        ]
+        .concat();
+        let image = Image::decode(&png(ihdr(2, 4, 8, 2, 0), &[], &data)).unwrap();
+
+        let rows: Vec<[u8; 3]> = pixels(&image)
+            .into_iter()
+            .map(|px| [px[0], px[1], px[2]])
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                [10, 20, 30],
+                [15, 25, 35],
+                [11, 21, 31],
+                [16, 26, 36],
+                [20, 40, 60],
+                [30, 50, 70],
+                [25, 45, 65],
+                [35, 55, 75],
+            ]
+        );
+    }
+
+    #[test]
+    fn interlaced_matches_plain() {
+        for (width, height) in [(1, 1), (3, 3), (8, 8), (13, 5)] {
+            let value = |x: usize, y: usize| (y * width + x) as u8;
+            let rows: Vec<Vec<u8>> = (0..height)
+                .map(|y| (0..width).map(|x| value(x, y)).collect())
+                .collect();
+            let rows: Vec<&[u8]> = rows.iter().map(|row| row.as_slice()).collect();
+            let plain = png(
+                ihdr(width as u32, height as u32, 8, 0, 0),
+                &[],
+                &unfiltered(&rows),
+            );
+
+            // each pass is its own little image, passes without pixels are left out
+            let mut data = Vec::new();
+            for (x0, y0, dx, dy) in ADAM7 {
+                let xs: Vec<usize> = (x0..width).step_by(dx).collect();
+                if xs.is_empty() {
+                    continue;
+                }
+                for y in (y0..height).step_by(dy) {
+                    data.push(0);
+                    data.extend(xs.iter().map(|&x| value(x, y)));
+                }
+            }
+            let interlaced = png(ihdr(width as u32, height as u32, 8, 0, 1), &[], &data);
+
+            assert_eq!(
+                Image::decode(&interlaced).unwrap(),
+                Image::decode(&plain).unwrap(),
+                "{}x{}",
+                width,
+                height
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        let valid = png(ihdr(2, 2, 8, 0, 0), &[], &unfiltered(&[&[1, 2], &[3, 4]]));
+        assert!(Image::decode(&valid).is_ok());
+
+        let idat = |data: &[u8]| (b"IDAT", zlib(data));
+        let iend = (b"IEND", Vec::new());
+        let mut huge = valid[..SIGNATURE.len() + 25].to_vec();
+        huge.extend_from_slice(&u32::MAX.to_be_bytes());
+        huge.extend_from_slice(b"IDAT");
+        huge.extend_from_slice(&[0; 16]);
+
+        let cases: Vec<(&str, Vec<u8>)> = vec![
+            ("empty", Vec::new()),
+            ("not a png", b"GIF89a\x01\x00\x01\x00".to_vec()),
+            ("truncated", valid[..valid.len() - 20].to_vec()),
+            ("no IEND", valid[..valid.len() - 12].to_vec()),
+            ("chunk longer than the file", huge),
+            (
+                "IDAT first",
+                build(&[
+                    idat(&[0, 1, 2]),
+                    (b"IHDR", ihdr(2, 1, 8, 0, 0)),
+                    iend.clone(),
+                ]),
+            ),
+            ("short IHDR", build(&[(b"IHDR", vec![0; 12]), iend.clone()])),
+            ("zero width", png(ihdr(0, 1, 8, 0, 0), &[], &[0])),
+            (
+                "too big",
+                png(ihdr(MAX_DIMENSION + 1, 1, 8, 0, 0), &[], &[0]),
+            ),
+            ("4 bit RGB", png(ihdr(1, 1, 4, 2, 0), &[], &[0, 0, 0])),
+            ("16 bit palette", png(ihdr(1, 1, 16, 3, 0), &[], &[0, 0, 0])),
+            ("colour type 7", png(ihdr(1, 1, 8, 7, 0), &[], &[0, 0])),
+            ("interlace method 2", png(ihdr(1, 1, 8, 0, 2), &[], &[0, 0])),
+            ("filter type 5", png(ihdr(1, 1, 8, 0, 0), &[], &[5, 0])),
+            (
+                "missing rows",
+                png(ihdr(2, 2, 8, 0, 0), &[], &unfiltered(&[&[1, 2]])),
+            ),
+            (
+                "palette without PLTE",
+                png(ihdr(1, 1, 8, 3, 0), &[], &[0, 0]),
+            ),
+            (
+                "not zlib",
+                build(&[
+                    (b"IHDR", ihdr(1, 1, 8, 0, 0)),
+                    (b"IDAT", b"definitely not deflate".to_vec()),
+                    iend,
+                ]),
+            ),
+        ];
+
+        for (name, data) in cases {
+            assert!(Image::decode(&data).is_err(), "{} decoded", name);
+        }
+    }
+
+    #[test]
+    fn dimensions_come_from_the_header() {
+        let data = png(ihdr(64, 32, 8, 6, 0), &[], &[]);
+        assert_eq!(dimensions(&data), Some((64, 32)));
+        assert_eq!(dimensions(&SIGNATURE), None);
+        assert_eq!(dimensions(b"not a png at all, but long enough"), None);
+    }
+
+    fn image(width: u32, height: u32, colour: impl Fn(u32, u32) -> [u8; 4]) -> Image {
+        let rgba = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .flat_map(|(x, y)| colour(x, y))
+            .collect();
+        Image {
+            width,
+            height,
+            rgba,
+        }
+    }
+
+    /// A bright disc off centre on a dark diagonal gradient, scaled to `size` and
+    /// moved right by `shift` pixels
+    fn icon(size: u32, shift: i32) -> Image {
+        image(size, size, |x, y| {
+            let (x, y) = (x * 64 / size, y * 64 / size);
+            let (dx, dy) = (x as i32 - 22 - shift, y as i32 - 26);
+            if dx * dx + dy * dy < 14 * 14 {
+                [230, 200, 40, 255]
+            } else {
+                let shade = ((x + y) * 2) as u8;
+                [shade, shade / 2, 60, 255]
+            }
+        })
+    }
+
+    fn distance(a: &Image, b: &Image) -> u32 {
+        (a.phash() ^ b.phash()).count_ones()
+    }
+
+    #[test]
+    fn phash_is_stable() {
+        let icon = icon(64, 0);
+        assert_eq!(icon.phash(), icon.clone().phash());
+
+        // the same pixels from a PNG hash like the image they came from
+        let rows: Vec<&[u8]> = icon.rgba.chunks(64 * 4).collect();
+        let decoded = Image::decode(&png(ihdr(64, 64, 8, 6, 0), &[], &unfiltered(&rows))).unwrap();
+        assert_eq!(decoded.phash(), icon.phash());
+    }
+
+    #[test]
+    fn flat_images_hash_alike() {
+        let red = image(64, 64, |_, _| [200, 10, 10, 255]);
+        let blue = image(64, 64, |_, _| [10, 10, 200, 255]);
+        assert_eq!(distance(&red, &blue), 0);
+
+        // nothing but the brightness bit, which black doesn't have
+        let black = image(64, 64, |_, _| [0, 0, 0, 255]);
+        assert_eq!(black.phash(), 0);
+        assert_eq!(distance(&black, &red), 1);
+    }
+
+    #[test]
+    fn small_changes_stay_close() {
+        let icon = icon(64, 0);
+        let brighter = Image {
+            rgba: icon
+                .rgba
+                .iter()
+                .enumerate()
+                .map(|(i, &b)| if i % 4 == 3 { b } else { b.saturating_add(25) })
+                .collect(),
+            ..icon.clone()
+        };
+        let recoloured = Image {
+            rgba: icon
+                .rgba
+                .chunks(4)
+                .flat_map(|px| [px[2], px[0], px[1], px[3]])
+                .collect(),
+            ..icon.clone()
+        };
+
+        for (name, other) in [
+            ("brighter", brighter),
+            ("recoloured", recoloured),
+            ("shifted", self::icon(64, 2)),
+            ("smaller", self::icon(32, 0)),
+            ("larger", self::icon(128, 0)),
+        ] {
+            let distance = distance(&icon, &other);
+            assert!(
+                distance <= DEFAULT_DISTANCE,
+                "{} is {} bits off",
+                name,
+                distance
+            );
+        }
+    }
+
+    #[test]
+    fn different_images_are_far_apart() {
+        let icon = icon(64, 0);
+        let mirrored = image(64, 64, |x, y| {
+            let i = ((y * 64 + 63 - x) * 4) as usize;
+            icon.rgba[i..i + 4].try_into().unwrap()
+        });
+        let checkers = image(64, 64, |x, y| {
+            let v = if (x / 16 + y / 16) % 2 == 0 { 255 } else { 0 };
+            [v, v, v, 255]
+        });
+
+        for (name, a, b) in [
+            ("mirrored", &icon, &mirrored),
+            ("checkers", &icon, &checkers),
+            ("mirrored checkers", &mirrored, &checkers),
+        ] {
+            let distance = distance(a, b);
+            assert!(
+                distance > MAX_DISTANCE,
+                "{} only {} bits apart",
+                name,
+                distance
+            );
+        }
+    }
+}
@@ -0,0 +1,264 @@
+//! GameSpy4 query protocol, answered by Java servers with `enable-query=true`.
+//! See <https://wiki.vg/Query>
+
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+use tokio::net::UdpSocket;
+
+const MAGIC: [u8; 2] = [0xfe, 0xfd];
+const HANDSHAKE: u8 = 0x09;
+const STAT: u8 = 0x00;
+/// Only the lower 4 bits of each byte are read by the server
+const SESSION_MASK: i32 = 0x0f0f0f0f;
+/// `splitnum\0\x80\0` before the key/value section
+const KV_PADDING: usize = 11;
+/// `\x01player_\0\0` between the key/value and player sections
+const PLAYER_PADDING: usize = 10;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryResponse {
+    pub motd: String,
+    pub map: String,
+    pub version: String,
+    /// The raw `plugins` value, e.g. `Paper on Bukkit 1.20.1: WorldEdit 7.2; LuckPerms 5.4`
+    pub plugins: String,
+    /// Everything before the `:` in `plugins`, `None` on vanilla servers
+    pub software: Option<String>,
+    pub online_players: usize,
+    pub max_players: usize,
+    pub players: Vec<String>,
+}
+
+pub async fn query(host: &str, port: u16) -> anyhow::Result<QueryResponse> {
+    let socket = UdpSocket::bind(("0.0.0.0", 0)).await?;
+    socket.connect((host, port)).await?;
+
+    let session = rand::random::<i32>() & SESSION_MASK;
+    let mut buf = vec![0; 8192];
+
+    socket.send(&build_request(HANDSHAKE, session, &[])).await?;
+    let len = socket.recv(&mut buf).await?;
+    let token = parse_handshake(&buf[..len], session)?;
+
+    // the trailing padding is what asks for the full stat instead of the basic one
+    let mut payload = token.to_be_bytes().to_vec();
+    payload.extend_from_slice(&[0; 4]);
+    socket.send(&build_request(STAT, session, &payload)).await?;
+    let len = socket.recv(&mut buf).await?;
+
+    parse_stat(&buf[..len], session)
+}
+
+fn build_request(kind: u8, session: i32, payload: &[u8]) -> Vec<u8> {
+    let mut buf = MAGIC.to_vec();
+    buf.push(kind);
+    buf.extend_from_slice(&session.to_be_bytes());
+    buf.extend_from_slice(payload);
+
+    buf
+}
+
+/// Checks the packet type and session id, returning what's after them
+fn strip_header(packet: &[u8], kind: u8, session: i32) -> anyhow::Result<&[u8]> {
+    if packet.len() < 5 || packet[0] != kind {
+        return Err(anyhow!("unexpected query packet type"));
+    }
+
+    if packet[1..5] != session.to_be_bytes() {
+        return Err(anyhow!("query session id mismatch"));
+    }
+
+    Ok(&packet[5..])
+}
+
+fn parse_handshake(packet: &[u8], session: i32) -> anyhow::Result<i32> {
+    let body = strip_header(packet, HANDSHAKE, session)?;
+    let token = String::from_utf8_lossy(body);
+
+    Ok(token.trim_end_matches('\0').parse()?)
+}
+
+pub fn parse_stat(packet: &[u8], session: i32) -> anyhow::Result<QueryResponse> {
+    let body = strip_header(packet, STAT, session)?;
+    let Some(body) = body.get(KV_PADDING..) else {
+        return Err(anyhow!("truncated query response"));
+    };
+
+    let mut rest = body;
+    let mut values = HashMap::new();
+    loop {
+        let key = read_string(&mut rest)?;
+        if key.is_empty() {
+            break;
+        }
+
+        let value = read_string(&mut rest)?;
+        values.insert(key, value);
+    }
+
+    let mut players = Vec::new();
+    if let Some(mut rest) = rest.get(PLAYER_PADDING..) {
+        while let Ok(name) = read_string(&mut rest) {
+            if name.is_empty() {
+                break;
+            }
+            players.push(name);
+        }
+    }
+
+    let mut field = |key: &str| values.remove(key).unwrap_or_default();
+    let plugins = field("plugins");
+    let software = plugins
+        .split(':')
+        .next()
+        .map(|software| software.trim().to_string())
+        .filter(|software| !software.is_empty());
+
+    Ok(QueryResponse {
+        motd: field("hostname"),
+        map: field("map"),
+        version: field("version"),
+        online_players: field("numplayers").parse()?,
+        max_players: field("maxplayers").parse()?,
+        plugins,
+        software,
+        players,
+    })
+}
+
+/// Reads a null terminated string and advances `input` past it.
+/// The server writes these as latin-1, not utf-8.
+fn read_string(input: &mut &[u8]) -> anyhow::Result<String> {
+    let Some(end) = input.iter().position(|b| *b == 0) else {
+        return Err(anyhow!("unterminated query string"));
+    };
+
+    let string = input[..end].iter().map(|&b| b as char).collect();
+    *input = &input[end + 1..];
+
+    Ok(string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SESSION: i32 = 0x01020304;
+
+    /// A full stat answer the way a vanilla server lays it out
+    fn stat(plugins: &[u8], players: &[&[u8]]) -> Vec<u8> {
+        let mut packet = vec![STAT, 0x01, 0x02, 0x03, 0x04];
+        packet.extend_from_slice(b"splitnum\0\x80\0");
+        let values: [(&[u8], &[u8]); 10] = [
+            (b"hostname", b"A Minecraft Server"),
+            (b"gametype", b"SMP"),
+            (b"game_id", b"MINECRAFT"),
+            (b"version", b"1.20.1"),
+            (b"plugins", plugins),
+            (b"map", b"world"),
+            (b"numplayers", b"2"),
+            (b"maxplayers", b"20"),
+            (b"hostport", b"25565"),
+            (b"hostip", b"127.0.0.1"),
+        ];
+        for (key, value) in values {
+            packet.extend_from_slice(key);
+            packet.push(0);
+            packet.extend_from_slice(value);
+            packet.push(0);
+        }
+        packet.push(0);
+        packet.extend_from_slice(b"\x01player_\0\0");
+        for player in players {
+            packet.extend_from_slice(player);
+            packet.push(0);
+        }
+        packet.push(0);
+        packet
+    }
+
+    #[test]
+    fn parses_full_stat() {
+        let packet = stat(b"", &[b"Notch", b"Ren\xe9"]);
+        let response = parse_stat(&packet, SESSION).unwrap();
+        assert_eq!(
+            response,
+            QueryResponse {
+                motd: "A Minecraft Server".to_string(),
+                map: "world".to_string(),
+                version: "1.20.1".to_string(),
+                plugins: String::new(),
+                software: None,
+                online_players: 2,
+                max_players: 20,
+                // latin-1, not utf-8
+                players: vec!["Notch".to_string(), "René".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn parses_plugins() {
+        let plugins = b"Paper on Bukkit 1.20.1: WorldEdit 7.2; LuckPerms 5.4";
+        let response = parse_stat(&stat(plugins, &[]), SESSION).unwrap();
+        assert_eq!(response.software.as_deref(), Some("Paper on Bukkit 1.20.1"));
+        assert_eq!(
+            response.plugins,
+            "Paper on Bukkit 1.20.1: WorldEdit 7.2; LuckPerms 5.4"
+        );
+        assert!(response.players.is_empty());
+
+        let response = parse_stat(&stat(b"Paper on Bukkit 1.20.1", &[]), SESSION).unwrap();
+        assert_eq!(response.software.as_deref(), Some("Paper on Bukkit 1.20.1"));
+    }
+
+    #[test]
+    fn player_section_is_optional() {
+        // cut right after the key/value section
+        let packet = stat(b"", &[]);
+        let end = packet.len() - PLAYER_PADDING - 1;
+        let response = parse_stat(&packet[..end], SESSION).unwrap();
+        assert_eq!(response.max_players, 20);
+        assert!(response.players.is_empty());
+    }
+
+    #[test]
+    fn rejects_bad_stats() {
+        let packet = stat(b"", &[b"Notch"]);
+        let mut kind = packet.clone();
+        kind[0] = HANDSHAKE;
+        let mut count = packet.clone();
+        let at = packet.windows(2).position(|w| w == b"2\0").unwrap();
+        count[at] = b'x';
+
+        for (packet, message) in [
+            (&packet[..3], "unexpected query packet type"),
+            (&kind[..], "unexpected query packet type"),
+            (&packet[..10], "truncated"),
+            (&packet[..40], "unterminated"),
+            (&count[..], "invalid digit"),
+        ] {
+            let err = parse_stat(packet, SESSION).unwrap_err();
+            assert!(
+                err.to_string().contains(message),
+                "expected {}, got {}",
+                message,
+                err
+            );
+        }
+
+        let err = parse_stat(&packet, SESSION + 1).unwrap_err();
+        assert!(err.to_string().contains("session id mismatch"));
+    }
+
+    #[test]
+    fn parses_handshake() {
+        let packet = b"\x09\x01\x02\x03\x049513307\0";
+        assert_eq!(parse_handshake(packet, SESSION).unwrap(), 9513307);
+        assert!(parse_handshake(b"\x09\x01\x02\x03\x04abc\0", SESSION).is_err());
+
+        let request = build_request(HANDSHAKE, SESSION, &[]);
+        assert_eq!(request, [0xfe, 0xfd, 0x09, 0x01, 0x02, 0x03, 0x04]);
+    }
+}
@@ -0,0 +1,189 @@
+//! Turns hostname targets into the address the server actually listens on.
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use azalea_protocol::{resolver::resolve_address, ServerAddress};
+use log::warn;
+use once_cell::sync::Lazy;
+
+/// Picked once from the environment, see `from_env`
+pub static RESOLVER: Lazy<Arc<dyn Resolver>> = Lazy::new(from_env);
+
+#[async_trait]
+pub trait Resolver: Send + Sync {
+    /// Resolves a hostname to `port` on its A/AAAA records. With `srv` the
+    /// `_minecraft._tcp` SRV record is tried first and picks the port instead.
+    async fn resolve(&self, host: &str, port: u16, srv: bool) -> anyhow::Result<SocketAddr>;
+}
+
+/// Resolves through DNS, the default
+#[derive(Debug, Default)]
+pub struct DnsResolver;
+
+#[async_trait]
+impl Resolver for DnsResolver {
+    async fn resolve(&self, host: &str, port: u16, srv: bool) -> anyhow::Result<SocketAddr> {
+        if srv {
+            let addr = ServerAddress {
+                host: host.to_string(),
+                port,
+            };
+
+            return Ok(resolve_address(&addr).await?);
+        }
+
+        tokio::net::lookup_host((host, port))
+            .await?
+            .next()
+            .ok_or_else(|| anyhow!("no address found for {}", host))
+    }
+}
+
+/// Answers from a fixed table instead of DNS, for testing without network.
+/// Names that aren't in the table don't resolve.
+#[derive(Debug, Default)]
+pub struct StaticResolver {
+    records: HashMap<String, Record>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Record {
+    /// Like an SRV record, redirects to another port as well
+    Srv(SocketAddr),
+    /// Like an A record, keeps the port that was asked for
+    Ip(IpAddr),
+}
+
+impl StaticResolver {
+    /// `host=ip:port` (SRV-like) or `host=ip` (A-like) entries separated by commas,
+    /// e.g. `play.example.com=127.0.0.1:25566,example.com=127.0.0.1`
+    pub fn parse(input: &str) -> anyhow::Result<Self> {
+        let mut records = HashMap::new();
+
+        for entry in input.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let Some((host, target)) = entry.split_once('=') else {
+                return Err(anyhow!("expected host=address, got {}", entry));
+            };
+
+            let record = match target.parse::<SocketAddr>() {
+                Ok(addr) => Record::Srv(addr),
+                Err(_) => Record::Ip(target.parse()?),
+            };
+            records.insert(host.to_lowercase(), record);
+        }
+
+        Ok(Self { records })
+    }
+}
+
+#[async_trait]
+impl Resolver for StaticResolver {
+    async fn resolve(&self, host: &str, port: u16, srv: bool) -> anyhow::Result<SocketAddr> {
+        match self.records.get(&host.to_lowercase()) {
+            Some(Record::Srv(addr)) if srv => Ok(*addr),
+            Some(Record::Srv(addr)) => Ok(SocketAddr::new(addr.ip(), port)),
+            Some(Record::Ip(ip)) => Ok(SocketAddr::new(*ip, port)),
+            None => Err(anyhow!("no static record for {}", host)),
+        }
+    }
+}
+
+/// Uses a `StaticResolver` built from `VOYAGER_STATIC_HOSTS` if it's set, DNS otherwise
+pub fn from_env() -> Arc<dyn Resolver> {
+    let Ok(spec) = std::env::var("VOYAGER_STATIC_HOSTS") else {
+        return Arc::new(DnsResolver);
+    };
+
+    match StaticResolver::parse(&spec) {
+        Ok(resolver) => Arc::new(resolver),
+        Err(e) => {
+            warn!("Invalid VOYAGER_STATIC_HOSTS ({}), using DNS", e);
+            Arc::new(DnsResolver)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::util::types::{Edition, OntosAddress};
+
+    use super::*;
+
+    fn resolver() -> StaticResolver {
+        StaticResolver::parse(
+            "Play.Example.com=10.0.0.1:25570, example.com=10.0.0.2, v6.example.com=::1",
+        )
+        .unwrap()
+    }
+
+    fn target(host: &str, edition: Edition, srv: bool) -> OntosAddress {
+        OntosAddress {
+            host: host.to_string(),
+            edition,
+            hostname: None,
+            srv,
+        }
+    }
+
+    #[test]
+    fn parse_rejects_bad_entries() {
+        assert!(StaticResolver::parse("example.com").is_err());
+        assert!(StaticResolver::parse("example.com=not-an-ip").is_err());
+        assert!(StaticResolver::parse(" , ").unwrap().records.is_empty());
+    }
+
+    #[tokio::test]
+    async fn srv_records_only_apply_with_srv() {
+        let resolver = resolver();
+        let srv = resolver.resolve("play.example.com", 25565, true).await;
+        assert_eq!(srv.unwrap(), "10.0.0.1:25570".parse().unwrap());
+
+        let plain = resolver.resolve("PLAY.example.com", 25565, false).await;
+        assert_eq!(plain.unwrap(), "10.0.0.1:25565".parse().unwrap());
+    }
+
+    #[tokio::test]
+    async fn ip_records_keep_the_port() {
+        let resolver = resolver();
+        for srv in [true, false] {
+            let addr = resolver.resolve("example.com", 25599, srv).await;
+            assert_eq!(addr.unwrap(), "10.0.0.2:25599".parse().unwrap());
+        }
+        assert!(resolver.resolve("missing.com", 25565, true).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn targets_follow_srv_only_without_a_port() {
+        let resolver = resolver();
+
+        let addr = target("play.example.com:25565", Edition::Java, true);
+        let resolved = addr.resolve(&resolver).await.unwrap();
+        assert_eq!(resolved.host, "10.0.0.1:25570");
+        assert_eq!(resolved.hostname.as_deref(), Some("play.example.com"));
+
+        let addr = target("play.example.com:25580", Edition::Java, false);
+        let resolved = addr.resolve(&resolver).await.unwrap();
+        assert_eq!(resolved.host, "10.0.0.1:25580");
+
+        let addr = target("play.example.com:19132", Edition::Bedrock, true);
+        let resolved = addr.resolve(&resolver).await.unwrap();
+        assert_eq!(resolved.host, "10.0.0.1:19132");
+    }
+
+    #[tokio::test]
+    async fn ip_targets_are_left_alone() {
+        let addr = target("10.1.2.3:25565", Edition::Java, true);
+        let resolved = addr.resolve(&resolver()).await.unwrap();
+        assert_eq!(resolved.host, "10.1.2.3:25565");
+        assert_eq!(resolved.hostname, None);
+
+        let addr = target("v6.example.com:25565", Edition::Java, false);
+        assert!(addr.resolve(&resolver()).await.is_err());
+    }
+}
@@ -1,47 +1,183 @@
-use std::time::Duration;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::{Duration, Instant};
 
+use anyhow::anyhow;
 use azalea_protocol::ServerAddress;
 use base64::{engine::general_purpose, Engine};
 use chrono::NaiveDateTime;
 use craftping::tokio::ping;
 use craftping::Response as CraftpingResponse;
-use sea_orm::sea_query::OnConflict;
-use sea_orm::{ActiveValue, DatabaseTransaction, EntityTrait};
+use ipnet::Ipv4Net;
+use iprange::IpRange;
+use log::{debug, warn};
+use md5::{Digest, Md5};
+use sea_orm::sea_query::{Expr, OnConflict};
+use sea_orm::{ActiveValue, ColumnTrait, DatabaseTransaction, EntityTrait, QueryFilter};
 use serde::{Deserialize, Serialize};
+use sha2::Sha256;
 use tokio::net::TcpStream;
+use tokio::time::error::Elapsed;
 
 use crate::database::entities::descriptions::ActiveModel as DescModel;
-use crate::database::entities::favicons::ActiveModel as FaviconModel;
 use crate::database::entities::players::ActiveModel as PlayerModel;
 use crate::database::entities::servers::ActiveModel as ServerModel;
-use crate::database::entities::{descriptions, favicons, ips, players, servers};
+use crate::database::entities::{
+    descriptions, exclusions, favicon_blobs, hostnames, ips, players, rescan_schedules, servers,
+};
+use crate::util::bedrock::{self, BedrockResponse};
+use crate::util::chat::Component;
+use crate::util::cron::Cron;
+use crate::util::legacy::{self, LegacyResponse};
+use crate::util::login::{self, LoginResponse};
+use crate::util::misc::decode_favicon;
+use crate::util::png::{self, Image};
+use crate::util::query::{self, QueryResponse};
+use crate::util::resolver::Resolver;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct OntosAddress {
     pub host: String,
+    #[serde(default)]
+    pub edition: Edition,
+    /// The name this address was resolved from, `host` is always an IP once resolved
+    #[serde(default)]
+    pub hostname: Option<String>,
+    /// Follow SRV records when resolving, only set for hostnames given without a port
+    #[serde(default)]
+    pub srv: bool,
 }
 
 impl OntosAddress {
-    pub async fn ping_server(&self, timeout: Duration) -> anyhow::Result<Entry> {
-        let scan = tokio::time::timeout(timeout, self.send_request()).await?;
+    /// Looks up hostname targets, returning the address to connect to with the
+    /// original name kept in `hostname`. IP targets are returned as they are.
+    pub async fn resolve(&self, resolver: &dyn Resolver) -> anyhow::Result<Self> {
+        let addr = ServerAddress::try_from(self.host.as_str()).map_err(|e| anyhow!(e))?;
+        if addr.host.parse::<Ipv4Addr>().is_ok() {
+            return Ok(self.clone());
+        }
+
+        // bedrock clients don't look at SRV records
+        let srv = self.srv && self.edition == Edition::Java;
+        let SocketAddr::V4(resolved) = resolver.resolve(&addr.host, addr.port, srv).await? else {
+            return Err(anyhow!("{} only resolved to IPv6", addr.host));
+        };
+
+        Ok(Self {
+            host: resolved.to_string(),
+            edition: self.edition,
+            hostname: Some(addr.host),
+            srv: false,
+        })
+    }
+
+    pub async fn ping_server(&self, timeout: Duration, mode: PingMode) -> anyhow::Result<Entry> {
+        let mut entry = self.ping_any(timeout, mode).await?;
+        entry.server.hostname = self.hostname.clone();
+
+        Ok(entry)
+    }
+
+    async fn ping_any(&self, timeout: Duration, mode: PingMode) -> anyhow::Result<Entry> {
+        let addr = ServerAddress::try_from(self.host.as_str()).map_err(|e| anyhow!(e))?;
+
+        // bedrock servers only answer the raknet ping, whatever the mode
+        if self.edition == Edition::Bedrock {
+            return self.ping_bedrock(timeout, addr).await;
+        }
+
+        match mode {
+            PingMode::Modern => self.ping_modern(timeout, addr).await,
+            PingMode::Legacy => self.ping_legacy(timeout, addr).await,
+            PingMode::Auto => match self.ping_modern(timeout, addr.clone()).await {
+                Ok(entry) => Ok(entry),
+                // nothing is listening, a legacy ping won't do any better
+                Err(e)
+                    if matches!(
+                        FailureKind::classify(&e),
+                        FailureKind::Timeout | FailureKind::Refused
+                    ) =>
+                {
+                    Err(e)
+                }
+                Err(e) => {
+                    debug!("Modern ping to {} failed ({}), trying legacy", self.host, e);
+                    self.ping_legacy(timeout, addr).await
+                }
+            },
+        }
+    }
+
+    async fn ping_modern(&self, timeout: Duration, addr: ServerAddress) -> anyhow::Result<Entry> {
+        let start = Instant::now();
+        let scan = tokio::time::timeout(timeout, self.send_request(&addr)).await?;
         let packet = scan?;
 
-        let ip = ServerAddress::try_from(self.host.as_str()).unwrap();
-        let entry = Entry::new(packet, ip);
+        let mut entry = Entry::new(packet, addr);
+        entry.server.latency_ms = Some(start.elapsed().as_millis() as u32);
         Ok(entry)
     }
 
-    async fn send_request(&self) -> anyhow::Result<CraftpingResponse> {
-        let Ok(addr) = ServerAddress::try_from(self.host.as_str()) else {
-            return Err(anyhow::anyhow!(""))
-        };
-        let host = addr.host;
+    async fn ping_legacy(&self, timeout: Duration, addr: ServerAddress) -> anyhow::Result<Entry> {
+        let start = Instant::now();
+        let scan = tokio::time::timeout(timeout, async {
+            let mut stream = TcpStream::connect((addr.host.as_str(), addr.port)).await?;
+            legacy::ping(&mut stream, self.handshake_host(&addr), addr.port).await
+        })
+        .await?;
+        let packet = scan?;
+
+        let mut entry = Entry::from_legacy(packet, addr);
+        entry.server.latency_ms = Some(start.elapsed().as_millis() as u32);
+        Ok(entry)
+    }
+
+    async fn ping_bedrock(&self, timeout: Duration, addr: ServerAddress) -> anyhow::Result<Entry> {
+        let start = Instant::now();
+        let scan = tokio::time::timeout(timeout, bedrock::ping(&addr.host, addr.port)).await?;
+        let packet = scan?;
+
+        let mut entry = Entry::from_bedrock(packet, addr);
+        entry.server.latency_ms = Some(start.elapsed().as_millis() as u32);
+        Ok(entry)
+    }
+
+    /// Runs the full stat query against the server's game port, which is where
+    /// `query.port` points unless it was changed.
+    pub async fn query_server(&self, timeout: Duration) -> anyhow::Result<QueryResponse> {
+        let addr = ServerAddress::try_from(self.host.as_str()).map_err(|e| anyhow!(e))?;
+        tokio::time::timeout(timeout, query::query(&addr.host, addr.port)).await?
+    }
+
+    /// Starts a login with the protocol the server reported, to find out what it
+    /// does with a player it has never seen.
+    pub async fn login_server(
+        &self,
+        timeout: Duration,
+        protocol: i32,
+    ) -> anyhow::Result<LoginResponse> {
+        let addr = ServerAddress::try_from(self.host.as_str()).map_err(|e| anyhow!(e))?;
+
+        tokio::time::timeout(timeout, async {
+            let stream = TcpStream::connect((addr.host.as_str(), addr.port)).await?;
+            login::login(stream, self.handshake_host(&addr), addr.port, protocol).await
+        })
+        .await?
+    }
+
+    async fn send_request(&self, addr: &ServerAddress) -> anyhow::Result<CraftpingResponse> {
+        let host = addr.host.clone();
         let port = addr.port;
 
         let mut stream = TcpStream::connect((host.clone(), port)).await?;
-        let response = ping(&mut stream, &host, port).await?;
+        let response = ping(&mut stream, self.handshake_host(addr), port).await?;
         Ok(response)
     }
+
+    /// Virtual hosts and proxies route on the name in the handshake, so send the
+    /// one we were given rather than the IP it resolved to
+    fn handshake_host<'a>(&'a self, addr: &'a ServerAddress) -> &'a str {
+        self.hostname.as_deref().unwrap_or(&addr.host)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -51,8 +187,131 @@ pub struct Entry {
     pub favicon: Favicon,
 }
 
+/// A known address that didn't answer when it was rescanned
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ScanFailure {
+    /// Always `ip:port`, hostnames are resolved before the ping
+    pub address: OntosAddress,
+    pub failed_at: NaiveDateTime,
+    #[serde(default)]
+    pub kind: FailureKind,
+}
+
+impl ScanFailure {
+    pub fn new(address: &OntosAddress, kind: FailureKind) -> Self {
+        Self {
+            address: OntosAddress {
+                hostname: None,
+                ..address.clone()
+            },
+            failed_at: chrono::Utc::now().naive_utc(),
+            kind,
+        }
+    }
+}
+
+/// Part of an uploaded batch europa couldn't store, by its index in the batch
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RejectedRecord {
+    pub index: usize,
+    /// Whether `index` is into the batch's failures rather than its servers
+    #[serde(default)]
+    pub failure: bool,
+    pub reason: String,
+}
+
+impl RejectedRecord {
+    pub fn server(index: usize, e: anyhow::Error) -> Self {
+        Self {
+            index,
+            failure: false,
+            reason: e.to_string(),
+        }
+    }
+
+    pub fn failure(index: usize, e: anyhow::Error) -> Self {
+        Self {
+            index,
+            failure: true,
+            reason: e.to_string(),
+        }
+    }
+}
+
+/// Why a ping got no usable answer
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FailureKind {
+    /// Nothing listening on the port
+    Refused,
+    Timeout,
+    /// The server hung up part way through
+    Reset,
+    /// No route to the host or its network
+    Unreachable,
+    /// Something answered, but not with anything we could read
+    Protocol,
+    #[default]
+    Other,
+}
+
+impl FailureKind {
+    pub fn classify(e: &anyhow::Error) -> Self {
+        if e.is::<Elapsed>() {
+            return FailureKind::Timeout;
+        }
+
+        let io = match e.downcast_ref::<craftping::Error>() {
+            Some(craftping::Error::Io(io)) => io,
+            Some(craftping::Error::UnsupportedProtocol) => return FailureKind::Protocol,
+            None => match e.downcast_ref::<std::io::Error>() {
+                Some(io) => io,
+                // the legacy, bedrock and login parsers only fail on bad packets
+                None => return FailureKind::Protocol,
+            },
+        };
+
+        use std::io::ErrorKind;
+        match io.kind() {
+            ErrorKind::ConnectionRefused => FailureKind::Refused,
+            ErrorKind::TimedOut => FailureKind::Timeout,
+            ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::BrokenPipe
+            | ErrorKind::UnexpectedEof => FailureKind::Reset,
+            ErrorKind::InvalidData => FailureKind::Protocol,
+            // ENETUNREACH and EHOSTUNREACH, which have no stable `ErrorKind` yet
+            _ if matches!(io.raw_os_error(), Some(101 | 113)) => FailureKind::Unreachable,
+            _ => FailureKind::Other,
+        }
+    }
+
+    pub fn to_string_but_consistent(&self) -> String {
+        match self {
+            FailureKind::Refused => "Refused".to_string(),
+            FailureKind::Timeout => "Timeout".to_string(),
+            FailureKind::Reset => "Reset".to_string(),
+            FailureKind::Unreachable => "Unreachable".to_string(),
+            FailureKind::Protocol => "Protocol".to_string(),
+            FailureKind::Other => "Other".to_string(),
+        }
+    }
+}
+
 impl Entry {
     pub fn new(packet: CraftpingResponse, addr: ServerAddress) -> Self {
+        // craftping quietly falls back to a legacy ping on the same connection
+        let ping = match packet.raw().first() {
+            Some(0xff) => PingKind::Legacy,
+            _ => PingKind::Modern,
+        };
+
+        // craftping's `Chat` drops everything but the text, styles and `extra`, the
+        // raw JSON has the rest
+        let component = serde_json::from_slice::<serde_json::Value>(packet.raw())
+            .ok()
+            .and_then(|json| json.get("description").map(Component::from_json))
+            .unwrap_or_else(|| Component::from_legacy(&packet.description.text));
+
         Self {
             server: Server {
                 id: 0,
@@ -73,6 +332,7 @@ impl Entry {
                                     uuid: uuid::Uuid::parse_str(&player.id).unwrap(),
                                     last_seen: chrono::Utc::now().naive_utc(),
                                     server_id: 0,
+                                    derived_uuid: false,
                                 })
                                 .collect();
 
@@ -81,35 +341,155 @@ impl Entry {
                         _ => None,
                     }
                 },
-                auth: OnlineStatus::Online,
+                auth: OnlineStatus::Unknown,
                 created_at: chrono::Utc::now().naive_utc(),
                 updated_at: chrono::Utc::now().naive_utc(),
                 forge: packet.forge_data.is_some(),
+                ping,
+                edition: Edition::Java,
+                gamemode: None,
+                server_guid: None,
+                plugins: None,
+                map: None,
+                software: None,
+                disconnect_reason: None,
+                hostname: None,
+                state: ServerState::Online,
+                latency_ms: None,
             },
 
-            description: Description {
-                id: 0,
+            description: Description::from_component(component),
+
+            favicon: Favicon {
+                hash: None,
+                phash: None,
+                png: packet.favicon.map(|favicon| Favicon::encode(&favicon)),
                 server_id: 0,
-                text: packet.description.text,
-                bold: packet.description.bold,
-                italic: packet.description.italic,
-                underline: packet.description.underlined,
-                strikethrough: packet.description.strikethrough,
-                obfuscated: packet.description.obfuscated,
-                colour: packet.description.color.unwrap_or("white".to_string()),
+                invalid: None,
             },
+        }
+    }
 
-            favicon: Favicon {
+    pub fn from_legacy(packet: LegacyResponse, addr: ServerAddress) -> Self {
+        Self {
+            server: Server {
                 id: 0,
-                png: packet
-                    .favicon
-                    .map(|favicon| general_purpose::STANDARD_NO_PAD.encode(favicon)),
-                server_id: 0,
+                ip: addr.host,
+                port: addr.port,
+                version: packet.version,
+                protocol: packet.protocol,
+                max_players: packet.max_players,
+                online_players: packet.online_players,
+                sample_players: None,
+                auth: OnlineStatus::Unknown,
+                created_at: chrono::Utc::now().naive_utc(),
+                updated_at: chrono::Utc::now().naive_utc(),
+                forge: false,
+                ping: PingKind::Legacy,
+                edition: Edition::Java,
+                gamemode: None,
+                server_guid: None,
+                plugins: None,
+                map: None,
+                software: None,
+                disconnect_reason: None,
+                hostname: None,
+                state: ServerState::Online,
+                latency_ms: None,
             },
+
+            description: Description::from_legacy(packet.motd),
+
+            favicon: Favicon::empty(),
+        }
+    }
+
+    pub fn from_bedrock(packet: BedrockResponse, addr: ServerAddress) -> Self {
+        Self {
+            server: Server {
+                id: 0,
+                ip: addr.host,
+                port: addr.port,
+                version: packet.version,
+                protocol: packet.protocol,
+                max_players: packet.max_players,
+                online_players: packet.online_players,
+                sample_players: None,
+                auth: OnlineStatus::Unknown,
+                created_at: chrono::Utc::now().naive_utc(),
+                updated_at: chrono::Utc::now().naive_utc(),
+                forge: false,
+                ping: PingKind::Raknet,
+                edition: Edition::Bedrock,
+                gamemode: packet.gamemode,
+                server_guid: packet.server_guid,
+                plugins: None,
+                map: None,
+                software: None,
+                disconnect_reason: None,
+                hostname: None,
+                state: ServerState::Online,
+                latency_ms: None,
+            },
+
+            description: Description::from_legacy(packet.motd.join("\n")),
+
+            favicon: Favicon::empty(),
+        }
+    }
+
+    /// Fills in what only the query protocol knows. Its player list is complete, unlike
+    /// the ping's sample, so it replaces the sample outright. Query only gives names,
+    /// so uuids are taken from the sample where possible. The rest are derived
+    /// offline-mode style and marked as such, on an online mode server nobody has them.
+    pub fn merge_query(&mut self, packet: QueryResponse) {
+        let server = &mut self.server;
+        let sample = server.sample_players.take().unwrap_or_default();
+        let now = chrono::Utc::now().naive_utc();
+
+        let players = packet
+            .players
+            .into_iter()
+            .map(|name| {
+                let sampled = sample.iter().find(|player| player.name == name);
+                let uuid = sampled.map_or_else(|| offline_uuid(&name), |player| player.uuid);
+
+                OntosPlayer {
+                    name,
+                    uuid,
+                    last_seen: now,
+                    server_id: 0,
+                    derived_uuid: sampled.is_none(),
+                }
+            })
+            .collect();
+
+        server.sample_players = Some(players);
+        server.online_players = packet.online_players;
+        server.plugins = Some(packet.plugins).filter(|plugins| !plugins.is_empty());
+        server.map = Some(packet.map).filter(|map| !map.is_empty());
+        server.software = packet.software;
+    }
+
+    pub fn merge_login(&mut self, packet: LoginResponse) {
+        // an offline mode server hands out exactly the uuids `merge_query` derived
+        if packet.auth == OnlineStatus::Offline {
+            for player in self.server.sample_players.iter_mut().flatten() {
+                player.derived_uuid = false;
+            }
         }
+
+        self.server.auth = packet.auth;
+        self.server.disconnect_reason = packet.disconnect_reason;
     }
 }
 
+/// The uuid an offline mode server hands out, `md5("OfflinePlayer:" + name)` as a v3 uuid
+pub fn offline_uuid(name: &str) -> uuid::Uuid {
+    let hash = Md5::digest(format!("OfflinePlayer:{}", name));
+    uuid::Builder::from_md5_bytes(hash.into()).into_uuid()
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Server {
     pub id: i32,
@@ -124,6 +504,33 @@ pub struct Server {
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
     pub forge: bool,
+    #[serde(default)]
+    pub ping: PingKind,
+    #[serde(default)]
+    pub edition: Edition,
+    #[serde(default)]
+    pub gamemode: Option<String>,
+    #[serde(default)]
+    pub server_guid: Option<String>,
+    #[serde(default)]
+    pub plugins: Option<String>,
+    #[serde(default)]
+    pub map: Option<String>,
+    #[serde(default)]
+    pub software: Option<String>,
+    /// Kick message from the login probe
+    #[serde(default)]
+    pub disconnect_reason: Option<String>,
+    /// The name the server was reached through, stored in `hostnames` rather than `servers`
+    #[serde(default)]
+    pub hostname: Option<String>,
+    /// Always `Online` for a fresh scan, rescans that fail move it along
+    #[serde(default)]
+    pub state: ServerState,
+    /// How long the whole status exchange took, connecting included.
+    /// Only kept in `server_observations`.
+    #[serde(default)]
+    pub latency_ms: Option<u32>,
 }
 
 impl Server {
@@ -137,6 +544,15 @@ impl Server {
             online_players: ActiveValue::Set(self.online_players as i32),
             auth: ActiveValue::Set(self.auth.to_string_but_consistent()),
             forge: ActiveValue::Set(self.forge),
+            ping: ActiveValue::Set(self.ping.to_string_but_consistent()),
+            edition: ActiveValue::Set(self.edition.to_string_but_consistent()),
+            gamemode: ActiveValue::Set(self.gamemode.clone()),
+            server_guid: ActiveValue::Set(self.server_guid.clone()),
+            plugins: ActiveValue::Set(self.plugins.clone()),
+            map: ActiveValue::Set(self.map.clone()),
+            software: ActiveValue::Set(self.software.clone()),
+            disconnect_reason: ActiveValue::Set(self.disconnect_reason.clone()),
+            state: ActiveValue::Set(self.state.to_string_but_consistent()),
             created_at: ActiveValue::Set(chrono::Utc::now().naive_utc()),
             updated_at: ActiveValue::Set(chrono::Utc::now().naive_utc()),
             ..Default::default()
@@ -156,28 +572,62 @@ impl Server {
             auth: match model.auth.as_str() {
                 "Online" => OnlineStatus::Online,
                 "Offline" => OnlineStatus::Offline,
-                _ => OnlineStatus::Offline,
+                "Whitelisted" => OnlineStatus::Whitelisted,
+                "Modded" => OnlineStatus::Modded,
+                _ => OnlineStatus::Unknown,
             },
             created_at: model.created_at,
             updated_at: model.updated_at,
             forge: model.forge,
+            ping: match model.ping.as_str() {
+                "Legacy" => PingKind::Legacy,
+                "Raknet" => PingKind::Raknet,
+                _ => PingKind::Modern,
+            },
+            edition: Edition::from_str_lossy(&model.edition),
+            gamemode: model.gamemode,
+            server_guid: model.server_guid,
+            plugins: model.plugins,
+            map: model.map,
+            software: model.software,
+            disconnect_reason: model.disconnect_reason,
+            hostname: None,
+            state: ServerState::from_str_lossy(&model.state),
+            latency_ms: None,
         }
     }
 
     pub async fn insert<T: sea_orm::ConnectionTrait>(&self, txn: &T) -> anyhow::Result<i32> {
+        let mut columns = vec![
+            servers::Column::Version,
+            servers::Column::Protocol,
+            servers::Column::MaxPlayers,
+            servers::Column::OnlinePlayers,
+            servers::Column::Forge,
+            servers::Column::Ping,
+            servers::Column::Gamemode,
+            servers::Column::ServerGuid,
+            servers::Column::Plugins,
+            servers::Column::Map,
+            servers::Column::Software,
+            servers::Column::State,
+            servers::Column::UpdatedAt,
+        ];
+
+        // a scan without the login probe shouldn't wipe what an earlier probe found
+        if self.auth != OnlineStatus::Unknown || self.disconnect_reason.is_some() {
+            columns.extend([servers::Column::Auth, servers::Column::DisconnectReason]);
+        }
+
         let id = servers::Entity::insert(self.model())
             .on_conflict(
-                OnConflict::columns(vec![servers::Column::Ip, servers::Column::Port])
-                    .update_columns(vec![
-                        servers::Column::Version,
-                        servers::Column::Protocol,
-                        servers::Column::MaxPlayers,
-                        servers::Column::OnlinePlayers,
-                        servers::Column::Auth,
-                        servers::Column::Forge,
-                        servers::Column::UpdatedAt,
-                    ])
-                    .to_owned(),
+                OnConflict::columns(vec![
+                    servers::Column::Ip,
+                    servers::Column::Port,
+                    servers::Column::Edition,
+                ])
+                .update_columns(columns)
+                .to_owned(),
             )
             .exec(txn)
             .await?
@@ -186,17 +636,64 @@ impl Server {
         Ok(id)
     }
 
+    /// Records the hostname the server was reached through. One name can point at
+    /// several servers on different ports, and `updated_at` tells stale ones apart.
+    pub async fn insert_hostname<T: sea_orm::ConnectionTrait>(
+        &self,
+        txn: &T,
+        server_id: i32,
+    ) -> anyhow::Result<()> {
+        let Some(hostname) = &self.hostname else {
+            return Ok(());
+        };
+
+        hostnames::Entity::insert(hostnames::ActiveModel {
+            hostname: ActiveValue::Set(hostname.to_lowercase()),
+            server_id: ActiveValue::Set(server_id),
+            updated_at: ActiveValue::Set(chrono::Utc::now().naive_utc()),
+            ..Default::default()
+        })
+        .on_conflict(
+            OnConflict::columns(vec![
+                hostnames::Column::Hostname,
+                hostnames::Column::ServerId,
+            ])
+            .update_column(hostnames::Column::UpdatedAt)
+            .to_owned(),
+        )
+        .exec(txn)
+        .await?;
+
+        Ok(())
+    }
+
     pub async fn update_scan_time(&self, txn: &DatabaseTransaction) -> anyhow::Result<()> {
+        let now = chrono::Utc::now().naive_utc();
         ips::Entity::insert(ips::ActiveModel {
             ip: ActiveValue::Set(self.ip.clone()),
             port: ActiveValue::Set(self.port as i32),
-            last_scanned: ActiveValue::Set(Some(chrono::Utc::now().naive_utc())),
+            edition: ActiveValue::Set(self.edition.to_string_but_consistent()),
+            last_scanned: ActiveValue::Set(Some(now)),
+            last_success: ActiveValue::Set(Some(now)),
+            successes: ActiveValue::Set(1),
             ..Default::default()
         })
         .on_conflict(
-            OnConflict::columns(vec![ips::Column::Ip, ips::Column::Port])
-                .update_column(ips::Column::LastScanned)
-                .to_owned(),
+            OnConflict::columns(vec![
+                ips::Column::Ip,
+                ips::Column::Port,
+                ips::Column::Edition,
+            ])
+            .values([
+                (ips::Column::LastScanned, Expr::val(now).into()),
+                (ips::Column::LastSuccess, Expr::val(now).into()),
+                (
+                    ips::Column::Successes,
+                    Expr::col((ips::Entity, ips::Column::Successes)).add(1),
+                ),
+                (ips::Column::ConsecutiveFailures, Expr::val(0).into()),
+            ])
+            .to_owned(),
         )
         .exec(txn)
         .await?;
@@ -209,26 +706,72 @@ impl Server {
 pub struct Description {
     pub id: i32,
     pub server_id: i32,
+    /// The description with `§` codes for its formatting, as older servers send it
     pub text: String,
+    /// The top level component's style, its children have their own in `components`
     pub bold: bool,
     pub italic: bool,
     pub underline: bool,
     pub strikethrough: bool,
     pub obfuscated: bool,
     pub colour: String,
+    /// Only known once stored, a scan leaves these empty
+    #[serde(default)]
+    pub first_seen: Option<NaiveDateTime>,
+    #[serde(default)]
+    pub last_seen: Option<NaiveDateTime>,
+    /// `text` without any formatting, what searches match against
+    #[serde(default)]
+    pub plain: String,
+    /// `None` for descriptions stored before components were kept
+    #[serde(default)]
+    pub components: Option<Component>,
 }
 
 impl Description {
+    pub fn from_component(component: Component) -> Self {
+        Self {
+            text: component.to_legacy(),
+            plain: component.plain(),
+            bold: component.bold.unwrap_or_default(),
+            italic: component.italic.unwrap_or_default(),
+            underline: component.underlined.unwrap_or_default(),
+            strikethrough: component.strikethrough.unwrap_or_default(),
+            obfuscated: component.obfuscated.unwrap_or_default(),
+            colour: component.color.clone().unwrap_or("white".to_string()),
+            components: Some(component),
+            ..Self::empty()
+        }
+    }
+
+    /// A MOTD with `§` codes, kept as it was sent
+    pub fn from_legacy(motd: String) -> Self {
+        let component = Component::from_legacy(&motd);
+        Self {
+            text: motd,
+            ..Self::from_component(component)
+        }
+    }
+
     pub fn model(self, server_id: i32) -> DescModel {
+        let now = chrono::Utc::now().naive_utc();
         DescModel {
             server_id: ActiveValue::Set(server_id),
+            text_hash: ActiveValue::Set(format!("{:x}", Sha256::digest(&self.text))),
             text: ActiveValue::Set(self.text),
-            bold: ActiveValue::Set(false),
-            italic: ActiveValue::Set(false),
-            underline: ActiveValue::Set(false),
-            strikethrough: ActiveValue::Set(false),
-            obfuscated: ActiveValue::Set(false),
-            colour: ActiveValue::Set("false".to_string()),
+            bold: ActiveValue::Set(self.bold),
+            italic: ActiveValue::Set(self.italic),
+            underline: ActiveValue::Set(self.underline),
+            strikethrough: ActiveValue::Set(self.strikethrough),
+            obfuscated: ActiveValue::Set(self.obfuscated),
+            colour: ActiveValue::Set(self.colour),
+            first_seen: ActiveValue::Set(now),
+            last_seen: ActiveValue::Set(now),
+            components: ActiveValue::Set(
+                self.components
+                    .and_then(|component| serde_json::to_value(component).ok()),
+            ),
+            plain: ActiveValue::Set(self.plain),
             ..Default::default()
         }
     }
@@ -244,6 +787,12 @@ impl Description {
             strikethrough: model.strikethrough,
             obfuscated: model.obfuscated,
             colour: model.colour,
+            first_seen: Some(model.first_seen),
+            last_seen: Some(model.last_seen),
+            plain: model.plain,
+            components: model
+                .components
+                .and_then(|json| serde_json::from_value(json).ok()),
         }
     }
 
@@ -258,35 +807,44 @@ impl Description {
             strikethrough: false,
             obfuscated: false,
             colour: "white".to_string(),
+            first_seen: None,
+            last_seen: None,
+            plain: "".to_string(),
+            components: None,
         }
     }
 
+    /// A description the server had before, going by `text`, only gets its `last_seen`
+    /// bumped, returns the row's id either way
     pub async fn insert<T: sea_orm::ConnectionTrait>(
         self,
         txn: &T,
         server_id: i32,
-    ) -> anyhow::Result<()> {
-        descriptions::Entity::insert(self.model(server_id))
+    ) -> anyhow::Result<i32> {
+        let id = descriptions::Entity::insert(self.model(server_id))
             .on_conflict(
                 OnConflict::columns(vec![
                     descriptions::Column::ServerId,
-                    descriptions::Column::Text,
+                    descriptions::Column::TextHash,
                 ])
                 .update_columns(vec![
-                    descriptions::Column::Text,
                     descriptions::Column::Bold,
                     descriptions::Column::Italic,
                     descriptions::Column::Underline,
                     descriptions::Column::Strikethrough,
                     descriptions::Column::Obfuscated,
                     descriptions::Column::Colour,
+                    descriptions::Column::LastSeen,
+                    descriptions::Column::Components,
+                    descriptions::Column::Plain,
                 ])
                 .to_owned(),
             )
             .exec(txn)
-            .await?;
+            .await?
+            .last_insert_id;
 
-        Ok(())
+        Ok(id)
     }
 }
 
@@ -296,16 +854,22 @@ pub struct OntosPlayer {
     pub uuid: uuid::Uuid,
     pub last_seen: chrono::NaiveDateTime,
     pub server_id: i32,
+    /// Made up from the name rather than reported by the server, see `Entry::merge_query`
+    #[serde(default)]
+    pub derived_uuid: bool,
 }
 
 impl OntosPlayer {
+    /// Seen when the scan saw them, like their sightings, not when europa got the batch
     pub fn from_sample(list: Vec<Self>, server_id: i32) -> Vec<PlayerModel> {
         list.into_iter()
             .map(|player| PlayerModel {
                 name: ActiveValue::Set(player.name),
                 uuid: ActiveValue::Set(player.uuid.to_string()),
-                last_seen: ActiveValue::Set(chrono::Utc::now().naive_utc()),
+                last_seen: ActiveValue::Set(player.last_seen),
+                first_seen: ActiveValue::Set(player.last_seen),
                 server_id: ActiveValue::Set(server_id),
+                derived_uuid: ActiveValue::Set(player.derived_uuid),
                 ..Default::default()
             })
             .collect()
@@ -315,10 +879,30 @@ impl OntosPlayer {
         txn: &T,
         list: Vec<PlayerModel>,
     ) -> anyhow::Result<()> {
+        // spooled batches can arrive out of order, an older one mustn't undo a newer one
+        let newer = |column: &str| {
+            Expr::cust(&format!(
+                "CASE WHEN excluded.last_seen >= players.last_seen \
+                 THEN excluded.{0} ELSE players.{0} END",
+                column
+            ))
+        };
+
         players::Entity::insert_many(list)
             .on_conflict(
                 OnConflict::columns(vec![players::Column::ServerId, players::Column::Uuid])
-                    .update_column(players::Column::LastSeen)
+                    .values([
+                        (players::Column::Name, newer("name")),
+                        (players::Column::DerivedUuid, newer("derived_uuid")),
+                        (
+                            players::Column::FirstSeen,
+                            Expr::cust("LEAST(players.first_seen, excluded.first_seen)"),
+                        ),
+                        (
+                            players::Column::LastSeen,
+                            Expr::cust("GREATEST(players.last_seen, excluded.last_seen)"),
+                        ),
+                    ])
                     .to_owned(),
             )
             .exec(txn)
@@ -328,49 +912,151 @@ impl OntosPlayer {
     }
 }
 
+/// Clients only show favicons that are exactly this wide and tall
+pub const FAVICON_SIZE: u32 = 64;
+
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct Favicon {
-    pub id: i32,
+    /// Hex SHA-256 of the decoded PNG, only known once stored
+    #[serde(default)]
+    pub hash: Option<String>,
+    /// Hex perceptual hash, for finding icons that look alike, see `util::png`
+    #[serde(default)]
+    pub phash: Option<String>,
+    /// Base64, with or without the data url prefix. Always padded and without the
+    /// prefix coming out of here, see `encode`.
     pub png: Option<String>,
     pub server_id: i32,
+    /// Why the server's last favicon wasn't stored, it has none until a valid one
+    #[serde(default)]
+    pub invalid: Option<InvalidFavicon>,
 }
 
 impl Favicon {
-    pub fn model(self, server_id: i32) -> FaviconModel {
-        FaviconModel {
-            server_id: ActiveValue::Set(server_id),
-            png: ActiveValue::Set(self.png.unwrap()),
-            ..Default::default()
-        }
-    }
-
-    pub fn from_model(model: favicons::Model) -> Self {
+    pub fn from_model(model: favicon_blobs::Model, server_id: i32) -> Self {
         Self {
-            id: model.id,
-            png: Some(model.png),
-            server_id: model.server_id,
+            hash: Some(model.hash),
+            phash: model.phash.map(|phash| format!("{:016x}", phash)),
+            png: Some(Self::encode(&model.png)),
+            server_id,
+            invalid: None,
         }
     }
 
     pub fn empty() -> Self {
         Self {
-            id: 0,
+            hash: None,
+            phash: None,
             png: None,
             server_id: 0,
+            invalid: None,
         }
     }
 
+    /// The one way favicons are handed around, so what's read back is exactly what
+    /// was stored: padded base64 of the PNG without a data url prefix
+    pub fn encode(png: &[u8]) -> String {
+        general_purpose::STANDARD.encode(png)
+    }
+
+    /// Decodes the favicon the way a client would show it, which takes base64 of a
+    /// 64x64 PNG
+    pub fn validate(favicon: &str) -> Result<(Vec<u8>, Image), InvalidFavicon> {
+        let png = decode_favicon(favicon).ok_or(InvalidFavicon::NotBase64)?;
+        if !png.starts_with(&png::SIGNATURE) {
+            return Err(InvalidFavicon::NotPng);
+        }
+
+        match png::dimensions(&png) {
+            Some((FAVICON_SIZE, FAVICON_SIZE)) => {}
+            Some(_) => return Err(InvalidFavicon::WrongSize),
+            None => return Err(InvalidFavicon::Corrupt),
+        }
+
+        match Image::decode(&png) {
+            Ok(image) => Ok((png, image)),
+            Err(e) => {
+                debug!("Favicon doesn't decode: {}", e);
+                Err(InvalidFavicon::Corrupt)
+            }
+        }
+    }
+
+    pub fn hash(png: &[u8]) -> String {
+        format!("{:x}", Sha256::digest(png))
+    }
+
+    /// The perceptual hash of the decoded PNG as stored, `None` if it doesn't decode
+    pub fn phash(png: &[u8]) -> Option<i64> {
+        match Image::decode(png) {
+            Ok(image) => Some(image.phash() as i64),
+            Err(e) => {
+                debug!("Not hashing favicon: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Stores the icon once per hash, however many servers share it, and points
+    /// the server at it. A scan without a favicon means the server dropped it.
     pub async fn insert<T: sea_orm::ConnectionTrait>(
         self,
         txn: &T,
         server_id: i32,
     ) -> anyhow::Result<()> {
-        favicons::Entity::insert(self.model(server_id))
-            .on_conflict(
-                OnConflict::column(favicons::Column::ServerId)
-                    .update_columns(vec![favicons::Column::Png])
-                    .to_owned(),
-            )
+        let Some(favicon) = self.png.as_deref() else {
+            servers::Entity::update_many()
+                .col_expr(servers::Column::FaviconHash, Expr::value(None::<String>))
+                .col_expr(servers::Column::InvalidFavicon, Expr::value(None::<String>))
+                .filter(servers::Column::Id.eq(server_id))
+                .exec(txn)
+                .await?;
+
+            return Ok(());
+        };
+
+        let (png, image) = match Self::validate(favicon) {
+            Ok(valid) => valid,
+            Err(invalid) => {
+                debug!(
+                    "Not storing favicon for server {}: {:?}",
+                    server_id, invalid
+                );
+                servers::Entity::update_many()
+                    .col_expr(servers::Column::FaviconHash, Expr::value(None::<String>))
+                    .col_expr(
+                        servers::Column::InvalidFavicon,
+                        Expr::value(invalid.to_string_but_consistent()),
+                    )
+                    .filter(servers::Column::Id.eq(server_id))
+                    .exec(txn)
+                    .await?;
+
+                return Ok(());
+            }
+        };
+        let hash = Self::hash(&png);
+
+        favicon_blobs::Entity::insert(favicon_blobs::ActiveModel {
+            hash: ActiveValue::Set(hash.clone()),
+            size: ActiveValue::Set(png.len() as i32),
+            phash: ActiveValue::Set(Some(image.phash() as i64)),
+            png: ActiveValue::Set(png),
+            invalid: ActiveValue::Set(None),
+            created_at: ActiveValue::Set(chrono::Utc::now().naive_utc()),
+        })
+        .on_conflict(
+            OnConflict::column(favicon_blobs::Column::Hash)
+                .do_nothing()
+                .to_owned(),
+        )
+        .exec_without_returning(txn)
+        .await?;
+
+        servers::Entity::update_many()
+            .col_expr(servers::Column::FaviconHash, Expr::value(hash))
+            .col_expr(servers::Column::InvalidFavicon, Expr::value(None::<String>))
+            .filter(servers::Column::Id.eq(server_id))
             .exec(txn)
             .await?;
 
@@ -378,10 +1064,50 @@ impl Favicon {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+/// Why a favicon was rejected, see `Favicon::validate`
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidFavicon {
+    NotBase64,
+    /// Decodes, but not to a PNG
+    NotPng,
+    /// A PNG, but not 64x64
+    WrongSize,
+    /// Starts like a 64x64 PNG but the image doesn't decode
+    Corrupt,
+}
+
+impl InvalidFavicon {
+    pub fn to_string_but_consistent(&self) -> String {
+        match self {
+            InvalidFavicon::NotBase64 => "NotBase64".to_string(),
+            InvalidFavicon::NotPng => "NotPng".to_string(),
+            InvalidFavicon::WrongSize => "WrongSize".to_string(),
+            InvalidFavicon::Corrupt => "Corrupt".to_string(),
+        }
+    }
+
+    pub fn from_str_lossy(input: &str) -> Self {
+        match input {
+            "NotBase64" => InvalidFavicon::NotBase64,
+            "NotPng" => InvalidFavicon::NotPng,
+            "WrongSize" => InvalidFavicon::WrongSize,
+            _ => InvalidFavicon::Corrupt,
+        }
+    }
+}
+
+/// What the login probe found, `Unknown` until a server has been probed
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
 pub enum OnlineStatus {
+    /// Asked us to authenticate with Mojang
     Online,
+    /// Let us in without authenticating
     Offline,
+    Whitelisted,
+    /// Wants a modded client, e.g. a Forge handshake
+    Modded,
+    #[default]
+    Unknown,
 }
 
 impl OnlineStatus {
@@ -389,6 +1115,248 @@ impl OnlineStatus {
         match self {
             OnlineStatus::Online => "Online".to_string(),
             OnlineStatus::Offline => "Offline".to_string(),
+            OnlineStatus::Whitelisted => "Whitelisted".to_string(),
+            OnlineStatus::Modded => "Modded".to_string(),
+            OnlineStatus::Unknown => "Unknown".to_string(),
+        }
+    }
+}
+
+/// Whether a known server is still around, see `DbConn::record_failure`
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ServerState {
+    /// Answered its last ping
+    #[default]
+    Online,
+    /// Stopped answering recently, it may well come back
+    Offline,
+    /// Hasn't answered in a long time, dashboards shouldn't show it as current
+    Dead,
+}
+
+impl ServerState {
+    pub fn to_string_but_consistent(&self) -> String {
+        match self {
+            ServerState::Online => "Online".to_string(),
+            ServerState::Offline => "Offline".to_string(),
+            ServerState::Dead => "Dead".to_string(),
+        }
+    }
+
+    pub fn from_str_lossy(input: &str) -> Self {
+        match input {
+            "Offline" => ServerState::Offline,
+            "Dead" => ServerState::Dead,
+            _ => ServerState::Online,
+        }
+    }
+}
+
+/// Which server list ping flavour the server answered
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+pub enum PingKind {
+    #[default]
+    Modern,
+    Legacy,
+    Raknet,
+}
+
+impl PingKind {
+    pub fn to_string_but_consistent(&self) -> String {
+        match self {
+            PingKind::Modern => "Modern".to_string(),
+            PingKind::Legacy => "Legacy".to_string(),
+            PingKind::Raknet => "Raknet".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Edition {
+    #[default]
+    Java,
+    Bedrock,
+}
+
+impl Edition {
+    pub fn to_string_but_consistent(&self) -> String {
+        match self {
+            Edition::Java => "Java".to_string(),
+            Edition::Bedrock => "Bedrock".to_string(),
+        }
+    }
+
+    pub fn from_str_lossy(input: &str) -> Self {
+        match input {
+            "Bedrock" => Edition::Bedrock,
+            _ => Edition::Java,
+        }
+    }
+
+    pub fn default_port(&self) -> u16 {
+        match self {
+            Edition::Java => 25565,
+            Edition::Bedrock => bedrock::DEFAULT_PORT,
+        }
+    }
+}
+
+/// Which server list ping flavour(s) a scan should try
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+pub enum PingMode {
+    /// Modern ping, falling back to legacy if the server answered with something we can't read
+    #[default]
+    Auto,
+    Modern,
+    Legacy,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Exclusion {
+    pub id: i32,
+    pub cidr: String,
+    pub reason: String,
+    pub created_at: NaiveDateTime,
+}
+
+impl Exclusion {
+    /// Accepts `1.2.3.0/24` or a bare address, which is treated as a /32
+    pub fn parse_cidr(input: &str) -> anyhow::Result<Ipv4Net> {
+        let input = input.trim();
+        if let Ok(addr) = input.parse::<Ipv4Addr>() {
+            return Ok(Ipv4Net::from(addr));
+        }
+
+        let net = input
+            .parse::<Ipv4Net>()
+            .map_err(|_| anyhow!("invalid CIDR block: {}", input))?;
+
+        Ok(net.trunc())
+    }
+
+    pub fn from_model(model: exclusions::Model) -> Self {
+        Self {
+            id: model.id,
+            cidr: model.cidr,
+            reason: model.reason,
+            created_at: model.created_at,
+        }
+    }
+}
+
+/// Every range voyager must never connect to, checked right before each ping.
+#[derive(Debug, Clone, Default)]
+pub struct ExclusionList {
+    ranges: IpRange<Ipv4Net>,
+}
+
+impl ExclusionList {
+    pub fn new(list: &[Exclusion]) -> Self {
+        let mut ranges = IpRange::new();
+        for exclusion in list {
+            match Exclusion::parse_cidr(&exclusion.cidr) {
+                Ok(net) => {
+                    ranges.add(net);
+                }
+                Err(e) => warn!("Skipping exclusion {}: {}", exclusion.id, e),
+            }
         }
+
+        ranges.simplify();
+        Self { ranges }
+    }
+
+    /// One CIDR block or address per line, blank lines and `#` comments are ignored.
+    /// Unlike `new` a bad line is an error, a typo shouldn't quietly unexclude a range.
+    pub fn parse(input: &str) -> anyhow::Result<Self> {
+        let mut ranges = IpRange::new();
+        for line in input.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            ranges.add(Exclusion::parse_cidr(line)?);
+        }
+
+        ranges.simplify();
+        Ok(Self { ranges })
+    }
+
+    /// `host` is either a bare address or `host:port`, hostnames are never excluded here.
+    pub fn contains(&self, host: &str) -> bool {
+        let ip = host.split(':').next().unwrap_or_default();
+        match ip.parse::<Ipv4Addr>() {
+            Ok(addr) => self.ranges.contains(&addr),
+            Err(_) => false,
+        }
+    }
+}
+
+/// Which servers a rescan schedule picks up each time it runs
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TargetSelector {
+    /// The `limit` most overdue addresses by rescan priority, see `database::priority`
+    Stale { limit: u64 },
+    /// Servers that had at least `min` players online when last scanned
+    PlayersOnline {
+        #[serde(default = "TargetSelector::default_min_players")]
+        min: i32,
+    },
+    /// Servers that haven't answered a ping in `days` days
+    NotSeenFor { days: i64 },
+    /// Servers whose version contains `version`, e.g. `1.20`
+    Version { version: String },
+    /// Every known address
+    All,
+}
+
+impl TargetSelector {
+    fn default_min_players() -> i32 {
+        1
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RescanSchedule {
+    pub id: i32,
+    pub name: String,
+    pub cron: Cron,
+    pub selector: TargetSelector,
+    /// Seconds per target
+    pub timeout: i32,
+    pub workers: i32,
+    pub enabled: bool,
+    pub last_run: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+impl RescanSchedule {
+    pub fn from_model(model: rescan_schedules::Model) -> anyhow::Result<Self> {
+        Ok(Self {
+            id: model.id,
+            name: model.name,
+            cron: Cron::parse(&model.cron)?,
+            selector: serde_json::from_str(&model.selector)?,
+            timeout: model.timeout,
+            workers: model.workers,
+            enabled: model.enabled,
+            last_run: model.last_run,
+            created_at: model.created_at,
+        })
+    }
+
+    /// Everything but `id`, `last_run` and `created_at`, which the database owns
+    pub fn to_active_model(&self) -> anyhow::Result<rescan_schedules::ActiveModel> {
+        Ok(rescan_schedules::ActiveModel {
+            name: ActiveValue::Set(self.name.clone()),
+            cron: ActiveValue::Set(self.cron.to_string()),
+            selector: ActiveValue::Set(serde_json::to_string(&self.selector)?),
+            timeout: ActiveValue::Set(self.timeout),
+            workers: ActiveValue::Set(self.workers),
+            enabled: ActiveValue::Set(self.enabled),
+            ..Default::default()
+        })
     }
 }
@@ -0,0 +1,479 @@
+//! Hands out scan targets to voyager workers in leased batches, so any number of
+//! them can share one queue without pinging the same server twice.
+//!
+//! Workers register, then lease batches and keep them alive with heartbeats.
+//! A lease whose worker stops heartbeating expires and its targets go back to
+//! the front of the queue for someone else.
+//!
+//! Targets are queued with the options they should be scanned with, a lease only
+//! ever holds targets that share them.
+//!
+//! The queue lives in memory only. A restarted europa starts empty, everything
+//! that was queued or leased is lost and workers have to register again. Rescan
+//! schedules enqueue their targets again on their next run, anything enqueued by
+//! hand through `/queue` has to be sent again.
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    time::{Duration, Instant},
+};
+
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::util::types::{Edition, OntosAddress, PingMode};
+
+/// How long a worker or lease lives without a heartbeat when `COORDINATOR_LEASE_SECS` isn't set,
+/// idle workers only check in every 10s so keep it well above that
+const DEFAULT_LEASE_TIMEOUT: Duration = Duration::from_secs(60);
+/// Upper bound on the size of a single lease
+pub const MAX_LEASE_SIZE: usize = 1000;
+
+#[derive(Debug)]
+pub struct Coordinator {
+    queue: VecDeque<(OntosAddress, ScanOptions)>,
+    /// Everything queued or leased, so enqueueing a target twice is a no-op
+    pending: HashSet<(String, Edition)>,
+    workers: HashMap<u64, Worker>,
+    leases: HashMap<u64, Lease>,
+    next_id: u64,
+    timeout: Duration,
+}
+
+#[derive(Debug)]
+struct Worker {
+    name: String,
+    last_seen: Instant,
+}
+
+#[derive(Debug)]
+struct Lease {
+    worker: u64,
+    targets: Vec<OntosAddress>,
+    options: ScanOptions,
+    expires: Instant,
+}
+
+/// How whoever queued the targets wants them scanned
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ScanOptions {
+    /// Seconds per target, the worker's own default when not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<i32>,
+    #[serde(default)]
+    pub ping: PingMode,
+    #[serde(default)]
+    pub query: bool,
+    #[serde(default)]
+    pub login: bool,
+    /// Tell the sink about targets that didn't answer, only worth it for known addresses
+    #[serde(default)]
+    pub report_failures: bool,
+}
+
+/// What a worker gets back when it leases a batch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LeaseInfo {
+    pub id: u64,
+    pub targets: Vec<OntosAddress>,
+    #[serde(default)]
+    pub options: ScanOptions,
+    /// Heartbeat at least this often or the lease is handed to someone else
+    pub timeout_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkerInfo {
+    pub id: u64,
+    pub name: String,
+    pub leases: usize,
+    pub idle_secs: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueueStats {
+    pub queued: usize,
+    pub leased: usize,
+    pub workers: Vec<WorkerInfo>,
+}
+
+impl Coordinator {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            queue: VecDeque::new(),
+            pending: HashSet::new(),
+            workers: HashMap::new(),
+            leases: HashMap::new(),
+            next_id: 1,
+            timeout,
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let timeout = std::env::var("COORDINATOR_LEASE_SECS")
+            .ok()
+            .and_then(|secs| secs.parse().ok())
+            .map_or(DEFAULT_LEASE_TIMEOUT, Duration::from_secs);
+
+        Self::new(timeout)
+    }
+
+    fn next_id(&mut self) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    pub fn register(&mut self, name: String) -> u64 {
+        let id = self.next_id();
+        debug!("Worker {} ({}) registered", id, name);
+        self.workers.insert(
+            id,
+            Worker {
+                name,
+                last_seen: Instant::now(),
+            },
+        );
+
+        id
+    }
+
+    /// Keeps the worker and every lease it holds alive, false if it was already reaped
+    pub fn heartbeat(&mut self, worker: u64) -> bool {
+        let now = Instant::now();
+        let Some(info) = self.workers.get_mut(&worker) else {
+            return false;
+        };
+        info.last_seen = now;
+
+        for lease in self.leases.values_mut().filter(|l| l.worker == worker) {
+            lease.expires = now + self.timeout;
+        }
+
+        true
+    }
+
+    /// Adds targets to the back of the queue, skipping any already queued or leased
+    /// whatever their options. Returns how many were added.
+    pub fn enqueue(&mut self, targets: Vec<OntosAddress>, options: ScanOptions) -> usize {
+        let mut added = 0;
+        for target in targets {
+            if self.pending.insert(key(&target)) {
+                self.queue.push_back((target, options));
+                added += 1;
+            }
+        }
+
+        added
+    }
+
+    /// Counts as a heartbeat. `None` if the worker isn't registered, `Some(None)` when
+    /// the queue is empty, an empty lease isn't handed out. The lease stops early at
+    /// the first target queued with other options than the front one.
+    pub fn lease(&mut self, worker: u64, size: usize) -> Option<Option<LeaseInfo>> {
+        if !self.heartbeat(worker) {
+            return None;
+        }
+
+        let Some((_, options)) = self.queue.front().cloned() else {
+            return Some(None);
+        };
+        let size = self
+            .queue
+            .iter()
+            .take(size.clamp(1, MAX_LEASE_SIZE))
+            .take_while(|(_, queued)| *queued == options)
+            .count();
+
+        let targets = self
+            .queue
+            .drain(..size)
+            .map(|(target, _)| target)
+            .collect::<Vec<_>>();
+        let id = self.next_id();
+        self.leases.insert(
+            id,
+            Lease {
+                worker,
+                targets: targets.clone(),
+                options,
+                expires: Instant::now() + self.timeout,
+            },
+        );
+
+        Some(Some(LeaseInfo {
+            id,
+            targets,
+            options,
+            timeout_secs: self.timeout.as_secs(),
+        }))
+    }
+
+    /// Drops a finished lease, false if it isn't this worker's (anymore)
+    pub fn complete(&mut self, worker: u64, lease: u64) -> bool {
+        let Some(lease) = self.take_lease(worker, lease) else {
+            return false;
+        };
+
+        for target in &lease.targets {
+            self.pending.remove(&key(target));
+        }
+
+        true
+    }
+
+    /// Gives an unfinished lease back, its targets are the next ones handed out
+    pub fn release(&mut self, worker: u64, lease: u64) -> bool {
+        let Some(lease) = self.take_lease(worker, lease) else {
+            return false;
+        };
+
+        self.requeue(lease);
+        true
+    }
+
+    fn take_lease(&mut self, worker: u64, lease: u64) -> Option<Lease> {
+        match self.leases.get(&lease) {
+            Some(l) if l.worker == worker => self.leases.remove(&lease),
+            _ => None,
+        }
+    }
+
+    fn requeue(&mut self, lease: Lease) {
+        for target in lease.targets.into_iter().rev() {
+            self.queue.push_front((target, lease.options));
+        }
+    }
+
+    /// Forgets silent workers and requeues every lease that wasn't kept alive
+    pub fn reap(&mut self) {
+        let now = Instant::now();
+        let timeout = self.timeout;
+
+        self.workers.retain(|id, worker| {
+            let alive = now.duration_since(worker.last_seen) < timeout;
+            if !alive {
+                warn!("Worker {} ({}) stopped responding", id, worker.name);
+            }
+            alive
+        });
+
+        let expired = self
+            .leases
+            .iter()
+            .filter(|(_, lease)| lease.expires <= now)
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+
+        for id in expired {
+            if let Some(lease) = self.leases.remove(&id) {
+                warn!(
+                    "Lease {} expired, requeueing {} targets",
+                    id,
+                    lease.targets.len()
+                );
+                self.requeue(lease);
+            }
+        }
+    }
+
+    pub fn stats(&self) -> QueueStats {
+        let now = Instant::now();
+        let mut workers = self
+            .workers
+            .iter()
+            .map(|(id, worker)| WorkerInfo {
+                id: *id,
+                name: worker.name.clone(),
+                leases: self.leases.values().filter(|l| l.worker == *id).count(),
+                idle_secs: now.duration_since(worker.last_seen).as_secs_f64(),
+            })
+            .collect::<Vec<_>>();
+        workers.sort_by_key(|worker| worker.id);
+
+        QueueStats {
+            queued: self.queue.len(),
+            leased: self.leases.values().map(|l| l.targets.len()).sum(),
+            workers,
+        }
+    }
+}
+
+fn key(target: &OntosAddress) -> (String, Edition) {
+    (target.host.clone(), target.edition)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(60);
+
+    fn targets(hosts: &[&str]) -> Vec<OntosAddress> {
+        hosts
+            .iter()
+            .map(|host| OntosAddress {
+                host: host.to_string(),
+                edition: Edition::Java,
+                hostname: None,
+                srv: false,
+            })
+            .collect()
+    }
+
+    fn hosts(targets: &[OntosAddress]) -> Vec<&str> {
+        targets.iter().map(|target| target.host.as_str()).collect()
+    }
+
+    fn queued(coordinator: &Coordinator) -> Vec<&str> {
+        coordinator
+            .queue
+            .iter()
+            .map(|(target, _)| target.host.as_str())
+            .collect()
+    }
+
+    /// Moves every deadline back past the timeout, as if nobody heard from anyone
+    fn age(coordinator: &mut Coordinator) {
+        let past = Instant::now() - coordinator.timeout;
+        for worker in coordinator.workers.values_mut() {
+            worker.last_seen = past;
+        }
+        for lease in coordinator.leases.values_mut() {
+            lease.expires = past;
+        }
+    }
+
+    #[test]
+    fn lease_expiry_requeues_at_front() {
+        let mut coordinator = Coordinator::new(TIMEOUT);
+        let worker = coordinator.register("a".to_string());
+        coordinator.enqueue(targets(&["1", "2", "3", "4"]), ScanOptions::default());
+
+        let lease = coordinator.lease(worker, 2).unwrap().unwrap();
+        assert_eq!(hosts(&lease.targets), ["1", "2"]);
+        assert_eq!(lease.timeout_secs, 60);
+        coordinator.reap();
+        assert_eq!(queued(&coordinator), ["3", "4"]);
+
+        age(&mut coordinator);
+        coordinator.reap();
+        assert!(coordinator.leases.is_empty());
+        assert_eq!(queued(&coordinator), ["1", "2", "3", "4"]);
+        // the expired lease can't be completed anymore
+        assert!(!coordinator.complete(worker, lease.id));
+    }
+
+    #[test]
+    fn heartbeat_extends_leases() {
+        let mut coordinator = Coordinator::new(TIMEOUT);
+        let worker = coordinator.register("a".to_string());
+        coordinator.enqueue(targets(&["1", "2"]), ScanOptions::default());
+        let lease = coordinator.lease(worker, 10).unwrap().unwrap();
+
+        age(&mut coordinator);
+        assert!(coordinator.heartbeat(worker));
+        coordinator.reap();
+
+        assert!(coordinator.workers.contains_key(&worker));
+        assert!(coordinator.leases.contains_key(&lease.id));
+        assert!(coordinator.queue.is_empty());
+        assert!(coordinator.complete(worker, lease.id));
+    }
+
+    #[test]
+    fn pending_dedups() {
+        let mut coordinator = Coordinator::new(TIMEOUT);
+        let worker = coordinator.register("a".to_string());
+        let options = ScanOptions::default();
+
+        assert_eq!(coordinator.enqueue(targets(&["1", "2", "1"]), options), 2);
+        let lease = coordinator.lease(worker, 1).unwrap().unwrap();
+        // one queued, one leased, both still pending
+        assert_eq!(coordinator.enqueue(targets(&["1", "2", "3"]), options), 1);
+        assert_eq!(queued(&coordinator), ["2", "3"]);
+
+        // once done a target can be queued again
+        assert!(coordinator.complete(worker, lease.id));
+        assert_eq!(coordinator.enqueue(targets(&["1"]), options), 1);
+
+        // another edition is another target
+        let mut bedrock = targets(&["2"]);
+        bedrock[0].edition = Edition::Bedrock;
+        assert_eq!(coordinator.enqueue(bedrock, options), 1);
+    }
+
+    #[test]
+    fn wrong_worker() {
+        let mut coordinator = Coordinator::new(TIMEOUT);
+        let a = coordinator.register("a".to_string());
+        let b = coordinator.register("b".to_string());
+        coordinator.enqueue(targets(&["1", "2"]), ScanOptions::default());
+        let lease = coordinator.lease(a, 10).unwrap().unwrap();
+
+        assert!(!coordinator.complete(b, lease.id));
+        assert!(!coordinator.release(b, lease.id));
+        assert!(!coordinator.complete(a, lease.id + 100));
+        assert!(coordinator.leases.contains_key(&lease.id));
+
+        assert!(coordinator.release(a, lease.id));
+        assert_eq!(queued(&coordinator), ["1", "2"]);
+        assert!(!coordinator.complete(a, lease.id));
+    }
+
+    #[test]
+    fn reaps_workers() {
+        let mut coordinator = Coordinator::new(TIMEOUT);
+        let worker = coordinator.register("a".to_string());
+        coordinator.enqueue(targets(&["1"]), ScanOptions::default());
+        coordinator.lease(worker, 10).unwrap().unwrap();
+
+        age(&mut coordinator);
+        coordinator.reap();
+
+        assert!(coordinator.stats().workers.is_empty());
+        assert!(!coordinator.heartbeat(worker));
+        assert!(coordinator.lease(worker, 10).is_none());
+        assert_eq!(queued(&coordinator), ["1"]);
+
+        // a new registration gets a fresh id
+        let again = coordinator.register("a".to_string());
+        assert_ne!(again, worker);
+        assert_eq!(
+            coordinator.lease(again, 10).unwrap().unwrap().targets.len(),
+            1
+        );
+    }
+
+    #[test]
+    fn leases_share_options() {
+        let mut coordinator = Coordinator::new(TIMEOUT);
+        let worker = coordinator.register("a".to_string());
+        let query = ScanOptions {
+            query: true,
+            ..Default::default()
+        };
+        coordinator.enqueue(targets(&["1", "2"]), ScanOptions::default());
+        coordinator.enqueue(targets(&["3"]), query);
+        coordinator.enqueue(targets(&["4"]), ScanOptions::default());
+
+        let first = coordinator.lease(worker, 10).unwrap().unwrap();
+        assert_eq!(hosts(&first.targets), ["1", "2"]);
+        assert_eq!(first.options, ScanOptions::default());
+
+        let second = coordinator.lease(worker, 10).unwrap().unwrap();
+        assert_eq!(hosts(&second.targets), ["3"]);
+        assert_eq!(second.options, query);
+
+        // a released lease keeps its options
+        assert!(coordinator.release(worker, second.id));
+        let again = coordinator.lease(worker, 10).unwrap().unwrap();
+        assert_eq!(again.options, query);
+
+        assert_eq!(
+            hosts(&coordinator.lease(worker, 10).unwrap().unwrap().targets),
+            ["4"]
+        );
+        assert!(coordinator.lease(worker, 10).unwrap().is_none());
+    }
+}
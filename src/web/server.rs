@@ -1,28 +1,76 @@
 #![allow(dead_code)]
 use std::{
+    collections::BTreeMap,
     net::{IpAddr, Ipv4Addr, SocketAddr},
     sync::Arc,
+    time::Duration,
 };
 
 use axum::{
-    routing::{get, post},
+    body::Bytes,
+    extract::{Path, Query},
+    http::{header, HeaderMap, StatusCode},
+    response::IntoResponse,
+    routing::{delete, get, post},
     Extension, Json, Router,
 };
-use log::error;
-use sea_orm::DatabaseTransaction;
+use chrono::NaiveDateTime;
+use log::{error, info, warn};
+use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 use tokio::sync::Mutex;
 
 use crate::{
-    database::{DbConn, DbStats},
-    util::types::Entry,
+    database::{
+        history::{MotdChange, Observation, ObservationBucket, Resolution},
+        priority::RescanPriority,
+        profile::PlayerProfile,
+        sessions::{self, PlayerPresence, PlayerSession, SightingScope},
+        similarity::{self, SimilarServer},
+        DbConn, DbStats,
+    },
+    util::{
+        misc::decode_favicon,
+        png,
+        types::{
+            Entry, Exclusion, Favicon, OntosAddress, RejectedRecord, ScanFailure, ServerState,
+        },
+    },
+    web::coordinator::{Coordinator, LeaseInfo, QueueStats, ScanOptions},
 };
 
+/// How often expired leases are looked for
+const REAP_INTERVAL: Duration = Duration::from_secs(5);
+/// Default and upper bound for how many addresses `/priorities` lists
+const DEFAULT_PRIORITIES: usize = 100;
+const MAX_PRIORITIES: usize = 1000;
+/// Default and upper bound for how many servers a paged list returns
+const DEFAULT_PAGE: u64 = 100;
+const MAX_PAGE: u64 = 1000;
+/// How far back `/servers/:id/history` looks without a `from`
+const DEFAULT_HISTORY_DAYS: i64 = 7;
+/// More raw observations than this have to be asked for hourly or daily
+const MAX_OBSERVATIONS: u64 = 10_000;
+/// Sessions are rebuilt in memory, so a range can't cover more sightings than this
+const MAX_SIGHTINGS: u64 = 50_000;
+/// Longest `gap` a session can bridge, a week apart is two visits anyway
+const MAX_SESSION_GAP_MINUTES: i64 = 7 * 24 * 60;
+/// How many uuids `/players?name=` looks up at most
+const MAX_NAME_MATCHES: u64 = 100;
+/// How long clients can keep a favicon before checking its ETag again, servers do
+/// change them so not forever
+const FAVICON_MAX_AGE_SECS: u64 = 3600;
+
+/// Served for servers without a favicon, a dark grey tile with a lighter square
+/// in the middle
+static PLACEHOLDER: &[u8] = include_bytes!("placeholder.png");
+static PLACEHOLDER_HASH: Lazy<String> = Lazy::new(|| Favicon::hash(PLACEHOLDER));
+
 #[derive(Clone, Debug)]
 struct AppState {
     database: DbConn,
-    txn_queue: Arc<Mutex<Vec<DatabaseTransaction>>>,
     stats: Arc<Mutex<DbStats>>,
+    coordinator: Arc<Mutex<Coordinator>>,
 }
 
 pub async fn start() -> anyhow::Result<()> {
@@ -32,32 +80,29 @@ pub async fn start() -> anyhow::Result<()> {
 
     let stats = conn.create_stats().await?;
 
+    let backfill = conn.clone();
+    tokio::spawn(async move {
+        match backfill.backfill_favicon_phashes().await {
+            Ok((0, 0)) => {}
+            Ok((hashed, corrupt)) => info!(
+                "Computed perceptual hashes for {} favicons, {} didn't decode",
+                hashed, corrupt
+            ),
+            Err(e) => error!("Error computing favicon perceptual hashes: {}", e),
+        }
+    });
+
     let state = AppState {
         database: conn,
-        txn_queue: Arc::new(Mutex::new(Vec::new())),
         stats: Arc::new(Mutex::new(stats)),
+        coordinator: Arc::new(Mutex::new(Coordinator::from_env())),
     };
 
-    let queue = Arc::clone(&state.txn_queue);
-    tokio::task::spawn(async move {
+    let coordinator = Arc::clone(&state.coordinator);
+    tokio::spawn(async move {
         loop {
-            let mut queue_copy = Vec::new();
-            {
-                let mut queue = queue.lock().await;
-                if queue.is_empty() {
-                    continue;
-                }
-
-                std::mem::swap(&mut queue_copy, &mut *queue);
-            }
-
-            for txn in queue_copy {
-                if let Err(e) = txn.commit().await {
-                    error!("Error committing transaction: {}", e);
-                }
-            }
-
-            tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+            tokio::time::sleep(REAP_INTERVAL).await;
+            coordinator.lock().await.reap();
         }
     });
 
@@ -80,7 +125,30 @@ fn app() -> Router {
     Router::new()
         .route("/", get(index))
         .route("/servers", get(get_server))
+        .route("/servers/states", get(count_states))
+        .route("/servers/search", get(search_servers))
+        .route("/servers/states/:state", get(servers_in_state))
+        .route("/servers/:id/history", get(server_history))
+        .route("/servers/:id/sessions", get(server_sessions))
+        .route("/servers/:id/descriptions", get(server_descriptions))
+        .route("/servers/:id/similar", get(similar_servers))
+        .route("/servers/:id/favicon.png", get(server_favicon))
+        .route("/favicons/:hash/servers", get(favicon_servers))
+        .route("/favicons/similar", post(similar_to_upload))
+        .route("/players", get(find_players))
+        .route("/players/:uuid", get(get_player))
+        .route("/players/:uuid/sessions", get(player_sessions))
         .route("/upload", post(upload_servers))
+        .route("/exclusions", get(list_exclusions).post(add_exclusion))
+        .route("/exclusions/:id", delete(remove_exclusion))
+        .route("/priorities", get(list_priorities))
+        .route("/ips/:ip/priority", get(ip_priority))
+        .route("/queue", get(queue_stats).post(enqueue))
+        .route("/workers", post(register_worker))
+        .route("/workers/:id/heartbeat", post(heartbeat))
+        .route("/workers/:id/lease", post(lease))
+        .route("/workers/:id/leases/:lease/complete", post(complete_lease))
+        .route("/workers/:id/leases/:lease/release", post(release_lease))
 }
 
 // ! Remember this on return types for routes
@@ -91,6 +159,24 @@ pub struct WebRequest {
 
     // upload_servers
     pub servers: Option<Vec<Entry>>,
+    pub failures: Option<Vec<ScanFailure>>,
+    pub batch: Option<u64>,
+
+    // add_exclusion
+    pub cidr: Option<String>,
+    pub reason: Option<String>,
+
+    // enqueue
+    pub targets: Option<Vec<OntosAddress>>,
+    pub rescan: Option<bool>,
+    /// How the targets are scanned, the defaults are a plain ping without failures
+    pub options: Option<ScanOptions>,
+
+    // register_worker
+    pub name: Option<String>,
+
+    // lease
+    pub size: Option<usize>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -101,12 +187,90 @@ pub struct Response {
     pub data: Option<ResponseData>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Default)]
 pub struct ResponseData {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub results: Option<Vec<Entry>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stats: Option<Stats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exclusions: Option<Vec<Exclusion>>,
+    /// Echoed back once every server in an uploaded batch is committed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub batch: Option<u64>,
+    /// Whatever in the batch couldn't be stored, the rest still was
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rejected: Option<Vec<RejectedRecord>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub worker: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lease: Option<LeaseInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub queue: Option<QueueStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub priorities: Option<Vec<RescanPriority>>,
+    /// Servers per state, see `ServerState`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub states: Option<BTreeMap<String, i64>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub observations: Option<Vec<Observation>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub history: Option<Vec<ObservationBucket>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub players: Option<Vec<PlayerPresence>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sessions: Option<Vec<PlayerSession>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profiles: Option<Vec<PlayerProfile>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub descriptions: Option<Vec<MotdChange>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub similar: Option<Vec<SimilarServer>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PriorityParams {
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PageParams {
+    limit: Option<u64>,
+    offset: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SimilarParams {
+    distance: Option<u32>,
+    limit: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HistoryParams {
+    from: Option<NaiveDateTime>,
+    to: Option<NaiveDateTime>,
+    #[serde(default)]
+    resolution: Resolution,
+}
+
+#[derive(Debug, Deserialize)]
+struct PlayerParams {
+    name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchParams {
+    motd: Option<String>,
+    limit: Option<u64>,
+    offset: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SessionParams {
+    from: Option<NaiveDateTime>,
+    to: Option<NaiveDateTime>,
+    /// Minutes between sightings that still count as one session
+    gap: Option<i64>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -129,7 +293,6 @@ async fn index(Extension(state): Extension<AppState>) -> Json<Response> {
     let stats = state.stats.lock().await;
 
     let data = ResponseData {
-        results: None,
         stats: Some(Stats {
             status: "ok".to_string(),
             runtime_mode,
@@ -137,6 +300,7 @@ async fn index(Extension(state): Extension<AppState>) -> Json<Response> {
             stored_servers: stats.servers,
             stored_players: stats.players,
         }),
+        ..Default::default()
     };
 
     success(None, Some(data))
@@ -162,7 +326,7 @@ async fn get_server(
 
     let data = ResponseData {
         results: Some(entry),
-        stats: None,
+        ..Default::default()
     };
 
     success(None, Some(data))
@@ -172,33 +336,798 @@ async fn upload_servers(
     Extension(state): Extension<AppState>,
     mut args: Json<WebRequest>,
 ) -> Json<Response> {
-    let Some(servers) = args.servers.take() else {
+    let servers = args.servers.take().unwrap_or_default();
+    let failures = args.failures.take().unwrap_or_default();
+    if servers.is_empty() && failures.is_empty() {
         return error("No servers provided");
+    }
+
+    // everything is committed before answering, voyager deletes the batch on success
+    let rejected = match state.database.add_batch(servers, &failures).await {
+        Ok(rejected) => rejected,
+        Err(e) => {
+            error!("Error adding batch: {}", e);
+            return error("Internal server error");
+        }
     };
+    for record in &rejected {
+        warn!(
+            "Rejected record {} of batch {:?}: {}",
+            record.index, args.batch, record.reason
+        );
+    }
 
-    let Ok(conn) = DbConn::new().await else {
-        return error("Internal server error");
+    if let Err(e) = update_stats(Extension(state)).await {
+        error!("Error updating stats: {}", e);
+    };
+
+    let data = ResponseData {
+        batch: args.batch,
+        rejected: (!rejected.is_empty()).then_some(rejected),
+        ..Default::default()
+    };
+
+    success(None, Some(data))
+}
+
+async fn count_states(Extension(state): Extension<AppState>) -> Json<Response> {
+    let states = match state.database.count_states().await {
+        Ok(states) => states,
+        Err(e) => {
+            error!("Error counting server states: {}", e);
+            return error("Internal server error");
+        }
+    };
+
+    let data = ResponseData {
+        states: Some(states),
+        ..Default::default()
+    };
+
+    success(None, Some(data))
+}
+
+/// Servers that are `online`, `offline` or `dead`, most recently updated first
+async fn servers_in_state(
+    Extension(state): Extension<AppState>,
+    Path(server_state): Path<String>,
+    Query(params): Query<PageParams>,
+) -> Json<Response> {
+    let server_state = match server_state.to_lowercase().as_str() {
+        "online" => ServerState::Online,
+        "offline" => ServerState::Offline,
+        "dead" => ServerState::Dead,
+        _ => return error("Unknown state"),
+    };
+    let limit = params.limit.unwrap_or(DEFAULT_PAGE).min(MAX_PAGE);
+    let offset = params.offset.unwrap_or_default();
+
+    let list = match state
+        .database
+        .get_servers_in_state(server_state, limit, offset)
+        .await
+    {
+        Ok(list) => list,
+        Err(e) => {
+            error!("Error fetching servers by state: {}", e);
+            return error("Internal server error");
+        }
+    };
+
+    let data = ResponseData {
+        results: Some(list),
+        ..Default::default()
     };
 
-    for s in servers {
-        let txn = match conn.add_server(s).await {
-            Ok(txn) => txn,
+    success(None, Some(data))
+}
+
+/// A server's observations from `from` (a week ago) up to `to` (now), raw in
+/// `observations` or summarised per hour or day in `history`
+async fn server_history(
+    Extension(state): Extension<AppState>,
+    Path(server_id): Path<i32>,
+    Query(params): Query<HistoryParams>,
+) -> Json<Response> {
+    let db = state.database;
+    let (from, to) = match time_range(params.from, params.to) {
+        Ok(range) => range,
+        Err(e) => return error(e),
+    };
+
+    match db.server_exists(server_id).await {
+        Ok(true) => {}
+        Ok(false) => return error("Unknown server"),
+        Err(e) => {
+            error!("Error fetching server: {}", e);
+            return error("Internal server error");
+        }
+    }
+
+    let data = match params.resolution {
+        Resolution::Raw => {
+            match db.count_observations(server_id, from, to).await {
+                Ok(count) if count > MAX_OBSERVATIONS => {
+                    return error("Too many observations, ask for an hourly or daily resolution")
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    error!("Error counting observations: {}", e);
+                    return error("Internal server error");
+                }
+            }
+
+            match db.get_observations(server_id, from, to).await {
+                Ok(list) => ResponseData {
+                    observations: Some(list),
+                    ..Default::default()
+                },
+                Err(e) => {
+                    error!("Error fetching observations: {}", e);
+                    return error("Internal server error");
+                }
+            }
+        }
+        resolution => match db.get_history(server_id, from, to, resolution).await {
+            Ok(list) => ResponseData {
+                history: Some(list),
+                ..Default::default()
+            },
             Err(e) => {
-                error!("Error adding server: {}", e);
-                continue;
+                error!("Error fetching history: {}", e);
+                return error("Internal server error");
             }
-        };
+        },
+    };
 
-        state.txn_queue.lock().await.push(txn);
+    success(None, Some(data))
+}
+
+/// Every server using the favicon with this SHA-256, most recently updated first
+async fn favicon_servers(
+    Extension(state): Extension<AppState>,
+    Path(hash): Path<String>,
+    Query(params): Query<PageParams>,
+) -> Json<Response> {
+    let limit = params.limit.unwrap_or(DEFAULT_PAGE).min(MAX_PAGE);
+    let offset = params.offset.unwrap_or_default();
+
+    let list = match state
+        .database
+        .get_servers_by_favicon(&hash, limit, offset)
+        .await
+    {
+        Ok(list) => list,
+        Err(e) => {
+            error!("Error fetching servers by favicon: {}", e);
+            return error("Internal server error");
+        }
+    };
+
+    let data = ResponseData {
+        results: Some(list),
+        ..Default::default()
+    };
+
+    success(None, Some(data))
+}
+
+/// The server's favicon as a PNG, or a grey placeholder if it doesn't have one.
+/// The ETag is the PNG's SHA-256, so `If-None-Match` gets a 304 until it changes.
+async fn server_favicon(
+    Extension(state): Extension<AppState>,
+    Path(server_id): Path<i32>,
+    headers: HeaderMap,
+) -> axum::response::Response {
+    let (png, hash) = match state.database.get_favicon(server_id).await {
+        Ok(Some(Some(blob))) => (blob.png, blob.hash),
+        Ok(Some(None)) => (PLACEHOLDER.to_vec(), PLACEHOLDER_HASH.clone()),
+        Ok(None) => return (StatusCode::NOT_FOUND, not_found("Unknown server")).into_response(),
+        Err(e) => {
+            error!("Error fetching favicon: {}", e);
+            return error("Internal server error").into_response();
+        }
+    };
+
+    let etag = format!("\"{}\"", hash);
+    let cache = [
+        (header::ETAG, etag.clone()),
+        (
+            header::CACHE_CONTROL,
+            format!("public, max-age={}", FAVICON_MAX_AGE_SECS),
+        ),
+    ];
+
+    let cached = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .map_or(false, |value| {
+            value
+                .split(',')
+                .map(|tag| tag.trim().trim_start_matches("W/"))
+                .any(|tag| tag == etag || tag == "*")
+        });
+    if cached {
+        return (StatusCode::NOT_MODIFIED, cache).into_response();
     }
 
-    if let Err(e) = update_stats(Extension(state)).await {
-        error!("Error updating stats: {}", e);
+    (cache, [(header::CONTENT_TYPE, "image/png")], png).into_response()
+}
+
+/// Servers whose favicon looks like this server's, closest first
+async fn similar_servers(
+    Extension(state): Extension<AppState>,
+    Path(server_id): Path<i32>,
+    Query(params): Query<SimilarParams>,
+) -> Json<Response> {
+    let phash = match state.database.get_favicon(server_id).await {
+        Ok(Some(blob)) => match blob.and_then(|blob| blob.phash) {
+            Some(phash) => phash,
+            None => return error("Server has no favicon that can be compared"),
+        },
+        Ok(None) => return error("Unknown server"),
+        Err(e) => {
+            error!("Error fetching favicon: {}", e);
+            return error("Internal server error");
+        }
+    };
+
+    find_similar(&state.database, phash, Some(server_id), params).await
+}
+
+/// Servers whose favicon looks like the uploaded PNG, sent as is or in base64
+async fn similar_to_upload(
+    Extension(state): Extension<AppState>,
+    Query(params): Query<SimilarParams>,
+    body: Bytes,
+) -> Json<Response> {
+    let png = if body.starts_with(&png::SIGNATURE) {
+        body.to_vec()
+    } else {
+        decode_favicon(&String::from_utf8_lossy(&body)).unwrap_or_default()
+    };
+
+    let Some(phash) = Favicon::phash(&png) else {
+        return error("Not a PNG that can be read");
+    };
+
+    find_similar(&state.database, phash, None, params).await
+}
+
+async fn find_similar(
+    db: &DbConn,
+    phash: i64,
+    exclude: Option<i32>,
+    params: SimilarParams,
+) -> Json<Response> {
+    let distance = params
+        .distance
+        .unwrap_or(similarity::DEFAULT_DISTANCE)
+        .min(similarity::MAX_DISTANCE);
+    let limit = params.limit.unwrap_or(DEFAULT_PAGE).min(MAX_PAGE);
+
+    let list = match db
+        .get_similar_servers(phash, distance, exclude, limit)
+        .await
+    {
+        Ok(list) => list,
+        Err(e) => {
+            error!("Error fetching similar servers: {}", e);
+            return error("Internal server error");
+        }
+    };
+
+    let data = ResponseData {
+        similar: Some(list),
+        ..Default::default()
+    };
+
+    success(None, Some(data))
+}
+
+/// Servers whose current MOTD contains `motd`, formatting and case aside
+async fn search_servers(
+    Extension(state): Extension<AppState>,
+    Query(params): Query<SearchParams>,
+) -> Json<Response> {
+    let Some(motd) = params.motd.filter(|motd| !motd.trim().is_empty()) else {
+        return error("No motd provided");
+    };
+    let limit = params.limit.unwrap_or(DEFAULT_PAGE).min(MAX_PAGE);
+    let offset = params.offset.unwrap_or_default();
+
+    let list = match state
+        .database
+        .find_servers_by_motd(motd.trim(), limit, offset)
+        .await
+    {
+        Ok(list) => list,
+        Err(e) => {
+            error!("Error searching servers: {}", e);
+            return error("Internal server error");
+        }
+    };
+
+    let data = ResponseData {
+        results: Some(list),
+        ..Default::default()
+    };
+
+    success(None, Some(data))
+}
+
+/// The server's MOTD timeline, oldest first
+async fn server_descriptions(
+    Extension(state): Extension<AppState>,
+    Path(server_id): Path<i32>,
+) -> Json<Response> {
+    let list = match state.database.get_motd_timeline(server_id).await {
+        Ok(Some(list)) => list,
+        Ok(None) => return error("Unknown server"),
+        Err(e) => {
+            error!("Error fetching descriptions: {}", e);
+            return error("Internal server error");
+        }
+    };
+
+    let data = ResponseData {
+        descriptions: Some(list),
+        ..Default::default()
+    };
+
+    success(None, Some(data))
+}
+
+/// Every server the player was seen on and every name they went by
+async fn get_player(
+    Extension(state): Extension<AppState>,
+    Path(uuid): Path<uuid::Uuid>,
+) -> Json<Response> {
+    let profile = match state.database.get_player_profile(uuid).await {
+        Ok(Some(profile)) => profile,
+        Ok(None) => return error("Unknown player"),
+        Err(e) => {
+            error!("Error fetching player: {}", e);
+            return error("Internal server error");
+        }
+    };
+
+    let data = ResponseData {
+        profiles: Some(vec![profile]),
+        ..Default::default()
+    };
+
+    success(None, Some(data))
+}
+
+/// A profile for every uuid that was ever seen as `name`
+async fn find_players(
+    Extension(state): Extension<AppState>,
+    Query(params): Query<PlayerParams>,
+) -> Json<Response> {
+    let Some(name) = params.name.filter(|name| !name.trim().is_empty()) else {
+        return error("No name provided");
     };
+    let db = state.database;
+
+    let uuids = match db.find_players_by_name(name.trim(), MAX_NAME_MATCHES).await {
+        Ok(uuids) => uuids,
+        Err(e) => {
+            error!("Error searching players: {}", e);
+            return error("Internal server error");
+        }
+    };
+
+    let mut profiles = Vec::new();
+    for uuid in uuids {
+        match db.get_player_profile(uuid).await {
+            Ok(Some(profile)) => profiles.push(profile),
+            Ok(None) => {}
+            Err(e) => {
+                error!("Error fetching player: {}", e);
+                return error("Internal server error");
+            }
+        }
+    }
+
+    let data = ResponseData {
+        profiles: Some(profiles),
+        ..Default::default()
+    };
+
+    success(None, Some(data))
+}
+
+/// First and last seen for every player on the server, plus the sessions between
+/// `from` (a week ago) and `to` (now)
+async fn server_sessions(
+    Extension(state): Extension<AppState>,
+    Path(server_id): Path<i32>,
+    Query(params): Query<SessionParams>,
+) -> Json<Response> {
+    match state.database.server_exists(server_id).await {
+        Ok(true) => {}
+        Ok(false) => return error("Unknown server"),
+        Err(e) => {
+            error!("Error fetching server: {}", e);
+            return error("Internal server error");
+        }
+    }
+
+    get_sessions(&state.database, SightingScope::Server(server_id), params).await
+}
+
+/// First and last seen for the player on every server, plus their sessions between
+/// `from` (a week ago) and `to` (now)
+async fn player_sessions(
+    Extension(state): Extension<AppState>,
+    Path(uuid): Path<uuid::Uuid>,
+    Query(params): Query<SessionParams>,
+) -> Json<Response> {
+    get_sessions(&state.database, SightingScope::Player(uuid), params).await
+}
+
+async fn get_sessions(db: &DbConn, scope: SightingScope, params: SessionParams) -> Json<Response> {
+    let (from, to) = match time_range(params.from, params.to) {
+        Ok(range) => range,
+        Err(e) => return error(e),
+    };
+    let gap = params
+        .gap
+        .unwrap_or(sessions::DEFAULT_SESSION_GAP_MINUTES)
+        .clamp(1, MAX_SESSION_GAP_MINUTES);
+
+    let players = match db.get_presence(scope).await {
+        Ok(players) => players,
+        Err(e) => {
+            error!("Error fetching players: {}", e);
+            return error("Internal server error");
+        }
+    };
+
+    let sightings = match db.get_sightings(scope, from, to, MAX_SIGHTINGS + 1).await {
+        Ok(list) if list.len() as u64 > MAX_SIGHTINGS => {
+            return error("Too many sightings, ask for a shorter range")
+        }
+        Ok(list) => list,
+        Err(e) => {
+            error!("Error fetching sightings: {}", e);
+            return error("Internal server error");
+        }
+    };
+
+    let data = ResponseData {
+        players: Some(players),
+        sessions: Some(sessions::sessions(
+            sightings,
+            chrono::Duration::minutes(gap),
+        )),
+        ..Default::default()
+    };
+
+    success(None, Some(data))
+}
+
+/// The most overdue addresses, what the next reping would pick up first
+async fn list_priorities(
+    Extension(state): Extension<AppState>,
+    Query(params): Query<PriorityParams>,
+) -> Json<Response> {
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_PRIORITIES)
+        .min(MAX_PRIORITIES);
+
+    let list = match state
+        .database
+        .get_priorities(None, Some(limit as u64))
+        .await
+    {
+        Ok(list) => list,
+        Err(e) => {
+            error!("Error scoring ips: {}", e);
+            return error("Internal server error");
+        }
+    };
+
+    let data = ResponseData {
+        priorities: Some(list),
+        ..Default::default()
+    };
+
+    success(None, Some(data))
+}
+
+/// Every known port on `ip` with its score and what went into it
+async fn ip_priority(
+    Extension(state): Extension<AppState>,
+    Path(ip): Path<String>,
+) -> Json<Response> {
+    let list = match state.database.get_priorities(Some(&ip), None).await {
+        Ok(list) => list,
+        Err(e) => {
+            error!("Error scoring ip: {}", e);
+            return error("Internal server error");
+        }
+    };
+
+    if list.is_empty() {
+        return error("Unknown ip");
+    }
+
+    let data = ResponseData {
+        priorities: Some(list),
+        ..Default::default()
+    };
+
+    success(None, Some(data))
+}
+
+async fn list_exclusions(
+    Extension(state): Extension<AppState>,
+    headers: HeaderMap,
+) -> Json<Response> {
+    if !is_admin(&headers) {
+        return unauthorized();
+    }
+
+    let list = match state.database.get_exclusions().await {
+        Ok(list) => list,
+        Err(e) => {
+            error!("Error fetching exclusions: {}", e);
+            return error("Internal server error");
+        }
+    };
+
+    let data = ResponseData {
+        exclusions: Some(list),
+        ..Default::default()
+    };
+
+    success(None, Some(data))
+}
+
+async fn add_exclusion(
+    Extension(state): Extension<AppState>,
+    headers: HeaderMap,
+    mut args: Json<WebRequest>,
+) -> Json<Response> {
+    if !is_admin(&headers) {
+        return unauthorized();
+    }
+
+    let Some(cidr) = args.cidr.take() else {
+        return error("No CIDR provided");
+    };
+
+    let cidr = match Exclusion::parse_cidr(&cidr) {
+        Ok(cidr) => cidr,
+        Err(e) => return error(&e.to_string()),
+    };
+
+    let reason = args.reason.take().unwrap_or_default();
+    let exclusion = match state.database.add_exclusion(cidr, reason).await {
+        Ok(exclusion) => exclusion,
+        Err(e) => {
+            error!("Error adding exclusion: {}", e);
+            return error("Internal server error");
+        }
+    };
+
+    let data = ResponseData {
+        exclusions: Some(vec![exclusion]),
+        ..Default::default()
+    };
+
+    success(None, Some(data))
+}
+
+async fn remove_exclusion(
+    Extension(state): Extension<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<i32>,
+) -> Json<Response> {
+    if !is_admin(&headers) {
+        return unauthorized();
+    }
+
+    match state.database.remove_exclusion(id).await {
+        Ok(true) => success(None, None),
+        Ok(false) => error("No exclusion with that id"),
+        Err(e) => {
+            error!("Error removing exclusion: {}", e);
+            error("Internal server error")
+        }
+    }
+}
+
+async fn queue_stats(Extension(state): Extension<AppState>, headers: HeaderMap) -> Json<Response> {
+    if !is_admin(&headers) {
+        return unauthorized();
+    }
+
+    let data = ResponseData {
+        queue: Some(state.coordinator.lock().await.stats()),
+        ..Default::default()
+    };
+
+    success(None, Some(data))
+}
+
+async fn enqueue(
+    Extension(state): Extension<AppState>,
+    headers: HeaderMap,
+    mut args: Json<WebRequest>,
+) -> Json<Response> {
+    if !is_admin(&headers) {
+        return unauthorized();
+    }
+
+    let targets = args.targets.take().unwrap_or_default();
+    let options = args.options.unwrap_or_default();
+    let mut known = Vec::new();
+    if args.rescan.unwrap_or_default() {
+        match state.database.get_all_ips(true).await {
+            Ok(ips) => known = ips,
+            Err(e) => {
+                error!("Error fetching ips: {}", e);
+                return error("Internal server error");
+            }
+        }
+    }
+
+    if targets.is_empty() && known.is_empty() {
+        return error("No targets provided");
+    }
+
+    let total = targets.len() + known.len();
+    let mut coordinator = state.coordinator.lock().await;
+    let mut added = coordinator.enqueue(targets, options);
+    // everything from the database is a known address, failures are worth keeping
+    let known_options = ScanOptions {
+        report_failures: true,
+        ..options
+    };
+    added += coordinator.enqueue(known, known_options);
+    info!("Queued {} of {} targets", added, total);
+
+    let data = ResponseData {
+        queue: Some(coordinator.stats()),
+        ..Default::default()
+    };
+
+    let msg = format!(
+        "queued {} targets, {} already pending",
+        added,
+        total - added
+    );
+    success(Some(&msg), Some(data))
+}
+
+async fn register_worker(
+    Extension(state): Extension<AppState>,
+    headers: HeaderMap,
+    mut args: Json<WebRequest>,
+) -> Json<Response> {
+    if !is_admin(&headers) {
+        return unauthorized();
+    }
+
+    let name = args.name.take().unwrap_or("voyager".to_string());
+    let id = state.coordinator.lock().await.register(name);
+
+    let data = ResponseData {
+        worker: Some(id),
+        ..Default::default()
+    };
+
+    success(None, Some(data))
+}
+
+async fn heartbeat(
+    Extension(state): Extension<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<u64>,
+) -> Json<Response> {
+    if !is_admin(&headers) {
+        return unauthorized();
+    }
+
+    if !state.coordinator.lock().await.heartbeat(id) {
+        return unknown_worker();
+    }
+
+    success(None, None)
+}
+
+async fn lease(
+    Extension(state): Extension<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<u64>,
+    args: Json<WebRequest>,
+) -> Json<Response> {
+    if !is_admin(&headers) {
+        return unauthorized();
+    }
+
+    let size = args.size.unwrap_or(100);
+    let Some(lease) = state.coordinator.lock().await.lease(id, size) else {
+        return unknown_worker();
+    };
+
+    // no lease just means the queue is empty
+    let data = ResponseData {
+        lease,
+        ..Default::default()
+    };
+
+    success(None, Some(data))
+}
+
+async fn complete_lease(
+    Extension(state): Extension<AppState>,
+    headers: HeaderMap,
+    Path((id, lease)): Path<(u64, u64)>,
+) -> Json<Response> {
+    if !is_admin(&headers) {
+        return unauthorized();
+    }
+
+    if !state.coordinator.lock().await.complete(id, lease) {
+        return error("No such lease, it may have expired");
+    }
+
+    success(None, None)
+}
+
+async fn release_lease(
+    Extension(state): Extension<AppState>,
+    headers: HeaderMap,
+    Path((id, lease)): Path<(u64, u64)>,
+) -> Json<Response> {
+    if !is_admin(&headers) {
+        return unauthorized();
+    }
+
+    if !state.coordinator.lock().await.release(id, lease) {
+        return error("No such lease, it may have expired");
+    }
 
     success(None, None)
 }
 
+/// `to` defaults to now and `from` to `DEFAULT_HISTORY_DAYS` before it. Nothing was
+/// scanned before 1970, so `from` doesn't go back further than that.
+fn time_range(
+    from: Option<NaiveDateTime>,
+    to: Option<NaiveDateTime>,
+) -> Result<(NaiveDateTime, NaiveDateTime), &'static str> {
+    let to = to.unwrap_or_else(|| chrono::Utc::now().naive_utc());
+    let from = match from {
+        Some(from) => from,
+        None => to
+            .checked_sub_signed(chrono::Duration::days(DEFAULT_HISTORY_DAYS))
+            .ok_or("to is out of range")?,
+    };
+    let from = from.max(NaiveDateTime::from_timestamp_opt(0, 0).unwrap_or_default());
+    if from >= to {
+        return Err("from has to be before to");
+    }
+
+    Ok((from, to))
+}
+
+fn is_admin(headers: &HeaderMap) -> bool {
+    let Ok(key) = std::env::var("ADMIN_KEY") else {
+        return false;
+    };
+
+    headers
+        .get("auth")
+        .and_then(|value| value.to_str().ok())
+        .map_or(false, |value| value == key)
+}
+
 fn success(msg: Option<&str>, data: Option<ResponseData>) -> Json<Response> {
     Json(Response {
         status: 200,
@@ -215,6 +1144,31 @@ fn error(msg: &str) -> Json<Response> {
     })
 }
 
+fn not_found(msg: &str) -> Json<Response> {
+    Json(Response {
+        status: 404,
+        message: msg.to_string(),
+        data: None,
+    })
+}
+
+fn unauthorized() -> Json<Response> {
+    Json(Response {
+        status: 401,
+        message: "Unauthorized".to_string(),
+        data: None,
+    })
+}
+
+/// The worker was reaped (or europa restarted) and has to register again
+fn unknown_worker() -> Json<Response> {
+    Json(Response {
+        status: 404,
+        message: "Unknown worker".to_string(),
+        data: None,
+    })
+}
+
 async fn update_stats(Extension(state): Extension<AppState>) -> anyhow::Result<()> {
     let db = state.database;
     let new_stats = match db.create_stats().await {